@@ -0,0 +1,322 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Local};
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use zip::{ZipArchive, ZipWriter, CompressionMethod};
+use zip::write::FileOptions;
+use crate::app::database::{Database, DatabaseRow, BookIdentity, BookMetadata, Bookmark, Highlight};
+use crate::app::error::ArchiveError;
+use crate::app::settings::{Settings, GeneralSettings, FileSettings};
+
+
+/// Version of the archive layout, bumped when the JSON files change.
+const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const SETTINGS_FILE: &str = "settings.json";
+const BOOKS_DIR: &str = "books/";
+
+/// Describes the archive, the first file in it.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format_version: u32,
+    program_version: String,
+    exported: DateTime<Local>,
+    books: usize,
+}
+
+/// A database row in a form that can be read and edited by hand.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportedBook {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub identity: BookIdentity,
+    pub file: String,
+    pub current_page: usize,
+    pub chapter_offset: usize,
+    pub num_pages: usize,
+    /// Percentage of the pages read, only for people reading the archive.
+    #[serde(default)]
+    pub progress: u32,
+    pub last_read: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+    #[serde(default)]
+    pub highlights: Vec<Highlight>,
+    #[serde(default)]
+    pub right_to_left: bool,
+}
+
+impl ExportedBook {
+    pub fn from_row(row: &DatabaseRow) -> ExportedBook {
+        let metadata = row.metadata.clone().unwrap_or_default();
+        let progress = match row.progress {
+            Some(progress) => (progress * 100.0) as u32,
+            None if metadata.num_pages > 0 => ((row.current_page + 1) * 100 / metadata.num_pages) as u32,
+            None => 0,
+        };
+
+        ExportedBook {
+            title: metadata.title,
+            author: metadata.author,
+            identity: row.identity.clone(),
+            file: row.file.to_string_lossy().to_string(),
+            current_page: row.current_page,
+            chapter_offset: row.chapter_offset,
+            num_pages: metadata.num_pages,
+            progress,
+            last_read: row.last_read,
+            bookmarks: row.bookmarks.clone(),
+            highlights: row.highlights.clone(),
+            right_to_left: row.right_to_left,
+        }
+    }
+
+    fn to_row(&self) -> DatabaseRow {
+        let file = PathBuf::from(&self.file);
+        // The cover thumbnails are in the cache of the other machine, the file
+        // path is kept even if it doesn't exist here so the position isn't lost.
+        let metadata = if self.title.is_some() || self.num_pages > 0 {
+            Some(BookMetadata {
+                title: self.title.clone(),
+                author: self.author.clone(),
+                num_pages: self.num_pages,
+                thumbnail: None,
+            })
+        } else {
+            None
+        };
+
+        DatabaseRow {
+            identity: self.identity.clone(),
+            filename: file.file_name().map(|name| name.to_os_string()).unwrap_or_default(),
+            file,
+            current_page: self.current_page,
+            chapter_offset: self.chapter_offset,
+            metadata,
+            bookmarks: self.bookmarks.clone(),
+            highlights: self.highlights.clone(),
+            last_read: self.last_read,
+            right_to_left: self.right_to_left,
+            // Measured again when the book is opened
+            progress: None,
+        }
+    }
+
+    /// Title for the import report.
+    fn name(&self) -> String {
+        self.title.clone().unwrap_or_else(|| self.file.clone())
+    }
+}
+
+/// The settings of the settings dialog, the general and file settings. The keyboard shortcuts
+/// are in their own file and the window size belongs to the machine, neither is exported.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ExportedSettings {
+    general: GeneralSettings,
+    file: FileSettings,
+}
+
+/// Contents of a library archive.
+pub struct LibraryArchive {
+    pub books: Vec<ExportedBook>,
+    settings: Option<ExportedSettings>,
+}
+
+/// What an import changed, or would change on a dry run.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: usize,
+    pub settings_changed: bool,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} new, {} updated, {} unchanged books.", self.added.len(), self.updated.len(), self.unchanged)?;
+        for name in &self.added {
+            writeln!(f, "  + {}", name)?;
+        }
+        for change in &self.updated {
+            writeln!(f, "  ~ {}", change)?;
+        }
+        if self.settings_changed {
+            writeln!(f, "Settings differ from the current ones.")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write every row of the database and the settings as JSON files into a zip archive.
+/// Returns the number of books exported.
+pub fn export_library(path: &Path, db: &Database, settings: &Settings) -> Result<usize, ArchiveError> {
+    let rows = db.rows.clone().unwrap_or_default();
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(File::create(path)?);
+
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        program_version: crate::VERSION.to_string(),
+        exported: Local::now(),
+        books: rows.len(),
+    };
+    write_json(&mut zip, options, MANIFEST_FILE, &manifest)?;
+
+    let exported_settings = ExportedSettings {
+        general: settings.general.clone(),
+        file: settings.file.clone(),
+    };
+    write_json(&mut zip, options, SETTINGS_FILE, &exported_settings)?;
+
+    for (i, row) in rows.iter().enumerate() {
+        let name = format!("{}{:04}.json", BOOKS_DIR, i + 1);
+        write_json(&mut zip, options, &name, &ExportedBook::from_row(row))?;
+    }
+
+    zip.finish()?;
+
+    Ok(rows.len())
+}
+
+/// Read a library archive written by `export_library`.
+pub fn read_archive(path: &Path) -> Result<LibraryArchive, ArchiveError> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+
+    let manifest: Manifest = read_json(&mut zip, MANIFEST_FILE)?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(ArchiveError::UnsupportedVersion(manifest.format_version));
+    }
+
+    let has_settings = zip.file_names().any(|name| name == SETTINGS_FILE);
+    let settings: Option<ExportedSettings> = if has_settings {
+        Some(read_json(&mut zip, SETTINGS_FILE)?)
+    } else {
+        None
+    };
+
+    let mut names: Vec<String> = zip.file_names()
+        .filter(|name| name.starts_with(BOOKS_DIR) && name.ends_with(".json"))
+        .map(String::from)
+        .collect();
+    names.sort();
+
+    let mut books = vec![];
+    for name in names {
+        books.push(read_json(&mut zip, &name)?);
+    }
+
+    Ok(LibraryArchive {
+        books,
+        settings
+    })
+}
+
+impl LibraryArchive {
+    /// Merge the books into the `db`. When a book is in both, the newer position wins
+    /// and the bookmarks and highlights missing from the `db` are added.
+    pub fn merge_into(&self, db: &mut Database, settings: &Settings) -> ImportReport {
+        let mut report = ImportReport {
+            settings_changed: self.settings_differ(settings),
+            ..Default::default()
+        };
+        
+        for book in &self.books {
+            let imported = book.to_row();
+            let index = db.position(&imported.identity, &imported.file);
+            let rows = db.rows.get_or_insert_with(Vec::new);
+
+            let row = match index {
+                Some(index) => &mut rows[index],
+                None => {
+                    report.added.push(book.name());
+                    rows.push(imported);
+                    continue;
+                }
+            };
+
+            let mut changes = vec![];
+            let is_newer = match (imported.last_read, row.last_read) {
+                (Some(imported_time), Some(time)) => imported_time > time,
+                (Some(_), None) => true,
+                _ => false,
+            };
+            if is_newer && (row.current_page, row.chapter_offset) != (imported.current_page, imported.chapter_offset) {
+                changes.push(format!("position page {} -> {}", row.current_page + 1, imported.current_page + 1));
+                row.current_page = imported.current_page;
+                row.chapter_offset = imported.chapter_offset;
+                row.progress = imported.progress;
+            }
+            if is_newer {
+                row.last_read = imported.last_read;
+            }
+
+            let new_bookmarks: Vec<Bookmark> = imported.bookmarks
+                .into_iter()
+                .filter(|bookmark| !row.bookmarks.iter().any(|b| b.page == bookmark.page && b.chapter_offset == bookmark.chapter_offset))
+                .collect();
+            if !new_bookmarks.is_empty() {
+                changes.push(format!("{} new bookmarks", new_bookmarks.len()));
+                row.bookmarks.extend(new_bookmarks);
+                row.bookmarks.sort_by_key(|bookmark| (bookmark.page, bookmark.chapter_offset));
+            }
+
+            let new_highlights: Vec<Highlight> = imported.highlights
+                .into_iter()
+                .filter(|highlight| !row.highlights.iter().any(|h| h.page == highlight.page && h.start == highlight.start && h.end == highlight.end))
+                .collect();
+            if !new_highlights.is_empty() {
+                changes.push(format!("{} new highlights", new_highlights.len()));
+                row.highlights.extend(new_highlights);
+                row.highlights.sort_by_key(|highlight| (highlight.page, highlight.start));
+            }
+
+            if row.metadata.is_none() {
+                row.metadata = imported.metadata;
+            }
+
+            if changes.is_empty() {
+                report.unchanged += 1;
+            } else {
+                report.updated.push(format!("{}: {}", book.name(), changes.join(", ")));
+            }
+        }
+
+        report
+    }
+
+    /// The general or file settings in the archive are not the same as the `settings`.
+    pub fn settings_differ(&self, settings: &Settings) -> bool {
+        match &self.settings {
+            Some(exported) => exported.general != settings.general || exported.file != settings.file,
+            None => false,
+        }
+    }
+
+    /// Replace the general and file settings with the ones in the archive.
+    pub fn apply_settings(&self, settings: &mut Settings) {
+        if let Some(exported) = &self.settings {
+            settings.general = exported.general.clone();
+            settings.file = exported.file.clone();
+        }
+    }
+}
+
+fn write_json<T: Serialize>(zip: &mut ZipWriter<File>, options: FileOptions, name: &str, data: &T) -> Result<(), ArchiveError> {
+    let json = serde_json::to_string_pretty(data).map_err(|e| ArchiveError::Json(name.to_string(), e))?;
+    zip.start_file(name, options)?;
+    zip.write_all(json.as_bytes())?;
+
+    Ok(())
+}
+
+fn read_json<T: DeserializeOwned>(zip: &mut ZipArchive<File>, name: &str) -> Result<T, ArchiveError> {
+    let mut json = String::new();
+    zip.by_name(name)?.read_to_string(&mut json)?;
+
+    serde_json::from_str(&json).map_err(|e| ArchiveError::Json(name.to_string(), e))
+}
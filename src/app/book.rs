@@ -0,0 +1,190 @@
+//! The books that are read as text. The reader only uses the `Book` trait so that
+//! a new format needs an implementation of it and nothing in the UI.
+//!
+//! Comic books are not books in this sense, their pages are images shown in their own
+//! view, so `ComicBook` has its own type and doesn't implement the trait.
+
+use std::path::{Path, PathBuf};
+use select::document::Document;
+
+
+/// Metadata the book has about itself.
+#[derive(Debug, Clone, Default)]
+pub struct BookInfo {
+    /// Identifier given by the publisher, e.g. an ISBN or a UUID.
+    pub identifier: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+}
+
+/// A part of the book in reading order, shown as one page in the reader.
+#[derive(Debug, Clone)]
+pub struct Section {
+    /// Path of the section inside the book, relative links and resources are resolved against it.
+    pub path: PathBuf,
+    /// Title of the first table of contents entry pointing to the section.
+    pub title: Option<String>,
+}
+
+impl Section {
+    /// Prettified path of the section. E.g: `EPUB/chapter_1.xml` -> `chapter_1.xml`
+    pub fn display_name(&self) -> String {
+        self.path
+            .to_string_lossy()
+            .replace("EPUB/", "")
+            .replace("OEBPS/", "")
+    }
+
+    /// Title of the section, or the file name for the sections without one.
+    pub fn title_or_file_name(&self) -> String {
+        self.title.clone().unwrap_or_else(|| {
+            self.path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// An entry in the table of contents.
+#[derive(Debug, Clone)]
+pub struct TocItem {
+    pub label: String,
+    /// Index of the section the entry points to, `None` for entries that only group other entries.
+    pub section: Option<usize>,
+    /// Id of the element in the section, already percent decoded.
+    pub anchor: Option<String>,
+    pub children: Vec<TocItem>,
+}
+
+/// A book that can be opened in the reader.
+///
+/// The sections are in reading order and their index is the page number
+/// that is shown in the UI and saved to the database.
+pub trait Book: Send {
+    fn info(&self) -> BookInfo;
+
+    fn sections(&self) -> &[Section];
+
+    /// The table of contents of the book, empty if it doesn't have one.
+    fn toc(&self) -> &[TocItem];
+
+    /// Parsed xhtml content of the section at `index`.
+    fn section_content(&mut self, index: usize) -> anyhow::Result<Document>;
+
+    /// Bytes of the file at `path` inside the book, e.g. an image in a section.
+    fn resource(&mut self, path: &Path) -> Option<Vec<u8>>;
+
+    fn cover(&mut self) -> Option<Vec<u8>>;
+
+    fn num_sections(&self) -> usize {
+        self.sections().len()
+    }
+
+    /// Index of the section in the file at `path`.
+    fn section_index(&self, path: &Path) -> Option<usize> {
+        self.sections().iter().position(|section| section.path == path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use select::predicate::Name;
+    use crate::app::epub_book::EpubBook;
+    use crate::app::epub_writer::{self, GeneratedBook, GeneratedChapter, GeneratedImage, TocEntry};
+
+    /// A small epub with two chapters, an image and a nested table of contents.
+    fn open_fixture(name: &str) -> Box<dyn Book> {
+        let book = GeneratedBook {
+            identifier: "urn:bad-reader:test".to_string(),
+            title: "Test Book".to_string(),
+            author: Some("Test Author".to_string()),
+            language: Some("en".to_string()),
+            chapters: vec![
+                GeneratedChapter {
+                    file_name: "chapter_1.xhtml".to_string(),
+                    title: "One".to_string(),
+                    body: "<h2>One</h2>\n<p>First chapter.</p>\n<img src=\"cover.png\"/>\n".to_string(),
+                },
+                GeneratedChapter {
+                    file_name: "chapter_2.xhtml".to_string(),
+                    title: "Two".to_string(),
+                    body: "<h2 id=\"two\">Two</h2>\n<p>Second chapter.</p>\n<p id=\"end\">The end.</p>\n".to_string(),
+                },
+            ],
+            toc: vec![
+                TocEntry { title: "One".to_string(), href: "chapter_1.xhtml".to_string(), children: vec![] },
+                TocEntry {
+                    title: "Two".to_string(),
+                    href: "chapter_2.xhtml#two".to_string(),
+                    children: vec![
+                        TocEntry { title: "The end".to_string(), href: "chapter_2.xhtml#end".to_string(), children: vec![] },
+                    ],
+                },
+            ],
+            images: vec![GeneratedImage {
+                file_name: "cover.png".to_string(),
+                media_type: "image/png".to_string(),
+                data: vec![0x89, b'P', b'N', b'G'],
+            }],
+            cover: Some("cover.png".to_string()),
+            ..Default::default()
+        };
+
+        let path = env::temp_dir().join(format!("bad-reader-test-{}-{}.epub", std::process::id(), name));
+        epub_writer::save(&path, &book).expect("Cannot write the test epub");
+        let opened = EpubBook::open(&path).expect("Cannot open the test epub");
+        fs::remove_file(&path).ok();
+
+        Box::new(opened)
+    }
+
+    #[test]
+    fn sections_are_in_reading_order() {
+        let book = open_fixture("sections");
+
+        assert_eq!(book.num_sections(), 2);
+        assert_eq!(book.sections()[0].path, PathBuf::from("OEBPS/chapter_1.xhtml"));
+        assert_eq!(book.sections()[1].title.as_deref(), Some("Two"));
+        assert_eq!(book.section_index(Path::new("OEBPS/chapter_2.xhtml")), Some(1));
+        assert_eq!(book.info().title.as_deref(), Some("Test Book"));
+    }
+
+    #[test]
+    fn section_content_is_parsed() {
+        let mut book = open_fixture("content");
+
+        let document = book.section_content(1).unwrap();
+        let paragraphs: Vec<String> = document.find(Name("p")).map(|node| node.text()).collect();
+        assert_eq!(paragraphs, vec!["Second chapter.", "The end."]);
+        assert!(book.section_content(2).is_err());
+    }
+
+    #[test]
+    fn resources_are_found_by_path() {
+        let mut book = open_fixture("resource");
+
+        assert_eq!(book.resource(Path::new("OEBPS/cover.png")), Some(vec![0x89, b'P', b'N', b'G']));
+        assert_eq!(book.resource(Path::new("OEBPS/missing.png")), None);
+    }
+
+    #[test]
+    fn toc_points_to_sections_and_anchors() {
+        let book = open_fixture("toc");
+        let toc = book.toc();
+
+        assert_eq!(toc.len(), 2);
+        assert_eq!(toc[0].label, "One");
+        assert_eq!(toc[0].section, Some(0));
+        assert_eq!(toc[0].anchor, None);
+        assert_eq!(toc[1].section, Some(1));
+        assert_eq!(toc[1].anchor.as_deref(), Some("two"));
+        assert_eq!(toc[1].children.len(), 1);
+        assert_eq!(toc[1].children[0].label, "The end");
+        assert_eq!(toc[1].children[0].anchor.as_deref(), Some("end"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Commands that run without opening the main window.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use crate::app::archive::{self, ExportedBook};
+use crate::app::book::{Book, TocItem};
+use crate::app::comic_book::ComicBook;
+use crate::app::database::{read_database, read_database_read_only};
+use crate::app::error::{CliError, StorageError};
+use crate::app::library::{self, BookFormat};
+use crate::app::profile_lock;
+use crate::app::settings::Settings;
+use crate::ui::renderer::{chapter_text, IMAGE_CHAR};
+
+
+/// `bad-reader export-library <archive>`
+///
+/// Only reads the library and the settings, nothing in the profile is created or moved.
+pub fn export_library(archive_path: &Path) -> Result<(), CliError> {
+    let db = read_database_read_only()?;
+    let settings = Settings::open_read_only()?;
+
+    let count = archive::export_library(archive_path, &db, &settings)?;
+    println!("Exported {} books to {}", count, archive_path.display());
+
+    Ok(())
+}
+
+/// `bad-reader import-library <archive> [--dry-run] [--include-settings]`
+///
+/// Prints what changed, or on a dry run what would change, without touching the files.
+/// A running reader keeps the library in memory and writes it over the imported one,
+/// so only a dry run is allowed while it's running.
+pub fn import_library(archive_path: &Path, dry_run: bool, include_settings: bool) -> Result<(), CliError> {
+    if !dry_run && profile_lock::is_locked().map_err(StorageError::Io)? {
+        return Err(CliError::ReaderRunning);
+    }
+    
+    let library = archive::read_archive(archive_path)?;
+    let mut db = read_database()?;
+    let mut settings = Settings::open()?;
+
+    let report = library.merge_into(&mut db, &settings);
+
+    if dry_run {
+        println!("Dry run, nothing was changed.");
+        print!("{}", report);
+        if report.settings_changed && !include_settings {
+            println!("Use --include-settings to import the settings as well.");
+        }
+        return Ok(());
+    }
+
+    db.save()?;
+    if include_settings && report.settings_changed {
+        library.apply_settings(&mut settings);
+        settings.write()?;
+        println!("Imported the settings.");
+    }
+
+    print!("{}", report);
+
+    Ok(())
+}
+
+/// `bad-reader info <file>`
+///
+/// Metadata, the number of chapters and the table of contents of the book.
+pub fn info(file: &Path) -> Result<(), CliError> {
+    let format = book_format(file)?;
+    let mut out = io::stdout();
+
+    if format == BookFormat::Comic {
+        let comic = ComicBook::open(file).map_err(|e| CliError::Malformed(file.to_path_buf(), e))?;
+        print_field(&mut out, "File", Some(file.display().to_string()))?;
+        print_field(&mut out, "Format", Some(format!("{:?}", format)))?;
+        print_field(&mut out, "Title", comic.metadata.title.clone())?;
+        print_field(&mut out, "Author", comic.metadata.author.clone())?;
+        print_field(&mut out, "Pages", Some(comic.num_pages().to_string()))?;
+        return Ok(());
+    }
+
+    let book = open_text_book(file)?;
+    let info = book.info();
+    print_field(&mut out, "File", Some(file.display().to_string()))?;
+    print_field(&mut out, "Format", Some(format!("{:?}", format)))?;
+    print_field(&mut out, "Title", info.title)?;
+    print_field(&mut out, "Author", info.author)?;
+    print_field(&mut out, "Language", info.language)?;
+    print_field(&mut out, "Identifier", info.identifier)?;
+    print_field(&mut out, "Chapters", Some(book.num_sections().to_string()))?;
+    writeln!(out, "Table of contents:").map_err(CliError::Output)?;
+    print_toc(&mut out, book.as_ref(), 1)
+}
+
+/// `bad-reader toc <file>`
+///
+/// One entry per line: the chapter number, a tab and the title indented by its depth.
+/// Books without a table of contents list their chapters instead.
+pub fn toc(file: &Path) -> Result<(), CliError> {
+    if book_format(file)? == BookFormat::Comic {
+        return Err(CliError::UnsupportedFormat(file.to_path_buf()));
+    }
+    let book = open_text_book(file)?;
+
+    print_toc(&mut io::stdout(), book.as_ref(), 0)
+}
+
+/// `bad-reader text <file> --chapter <N>`
+///
+/// Text of the chapter as the reader shows it, chapters are numbered from 0 like the pages.
+pub fn text(file: &Path, chapter: usize) -> Result<(), CliError> {
+    if book_format(file)? == BookFormat::Comic {
+        return Err(CliError::UnsupportedFormat(file.to_path_buf()));
+    }
+    let mut book = open_text_book(file)?;
+    if chapter >= book.num_sections() {
+        return Err(CliError::NoSuchChapter(chapter, book.num_sections()));
+    }
+
+    let document = book.section_content(chapter).map_err(|e| CliError::Malformed(file.to_path_buf(), e))?;
+    // The images are left out, their place holder chars would show up as boxes.
+    let text = chapter_text(&document).replace(IMAGE_CHAR, "");
+    writeln!(io::stdout(), "{}", text.trim_end()).map_err(CliError::Output)
+}
+
+/// `bad-reader recent [--json]`
+///
+/// The books in the database, the most recently read first. One book per line with tab separated
+/// last read time, page, number of pages, title and file, or every saved detail as JSON.
+pub fn recent(json: bool) -> Result<(), CliError> {
+    let mut rows = read_database_read_only()?.rows.unwrap_or_default();
+    rows.sort_by(|a, b| b.last_read.cmp(&a.last_read));
+    let mut out = io::stdout();
+
+    if json {
+        let books: Vec<ExportedBook> = rows.iter().map(ExportedBook::from_row).collect();
+        let json = serde_json::to_string_pretty(&books).expect("Cannot serialize the books");
+        return writeln!(out, "{}", json).map_err(CliError::Output);
+    }
+
+    for row in rows {
+        let metadata = row.metadata.unwrap_or_default();
+        let last_read = row.last_read
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let title = metadata.title.unwrap_or_else(|| row.filename.to_string_lossy().to_string());
+
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}",
+            last_read,
+            row.current_page,
+            metadata.num_pages,
+            title,
+            row.file.display()
+        ).map_err(CliError::Output)?;
+    }
+
+    Ok(())
+}
+
+/// Format of the book in `file`, an error if it cannot be read or isn't a book.
+fn book_format(file: &Path) -> Result<BookFormat, CliError> {
+    fs::metadata(file).map_err(|e| CliError::Unreadable(file.to_path_buf(), e))?;
+
+    BookFormat::from_path(file).ok_or_else(|| CliError::UnsupportedFormat(file.to_path_buf()))
+}
+
+fn open_text_book(file: &Path) -> Result<Box<dyn Book>, CliError> {
+    library::open_book(file).map_err(|e| CliError::Malformed(file.to_path_buf(), e))
+}
+
+fn print_field(out: &mut impl Write, name: &str, value: Option<String>) -> Result<(), CliError> {
+    writeln!(out, "{}: {}", name, value.unwrap_or_else(|| "-".to_string())).map_err(CliError::Output)
+}
+
+fn print_toc(out: &mut impl Write, book: &dyn Book, depth: usize) -> Result<(), CliError> {
+    fn print_items(out: &mut impl Write, items: &[TocItem], depth: usize) -> io::Result<()> {
+        for item in items {
+            let chapter = item.section.map_or_else(|| "-".to_string(), |section| section.to_string());
+            writeln!(out, "{}\t{}{}", chapter, "  ".repeat(depth), item.label)?;
+            print_items(out, &item.children, depth + 1)?;
+        }
+
+        Ok(())
+    }
+
+    if book.toc().is_empty() {
+        for (i, section) in book.sections().iter().enumerate() {
+            writeln!(out, "{}\t{}{}", i, "  ".repeat(depth), section.display_name()).map_err(CliError::Output)?;
+        }
+        return Ok(());
+    }
+
+    print_items(out, book.toc(), depth).map_err(CliError::Output)
+}
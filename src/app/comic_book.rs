@@ -0,0 +1,203 @@
+//! Comic book archives (`.cbz`), a zip of page images.
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use anyhow::anyhow;
+use zip::ZipArchive;
+use crate::app::database::{BookIdentity, BookMetadata, DatabaseRow};
+use crate::app::library;
+use crate::app::utils::hash_file;
+
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp"];
+/// Optional metadata file used by many comic book tools.
+const COMIC_INFO_FILE: &str = "ComicInfo.xml";
+
+pub struct ComicBook {
+    pub file: PathBuf,
+    archive: ZipArchive<File>,
+    /// Names of the page images in the archive, in reading order.
+    pages: Vec<String>,
+    pub current_page: usize,
+    pub initial_page: Option<usize>,
+    pub identity: BookIdentity,
+    pub metadata: BookMetadata,
+    /// Loaded from the db when the book is opened.
+    pub right_to_left: bool,
+}
+
+impl ComicBook {
+    /// Open the archive and read the metadata, this caches the thumbnail of the
+    /// first page so it should be called outside of the main thread.
+    pub fn open(file: &Path) -> anyhow::Result<ComicBook> {
+        let mut archive = ZipArchive::new(File::open(file)?)?;
+
+        let mut pages: Vec<String> = archive.file_names()
+            .filter(|name| is_image(name))
+            .map(String::from)
+            .collect();
+        if pages.is_empty() {
+            return Err(anyhow!("No images in the comic book"));
+        }
+        pages.sort_by(|a, b| natural_cmp(a, b));
+
+        let identity = BookIdentity {
+            identifier: None,
+            content_hash: hash_file(file)?,
+        };
+        let (title, author) = comic_info(&mut archive);
+
+        let mut comic = ComicBook {
+            file: file.to_path_buf(),
+            archive,
+            pages,
+            current_page: 0,
+            initial_page: None,
+            identity,
+            metadata: BookMetadata::default(),
+            right_to_left: false,
+        };
+
+        let identity = comic.identity.clone();
+        let thumbnail = library::cache_thumbnail(&identity, || comic.page_image(0));
+        comic.metadata = BookMetadata {
+            title: title.or_else(|| file.file_stem().map(|stem| stem.to_string_lossy().to_string())),
+            author,
+            num_pages: comic.num_pages(),
+            thumbnail,
+        };
+
+        Ok(comic)
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Bytes of the image of the `page`.
+    pub fn page_image(&mut self, page: usize) -> Option<Vec<u8>> {
+        let name = self.pages.get(page)?;
+        let mut bytes = vec![];
+
+        match self.archive.by_name(name).and_then(|mut image| Ok(image.read_to_end(&mut bytes)?)) {
+            Ok(_) => Some(bytes),
+            Err(e) => {
+                error!("Cannot read the page {:?} of {:?}. {}", name, self.file, e);
+                None
+            }
+        }
+    }
+
+    /// Database row for the current page of the book.
+    pub fn to_db_row(&self) -> DatabaseRow {
+        DatabaseRow {
+            identity: self.identity.clone(),
+            file: self.file.clone(),
+            filename: self.file.file_name().unwrap().to_os_string(),
+            current_page: self.current_page,
+            chapter_offset: 0,
+            metadata: Some(self.metadata.clone()),
+            bookmarks: vec![],
+            highlights: vec![],
+            last_read: Some(chrono::Local::now()),
+            right_to_left: self.right_to_left,
+            progress: Some((self.current_page + 1) as f64 / self.num_pages() as f64)
+        }
+    }
+}
+
+fn is_image(name: &str) -> bool {
+    let name = name.to_lowercase();
+    // macOS adds these next to the real files
+    if name.starts_with("__macosx/") || name.ends_with('/') {
+        return false;
+    }
+
+    Path::new(&name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| IMAGE_EXTENSIONS.contains(&extension))
+}
+
+/// Title and writer from the `ComicInfo.xml` in the archive, if it has one.
+fn comic_info(archive: &mut ZipArchive<File>) -> (Option<String>, Option<String>) {
+    let mut xml = String::new();
+    let found = archive
+        .by_name(COMIC_INFO_FILE)
+        .ok()
+        .map(|mut file| file.read_to_string(&mut xml).is_ok())
+        .unwrap_or(false);
+    if !found {
+        return (None, None);
+    }
+
+    let document = match roxmltree::Document::parse(&xml) {
+        Ok(document) => document,
+        Err(e) => {
+            warn!("Cannot read {}. {}", COMIC_INFO_FILE, e);
+            return (None, None);
+        }
+    };
+    let text = |name: &str| {
+        document
+            .root_element()
+            .children()
+            .find(|node| node.tag_name().name() == name)
+            .and_then(|node| node.text())
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+    };
+
+    let title = match (text("Series"), text("Number"), text("Title")) {
+        (Some(series), Some(number), _) => Some(format!("{} #{}", series, number)),
+        (_, _, Some(title)) => Some(title),
+        (series, _, None) => series,
+    };
+
+    (title, text("Writer"))
+}
+
+/// Compare the names so that the numbers in them are in numeric order, `page2` before `page10`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                // Compare by length first so long numbers don't overflow
+                let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(&y));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            },
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            },
+        }
+    }
+}
+
+/// The digits at the start of `chars` without the leading zeros.
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.peek().copied().filter(char::is_ascii_digit) {
+        number.push(c);
+        chars.next();
+    }
+
+    let trimmed = number.trim_start_matches('0');
+    if trimmed.is_empty() { "0".to_string() } else { trimmed.to_string() }
+}
@@ -1,41 +1,157 @@
-use std::io::{BufReader, BufWriter};
-use std::fs::File;
-use bincode::{serialize_into, deserialize_from};
 use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
 use std::ffi::OsString;
-use crate::app::utils::working_dir;
+use chrono::{DateTime, Local};
+use crate::app::paths::{self, Location};
+use crate::app::error::StorageError;
+use crate::app::storage::{self, Versioned};
 
 
 pub const DB_FILE: &str = "bad-reader.db";
 
+/// Identifies a book regardless of its file name or location.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default, Clone)]
+pub struct BookIdentity {
+    /// Unique identifier from the book metadata.
+    pub identifier: Option<String>,
+    /// Hash of the file content, used when the books don't both have an identifier.
+    pub content_hash: u64,
+}
+
+impl BookIdentity {
+    pub fn matches(&self, other: &BookIdentity) -> bool {
+        if self.is_unknown() || other.is_unknown() {
+            return false;
+        }
+        
+        match (&self.identifier, &other.identifier) {
+            (Some(identifier), Some(other_identifier)) => identifier == other_identifier,
+            _ => self.content_hash == other.content_hash,
+        }
+    }
+    
+    /// Rows saved before books had an identity have an empty one.
+    pub fn is_unknown(&self) -> bool {
+        self.identifier.is_none() && self.content_hash == 0
+    }
+}
+
+/// Book details shown in the library so the books don't have to be opened for it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default, Clone)]
+pub struct BookMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Number of pages (spine items), for showing the progress.
+    pub num_pages: usize,
+    /// Cover thumbnail in the cache directory, `None` if the book has no cover.
+    pub thumbnail: Option<PathBuf>,
+}
+
+/// A saved position in a book.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Bookmark {
+    pub page: usize,
+    /// Char offset inside the page, same as `DatabaseRow::chapter_offset`.
+    pub chapter_offset: usize,
+    pub created: DateTime<Local>,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum HighlightColor {
+    Yellow,
+    Green,
+    Blue,
+    Pink
+}
+
+impl HighlightColor {
+    pub const ALL: [HighlightColor; 4] = [
+        HighlightColor::Yellow,
+        HighlightColor::Green,
+        HighlightColor::Blue,
+        HighlightColor::Pink,
+    ];
+    
+    /// Name of the reader text tag for the color.
+    pub fn tag_name(&self) -> &'static str {
+        match self {
+            HighlightColor::Yellow => "highlight-yellow",
+            HighlightColor::Green => "highlight-green",
+            HighlightColor::Blue => "highlight-blue",
+            HighlightColor::Pink => "highlight-pink",
+        }
+    }
+    
+    /// Background color, translucent so it works with every theme.
+    pub fn rgba(&self) -> &'static str {
+        match self {
+            HighlightColor::Yellow => "rgba(252, 233, 79, 0.5)",
+            HighlightColor::Green => "rgba(138, 226, 52, 0.45)",
+            HighlightColor::Blue => "rgba(114, 159, 207, 0.5)",
+            HighlightColor::Pink => "rgba(239, 41, 141, 0.35)",
+        }
+    }
+}
+
+/// Highlighted text in a book, with an optional note.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Highlight {
+    pub page: usize,
+    /// Char offsets of the highlighted text inside the page.
+    pub start: usize,
+    pub end: usize,
+    /// The highlighted text, for listing the highlights without opening the page.
+    pub text: String,
+    pub color: HighlightColor,
+    pub note: Option<String>,
+    pub created: DateTime<Local>,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct DatabaseRow {
+    pub identity: BookIdentity,
     pub file: PathBuf,
     pub filename: OsString,
-    pub current_page: usize
+    pub current_page: usize,
+    /// Char offset of the text at the top of the reader in the current page.
+    /// Unlike a scroll position this doesn't change with the font size.
+    pub chapter_offset: usize,
+    /// `None` until the metadata has been read from the book.
+    pub metadata: Option<BookMetadata>,
+    /// Sorted by the position in the book.
+    pub bookmarks: Vec<Bookmark>,
+    /// Sorted by the position in the book.
+    pub highlights: Vec<Highlight>,
+    /// When the position was saved, `None` for rows from before it was recorded.
+    pub last_read: Option<DateTime<Local>>,
+    /// The pages of a comic book are read from right to left (manga).
+    pub right_to_left: bool,
+    /// Part of the book before the saved position, from 0 to 1.
+    /// `None` until the lengths of the chapters are known.
+    pub progress: Option<f64>
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Default, Clone)]
 #[serde(rename = "database", default)]
 pub struct Database {
     #[serde(rename = "row", default)]
-    pub rows: Option<Vec<DatabaseRow>>
+    pub rows: Option<Vec<DatabaseRow>>,
+    /// Measured reading speed in chars per minute, for estimating the time left.
+    pub reading_speed: Option<f64>
 }
 
 impl Database {
     pub fn new(rows: Option<Vec<DatabaseRow>>) -> Database {
         Database {
-            rows
+            rows,
+            reading_speed: None
         }
     }
     
     pub fn write_database(&mut self) {
-        let file_result = File::create(&working_dir(DB_FILE));
-        match file_result {
-            Ok(file) => {
-                let mut f = BufWriter::new(file);
-                serialize_into(&mut f, self).expect("Cannot serialize into DB_FILE");
+        match self.save() {
+            Ok(_) => {
                 debug!("Wrote to DB!");
             },
             Err(e) => {
@@ -44,35 +160,496 @@ impl Database {
         }
     }
     
-    pub fn get_by_row_file(&self, file: &Path) -> Option<DatabaseRow> {
-        let filename = file.file_name().unwrap();
+    /// Write the database file, leaving the error handling to the caller.
+    pub fn save(&self) -> Result<(), StorageError> {
+        storage::save(&paths::path(Location::Data, DB_FILE), self)
+    }
+    
+    /// Index of the row of the book with the `identity`.
+    /// Rows saved before books had an identity are matched by the file name.
+    pub fn position(&self, identity: &BookIdentity, file: &Path) -> Option<usize> {
+        let rows = self.rows.as_ref()?;
         
-        if let Some(rows) = &self.rows {
-            for row in rows {
-                if row.filename == filename {
-                    return Some(row.clone());
-                }
-            }
-        }
+        rows.iter()
+            .position(|row| row.identity.matches(identity))
+            .or_else(|| {
+                let filename = file.file_name()?;
+                rows.iter().position(|row| row.identity.is_unknown() && row.filename == filename)
+            })
+    }
+    
+    pub fn get_by_book(&self, identity: &BookIdentity, file: &Path) -> Option<DatabaseRow> {
+        let index = self.position(identity, file)?;
         
-        None
+        self.rows.as_ref().map(|rows| rows[index].clone())
     }
 }
 
-pub(crate) fn read_database() -> Database {
-    debug!("db file exists: {:?}", Path::new(&working_dir(DB_FILE)).exists());
+impl Versioned for Database {
+    const VERSION: u32 = 7;
     
-    // Create the db file if it doesn't exist
-    if !Path::new(&working_dir(DB_FILE)).exists() {
-        let mut db = Database::new(Some(vec![]));
-        
-        db.write_database();
+    fn migrate(version: u32, bytes: &[u8]) -> bincode::Result<Self> {
+        use legacy::LegacyDatabase::*;
         
-        return db
+        // Upgrade one format at a time until it's the current one.
+        let mut db = legacy::LegacyDatabase::read(version, bytes)?;
+        loop {
+            db = match db {
+                V0(db) => V1(db.into()),
+                V1(db) => V2(db.into()),
+                V2(db) => V3(db.into()),
+                V3(db) => V4(db.into()),
+                V4(db) => V5(db.into()),
+                V5(db) => V6(db.into()),
+                V6(db) => return Ok(db.into()),
+            };
+        }
+    }
+}
+
+/// Open the database, or create it if it doesn't exist.
+///
+/// An unreadable database is moved aside by `storage::load` and an empty one
+/// is used instead, the error is returned so it can be shown to the user.
+pub(crate) fn read_database() -> Result<Database, StorageError> {
+    let path = paths::path(Location::Data, DB_FILE);
+    debug!("db file exists: {:?}", path.exists());
+    
+    match storage::load::<Database>(&path)? {
+        Some(db) => Ok(db),
+        // Create the db file if it doesn't exist
+        None => {
+            let mut db = Database::new(Some(vec![]));
+            db.write_database();
+            
+            Ok(db)
+        }
+    }
+}
+
+/// Open the database without creating it or moving it aside when it cannot be read.
+/// A missing database is an empty one.
+pub(crate) fn read_database_read_only() -> Result<Database, StorageError> {
+    let path = paths::path(Location::Data, DB_FILE);
+    let db = storage::load_read_only::<Database>(&path)?;
+    
+    Ok(db.unwrap_or_else(|| Database::new(Some(vec![]))))
+}
+
+/// Earlier formats of the database, kept for migrating old files.
+/// Each format is converted to the next one until it's the current one.
+///
+/// The types in the rows are frozen here too, as they were in the format that added them,
+/// so changing the current types doesn't change how the old files are read.
+mod legacy {
+    use super::{Database, DatabaseRow, BookIdentity, BookMetadata, Bookmark, Highlight, HighlightColor};
+    use chrono::{DateTime, Local};
+    use serde::Deserialize;
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+    
+    /// A database in one of the earlier formats.
+    pub enum LegacyDatabase {
+        V0(DatabaseV0),
+        V1(DatabaseV1),
+        V2(DatabaseV2),
+        V3(DatabaseV3),
+        V4(DatabaseV4),
+        V5(DatabaseV5),
+        V6(DatabaseV6),
+    }
+    
+    impl LegacyDatabase {
+        pub fn read(version: u32, bytes: &[u8]) -> bincode::Result<LegacyDatabase> {
+            Ok(match version {
+                0 => LegacyDatabase::V0(bincode::deserialize(bytes)?),
+                1 => LegacyDatabase::V1(bincode::deserialize(bytes)?),
+                2 => LegacyDatabase::V2(bincode::deserialize(bytes)?),
+                3 => LegacyDatabase::V3(bincode::deserialize(bytes)?),
+                4 => LegacyDatabase::V4(bincode::deserialize(bytes)?),
+                5 => LegacyDatabase::V5(bincode::deserialize(bytes)?),
+                6 => LegacyDatabase::V6(bincode::deserialize(bytes)?),
+                _ => return Err(Box::new(bincode::ErrorKind::Custom(format!("Unknown DB version {}", version)))),
+            })
+        }
+    }
+    
+    /// `BookIdentity` of version 1.
+    #[derive(Deserialize, Default)]
+    pub struct BookIdentityV1 {
+        pub identifier: Option<String>,
+        pub content_hash: u64,
+    }
+    
+    impl From<BookIdentityV1> for BookIdentity {
+        fn from(identity: BookIdentityV1) -> Self {
+            BookIdentity {
+                identifier: identity.identifier,
+                content_hash: identity.content_hash,
+            }
+        }
+    }
+    
+    /// `BookMetadata` of version 2.
+    #[derive(Deserialize)]
+    pub struct BookMetadataV2 {
+        pub title: Option<String>,
+        pub author: Option<String>,
+        pub num_pages: usize,
+        pub thumbnail: Option<PathBuf>,
+    }
+    
+    impl From<BookMetadataV2> for BookMetadata {
+        fn from(metadata: BookMetadataV2) -> Self {
+            BookMetadata {
+                title: metadata.title,
+                author: metadata.author,
+                num_pages: metadata.num_pages,
+                thumbnail: metadata.thumbnail,
+            }
+        }
+    }
+    
+    /// `Bookmark` of version 3.
+    #[derive(Deserialize)]
+    pub struct BookmarkV3 {
+        pub page: usize,
+        pub chapter_offset: usize,
+        pub created: DateTime<Local>,
+        pub label: Option<String>,
+    }
+    
+    impl From<BookmarkV3> for Bookmark {
+        fn from(bookmark: BookmarkV3) -> Self {
+            Bookmark {
+                page: bookmark.page,
+                chapter_offset: bookmark.chapter_offset,
+                created: bookmark.created,
+                label: bookmark.label,
+            }
+        }
+    }
+    
+    /// `HighlightColor` of version 4.
+    #[derive(Deserialize)]
+    pub enum HighlightColorV4 {
+        Yellow,
+        Green,
+        Blue,
+        Pink
+    }
+    
+    impl From<HighlightColorV4> for HighlightColor {
+        fn from(color: HighlightColorV4) -> Self {
+            match color {
+                HighlightColorV4::Yellow => HighlightColor::Yellow,
+                HighlightColorV4::Green => HighlightColor::Green,
+                HighlightColorV4::Blue => HighlightColor::Blue,
+                HighlightColorV4::Pink => HighlightColor::Pink,
+            }
+        }
+    }
+    
+    /// `Highlight` of version 4.
+    #[derive(Deserialize)]
+    pub struct HighlightV4 {
+        pub page: usize,
+        pub start: usize,
+        pub end: usize,
+        pub text: String,
+        pub color: HighlightColorV4,
+        pub note: Option<String>,
+        pub created: DateTime<Local>,
+    }
+    
+    impl From<HighlightV4> for Highlight {
+        fn from(highlight: HighlightV4) -> Self {
+            Highlight {
+                page: highlight.page,
+                start: highlight.start,
+                end: highlight.end,
+                text: highlight.text,
+                color: highlight.color.into(),
+                note: highlight.note,
+                created: highlight.created,
+            }
+        }
+    }
+    
+    /// The unversioned format of bad-reader 1.0.
+    #[derive(Deserialize)]
+    pub struct DatabaseV0 {
+        pub rows: Option<Vec<DatabaseRowV0>>
+    }
+    
+    #[derive(Deserialize)]
+    pub struct DatabaseRowV0 {
+        pub file: PathBuf,
+        pub filename: OsString,
+        pub current_page: usize
+    }
+    
+    /// Book identity and the position inside a chapter were added.
+    #[derive(Deserialize)]
+    pub struct DatabaseV1 {
+        pub rows: Option<Vec<DatabaseRowV1>>
+    }
+    
+    #[derive(Deserialize)]
+    pub struct DatabaseRowV1 {
+        pub identity: BookIdentityV1,
+        pub file: PathBuf,
+        pub filename: OsString,
+        pub current_page: usize,
+        pub chapter_offset: usize
+    }
+    
+    impl From<DatabaseV0> for DatabaseV1 {
+        fn from(db: DatabaseV0) -> Self {
+            let rows = db.rows.map(|rows| {
+                rows.into_iter()
+                    .map(|row| DatabaseRowV1 {
+                        identity: Default::default(),
+                        file: row.file,
+                        filename: row.filename,
+                        current_page: row.current_page,
+                        chapter_offset: 0
+                    })
+                    .collect()
+            });
+            
+            DatabaseV1 {
+                rows
+            }
+        }
+    }
+    
+    /// Library metadata was added.
+    #[derive(Deserialize)]
+    pub struct DatabaseV2 {
+        pub rows: Option<Vec<DatabaseRowV2>>
+    }
+    
+    #[derive(Deserialize)]
+    pub struct DatabaseRowV2 {
+        pub identity: BookIdentityV1,
+        pub file: PathBuf,
+        pub filename: OsString,
+        pub current_page: usize,
+        pub chapter_offset: usize,
+        pub metadata: Option<BookMetadataV2>
+    }
+    
+    impl From<DatabaseV1> for DatabaseV2 {
+        fn from(db: DatabaseV1) -> Self {
+            let rows = db.rows.map(|rows| {
+                rows.into_iter()
+                    .map(|row| DatabaseRowV2 {
+                        identity: row.identity,
+                        file: row.file,
+                        filename: row.filename,
+                        current_page: row.current_page,
+                        chapter_offset: row.chapter_offset,
+                        metadata: None
+                    })
+                    .collect()
+            });
+            
+            DatabaseV2 {
+                rows
+            }
+        }
+    }
+    
+    /// Bookmarks were added.
+    #[derive(Deserialize)]
+    pub struct DatabaseV3 {
+        pub rows: Option<Vec<DatabaseRowV3>>
+    }
+    
+    #[derive(Deserialize)]
+    pub struct DatabaseRowV3 {
+        pub identity: BookIdentityV1,
+        pub file: PathBuf,
+        pub filename: OsString,
+        pub current_page: usize,
+        pub chapter_offset: usize,
+        pub metadata: Option<BookMetadataV2>,
+        pub bookmarks: Vec<BookmarkV3>
+    }
+    
+    impl From<DatabaseV2> for DatabaseV3 {
+        fn from(db: DatabaseV2) -> Self {
+            let rows = db.rows.map(|rows| {
+                rows.into_iter()
+                    .map(|row| DatabaseRowV3 {
+                        identity: row.identity,
+                        file: row.file,
+                        filename: row.filename,
+                        current_page: row.current_page,
+                        chapter_offset: row.chapter_offset,
+                        metadata: row.metadata,
+                        bookmarks: vec![]
+                    })
+                    .collect()
+            });
+            
+            DatabaseV3 {
+                rows
+            }
+        }
+    }
+    
+    /// Highlights were added.
+    #[derive(Deserialize)]
+    pub struct DatabaseV4 {
+        pub rows: Option<Vec<DatabaseRowV4>>
+    }
+    
+    #[derive(Deserialize)]
+    pub struct DatabaseRowV4 {
+        pub identity: BookIdentityV1,
+        pub file: PathBuf,
+        pub filename: OsString,
+        pub current_page: usize,
+        pub chapter_offset: usize,
+        pub metadata: Option<BookMetadataV2>,
+        pub bookmarks: Vec<BookmarkV3>,
+        pub highlights: Vec<HighlightV4>
+    }
+    
+    impl From<DatabaseV3> for DatabaseV4 {
+        fn from(db: DatabaseV3) -> Self {
+            let rows = db.rows.map(|rows| {
+                rows.into_iter()
+                    .map(|row| DatabaseRowV4 {
+                        identity: row.identity,
+                        file: row.file,
+                        filename: row.filename,
+                        current_page: row.current_page,
+                        chapter_offset: row.chapter_offset,
+                        metadata: row.metadata,
+                        bookmarks: row.bookmarks,
+                        highlights: vec![]
+                    })
+                    .collect()
+            });
+            
+            DatabaseV4 {
+                rows
+            }
+        }
+    }
+    
+    /// The time the book was last read was added.
+    #[derive(Deserialize)]
+    pub struct DatabaseV5 {
+        pub rows: Option<Vec<DatabaseRowV5>>
+    }
+    
+    #[derive(Deserialize)]
+    pub struct DatabaseRowV5 {
+        pub identity: BookIdentityV1,
+        pub file: PathBuf,
+        pub filename: OsString,
+        pub current_page: usize,
+        pub chapter_offset: usize,
+        pub metadata: Option<BookMetadataV2>,
+        pub bookmarks: Vec<BookmarkV3>,
+        pub highlights: Vec<HighlightV4>,
+        pub last_read: Option<DateTime<Local>>
+    }
+    
+    impl From<DatabaseV4> for DatabaseV5 {
+        fn from(db: DatabaseV4) -> Self {
+            let rows = db.rows.map(|rows| {
+                rows.into_iter()
+                    .map(|row| DatabaseRowV5 {
+                        identity: row.identity,
+                        file: row.file,
+                        filename: row.filename,
+                        current_page: row.current_page,
+                        chapter_offset: row.chapter_offset,
+                        metadata: row.metadata,
+                        bookmarks: row.bookmarks,
+                        highlights: row.highlights,
+                        last_read: None
+                    })
+                    .collect()
+            });
+            
+            DatabaseV5 {
+                rows
+            }
+        }
+    }
+    
+    /// Comic books read from right to left were added.
+    #[derive(Deserialize)]
+    pub struct DatabaseV6 {
+        pub rows: Option<Vec<DatabaseRowV6>>
+    }
+    
+    #[derive(Deserialize)]
+    pub struct DatabaseRowV6 {
+        pub identity: BookIdentityV1,
+        pub file: PathBuf,
+        pub filename: OsString,
+        pub current_page: usize,
+        pub chapter_offset: usize,
+        pub metadata: Option<BookMetadataV2>,
+        pub bookmarks: Vec<BookmarkV3>,
+        pub highlights: Vec<HighlightV4>,
+        pub last_read: Option<DateTime<Local>>,
+        pub right_to_left: bool
+    }
+    
+    impl From<DatabaseV5> for DatabaseV6 {
+        fn from(db: DatabaseV5) -> Self {
+            let rows = db.rows.map(|rows| {
+                rows.into_iter()
+                    .map(|row| DatabaseRowV6 {
+                        identity: row.identity,
+                        file: row.file,
+                        filename: row.filename,
+                        current_page: row.current_page,
+                        chapter_offset: row.chapter_offset,
+                        metadata: row.metadata,
+                        bookmarks: row.bookmarks,
+                        highlights: row.highlights,
+                        last_read: row.last_read,
+                        right_to_left: false
+                    })
+                    .collect()
+            });
+            
+            DatabaseV6 {
+                rows
+            }
+        }
+    }
+    
+    impl From<DatabaseV6> for Database {
+        fn from(db: DatabaseV6) -> Self {
+            let rows = db.rows.map(|rows| {
+                rows.into_iter()
+                    .map(|row| DatabaseRow {
+                        identity: row.identity.into(),
+                        file: row.file,
+                        filename: row.filename,
+                        current_page: row.current_page,
+                        chapter_offset: row.chapter_offset,
+                        metadata: row.metadata.map(BookMetadata::from),
+                        bookmarks: row.bookmarks.into_iter().map(Bookmark::from).collect(),
+                        highlights: row.highlights.into_iter().map(Highlight::from).collect(),
+                        last_read: row.last_read,
+                        right_to_left: row.right_to_left,
+                        progress: None
+                    })
+                    .collect()
+            });
+            
+            Database::new(rows)
+        }
     }
-    // Open the db file and try to deserialize its contents into `Database`
-    let db_file = File::open(&working_dir(DB_FILE))
-        .expect("DB_FILE does not exist");
-    deserialize_from(BufReader::new(db_file))
-        .expect("Could not deserialize DB_FILE")
 }
\ No newline at end of file
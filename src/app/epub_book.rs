@@ -0,0 +1,105 @@
+//! Epub books, and the books in the other formats once they have been converted to an epub.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use anyhow::anyhow;
+use epub::doc::{EpubDoc, NavPoint};
+use select::document::Document;
+use crate::app::book::{Book, BookInfo, Section, TocItem};
+use crate::app::utils::percent_decode;
+
+
+pub struct EpubBook {
+    doc: EpubDoc<BufReader<File>>,
+    sections: Vec<Section>,
+    toc: Vec<TocItem>,
+}
+
+impl EpubBook {
+    pub fn open(path: &Path) -> anyhow::Result<EpubBook> {
+        let doc = EpubDoc::new(path)?;
+
+        let mut sections: Vec<Section> = doc.spine
+            .iter()
+            .map(|id| Section {
+                path: doc.resources.get(id).map(|resource| resource.0.clone()).unwrap_or_default(),
+                title: None,
+            })
+            .collect();
+        if sections.is_empty() {
+            return Err(anyhow!("The book has no sections"));
+        }
+        let toc = toc_items(&doc.toc, &mut sections);
+
+        Ok(EpubBook {
+            doc,
+            sections,
+            toc,
+        })
+    }
+}
+
+impl Book for EpubBook {
+    fn info(&self) -> BookInfo {
+        BookInfo {
+            identifier: self.doc.mdata("identifier"),
+            title: self.doc.mdata("title"),
+            author: self.doc.mdata("creator"),
+            language: self.doc.mdata("language"),
+        }
+    }
+
+    fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    fn toc(&self) -> &[TocItem] {
+        &self.toc
+    }
+
+    fn section_content(&mut self, index: usize) -> anyhow::Result<Document> {
+        let section = self.sections.get(index).ok_or_else(|| anyhow!("No section {}", index))?;
+        let content = self.doc.get_resource_str_by_path(&section.path)?;
+
+        Ok(Document::from(content.as_str()))
+    }
+
+    fn resource(&mut self, path: &Path) -> Option<Vec<u8>> {
+        self.doc.get_resource_by_path(path).ok()
+    }
+
+    fn cover(&mut self) -> Option<Vec<u8>> {
+        self.doc.get_cover().ok()
+    }
+}
+
+/// Table of contents from the navigation points. The sections get the
+/// title of the first entry that points to them.
+fn toc_items(points: &[NavPoint], sections: &mut [Section]) -> Vec<TocItem> {
+    let mut items = vec![];
+
+    for point in points {
+        let content = point.content.to_string_lossy().to_string();
+        let (path, anchor) = match content.split_once('#') {
+            Some((path, anchor)) => (path.to_string(), Some(percent_decode(anchor))),
+            None => (content, None),
+        };
+        let label = point.label.trim().to_string();
+
+        let path = PathBuf::from(path);
+        let section = sections.iter().position(|section| section.path == path);
+        if let Some(index) = section {
+            sections[index].title.get_or_insert_with(|| label.clone());
+        }
+
+        items.push(TocItem {
+            label,
+            section,
+            anchor,
+            children: toc_items(&point.children, sections),
+        });
+    }
+
+    items
+}
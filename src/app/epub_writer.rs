@@ -0,0 +1,191 @@
+//! Writes the epubs that the other book formats are converted to.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use zip::{ZipWriter, CompressionMethod};
+use zip::write::FileOptions;
+use crate::app::paths::{self, Location};
+
+
+/// A book to write as an epub. The file names are relative to the content directory.
+#[derive(Debug, Default)]
+pub struct GeneratedBook {
+    pub identifier: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub description: Option<String>,
+    pub chapters: Vec<GeneratedChapter>,
+    pub toc: Vec<TocEntry>,
+    pub images: Vec<GeneratedImage>,
+    /// File name of the cover in `images`.
+    pub cover: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct GeneratedChapter {
+    pub file_name: String,
+    pub title: String,
+    /// Inner xhtml of the `<body>`.
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub struct TocEntry {
+    pub title: String,
+    /// Chapter file name, optionally with an `#anchor`.
+    pub href: String,
+    pub children: Vec<TocEntry>,
+}
+
+#[derive(Debug)]
+pub struct GeneratedImage {
+    pub file_name: String,
+    pub media_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Where the epub converted from a book with the `content_hash` is cached.
+/// `kind` and `version` keep the conversions of the different formats and converter versions apart.
+pub fn cache_path(kind: &str, version: u32, content_hash: u64) -> PathBuf {
+    paths::path(Location::Cache, &format!("books/{}-{}-{:016x}.epub", kind, version, content_hash))
+}
+
+/// Write the `book` to `path`. The file is written next to it first so a half written epub is never opened.
+pub fn save(path: &Path, book: &GeneratedBook) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp_path = path.with_extension("epub.tmp");
+    write_epub(&tmp_path, book).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    fs::rename(&tmp_path, path)
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_epub(path: &Path, book: &GeneratedBook) -> zip::result::ZipResult<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The mimetype has to be the first file and uncompressed.
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(br#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#)?;
+
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(package(book).as_bytes())?;
+
+    zip.start_file("OEBPS/toc.ncx", deflated)?;
+    zip.write_all(ncx(book).as_bytes())?;
+
+    for chapter in &book.chapters {
+        zip.start_file(format!("OEBPS/{}", chapter.file_name), deflated)?;
+        write!(zip, r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{}</title></head>
+<body>
+{}</body>
+</html>
+"#, escape(&chapter.title), chapter.body)?;
+    }
+
+    for image in &book.images {
+        zip.start_file(format!("OEBPS/{}", image.file_name), stored)?;
+        zip.write_all(&image.data)?;
+    }
+
+    zip.finish()?;
+
+    Ok(())
+}
+
+fn package(book: &GeneratedBook) -> String {
+    let mut metadata = format!(
+        "    <dc:identifier id=\"book-id\">{}</dc:identifier>\n    <dc:title>{}</dc:title>\n",
+        escape(&book.identifier),
+        escape(&book.title)
+    );
+    if let Some(author) = &book.author {
+        metadata.push_str(&format!("    <dc:creator>{}</dc:creator>\n", escape(author)));
+    }
+    if let Some(language) = &book.language {
+        metadata.push_str(&format!("    <dc:language>{}</dc:language>\n", escape(language)));
+    }
+    if let Some(description) = &book.description {
+        metadata.push_str(&format!("    <dc:description>{}</dc:description>\n", escape(description)));
+    }
+
+    let mut manifest = String::from("    <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n");
+    let mut spine = String::new();
+    for (i, chapter) in book.chapters.iter().enumerate() {
+        manifest.push_str(&format!(
+            "    <item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+            i + 1, escape(&chapter.file_name)
+        ));
+        spine.push_str(&format!("    <itemref idref=\"chapter-{}\"/>\n", i + 1));
+    }
+    for (i, image) in book.images.iter().enumerate() {
+        manifest.push_str(&format!(
+            "    <item id=\"image-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            i + 1, escape(&image.file_name), escape(&image.media_type)
+        ));
+        if book.cover.as_ref() == Some(&image.file_name) {
+            metadata.push_str(&format!("    <meta name=\"cover\" content=\"image-{}\"/>\n", i + 1));
+        }
+    }
+
+    format!(r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="book-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{}  </metadata>
+  <manifest>
+{}  </manifest>
+  <spine toc="ncx">
+{}  </spine>
+</package>
+"#, metadata, manifest, spine)
+}
+
+fn ncx(book: &GeneratedBook) -> String {
+    fn nav_points(entries: &[TocEntry], depth: usize, play_order: &mut usize, out: &mut String) {
+        let indent = "  ".repeat(depth + 2);
+        for entry in entries {
+            *play_order += 1;
+            out.push_str(&format!(
+                "{0}<navPoint id=\"nav-{1}\" playOrder=\"{1}\"><navLabel><text>{2}</text></navLabel><content src=\"{3}\"/>\n",
+                indent, play_order, escape(&entry.title), escape(&entry.href)
+            ));
+            nav_points(&entry.children, depth + 1, play_order, out);
+            out.push_str(&format!("{}</navPoint>\n", indent));
+        }
+    }
+
+    let mut nav_map = String::new();
+    nav_points(&book.toc, 0, &mut 0, &mut nav_map);
+
+    format!(r#"<?xml version="1.0" encoding="utf-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <head><meta name="dtb:uid" content="{}"/></head>
+  <docTitle><text>{}</text></docTitle>
+  <navMap>
+{}  </navMap>
+</ncx>
+"#, escape(&book.identifier), escape(&book.title), nav_map)
+}
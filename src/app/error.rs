@@ -1,18 +1,66 @@
 use thiserror::Error;
+use std::path::PathBuf;
 
 
 #[derive(Error, Debug)]
-pub enum SettingsError {
-    #[error("Failed to open and read config file on disk.")]
-    ReadFromDisk,
-    #[error("Failed to write config file to disk.")]
-    WriteToDisk,
-    #[error("Unknown error.")]
-    Unknown,
+pub enum StorageError {
+    #[error("Failed to read or write a file on disk: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to serialize the data: {0}")]
+    Serialize(#[from] bincode::Error),
+    #[error("Could not read {0:?} ({1}). The file was kept as {2:?}.")]
+    Unreadable(PathBuf, String, PathBuf),
+    #[error("Could not read {0:?} ({1}).")]
+    Invalid(PathBuf, String),
+    #[error("{0:?} was written by a newer version of the program (format version {1}). It was left as it is and won't be changed.")]
+    UnsupportedVersion(PathBuf, u32),
 }
 
-impl From<anyhow::Error> for SettingsError {
-    fn from(_err: anyhow::Error) -> Self {
-        SettingsError::Unknown
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("Failed to read or write the archive: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The archive is not a valid zip file: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Invalid JSON in {0}: {1}")]
+    Json(String, serde_json::Error),
+    #[error("The archive format version {0} is newer than this program supports.")]
+    UnsupportedVersion(u32),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+/// Errors of the commands that run without the main window.
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("Cannot read {0:?}: {1}")]
+    Unreadable(PathBuf, std::io::Error),
+    #[error("{0:?} is not a book that can be read as text.")]
+    UnsupportedFormat(PathBuf),
+    #[error("{0:?} is not a valid book: {1}")]
+    Malformed(PathBuf, anyhow::Error),
+    #[error("There is no chapter {0}, the book has {1} chapters numbered from 0.")]
+    NoSuchChapter(usize, usize),
+    #[error("Failed to write the output: {0}")]
+    Output(std::io::Error),
+    #[error("A reader is running with this library and would overwrite the import. Close it or import from the File menu.")]
+    ReaderRunning,
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+impl CliError {
+    /// Exit code of the program for the error, listed in the README.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Archive(_) | CliError::Storage(_) | CliError::Output(_) => 1,
+            CliError::Unreadable(..) => 3,
+            CliError::UnsupportedFormat(_) => 4,
+            CliError::Malformed(..) => 5,
+            CliError::NoSuchChapter(..) => 6,
+            CliError::ReaderRunning => 7,
+        }
     }
-}
\ No newline at end of file
+}
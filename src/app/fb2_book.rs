@@ -0,0 +1,536 @@
+//! FictionBook (`.fb2` and `.fb2.zip`) books, converted to an epub in the cache
+//! once like the text books.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context};
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+use roxmltree::{Document, Node};
+use zip::ZipArchive;
+use crate::app::epub_writer::{self, GeneratedBook, GeneratedChapter, GeneratedImage, TocEntry, escape};
+use crate::app::utils::hash_file;
+
+
+/// Bumped when the conversion changes so the old cached epubs aren't used.
+const CONVERTER_VERSION: u32 = 2;
+/// Links are written with this prefix and pointed to the chapter that has the id after all chapters are done.
+const LINK_MARKER: &str = "bad-reader-link:";
+const NOTES_FILE: &str = "notes.xhtml";
+
+/// Path of the epub converted from the FictionBook `file`, converting it first if it's not in the cache.
+pub fn converted_epub(file: &Path) -> anyhow::Result<PathBuf> {
+    let content_hash = hash_file(file)?;
+    let path = epub_writer::cache_path("fb2", CONVERTER_VERSION, content_hash);
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let xml = decode(&read_fb2(file)?);
+    let document = Document::parse(&xml).context("Invalid FictionBook XML")?;
+    let root = document.root_element();
+    if root.tag_name().name() != "FictionBook" {
+        return Err(anyhow!("Not a FictionBook file"));
+    }
+
+    let mut converter = Converter::default();
+    converter.add_binaries(root);
+
+    let mut book = converter.description(root, file);
+    if book.identifier.is_empty() {
+        book.identifier = format!("urn:bad-reader:fb2:{:016x}", content_hash);
+    }
+
+    let bodies: Vec<Node> = children(root, "body").collect();
+    let (notes, main): (Vec<Node>, Vec<Node>) = bodies
+        .into_iter()
+        .partition(|body| matches!(body.attribute("name"), Some("notes") | Some("comments")));
+
+    for body in main {
+        converter.body(body);
+    }
+    for body in notes {
+        converter.notes(body);
+    }
+
+    book.toc = std::mem::take(&mut converter.toc);
+    book.chapters = converter.resolve_links();
+    book.images = converter.images;
+
+    epub_writer::save(&path, &book)?;
+    debug!("Converted {:?} to {:?}", file, path);
+
+    Ok(path)
+}
+
+/// Bytes of the `.fb2` file, or of the first `.fb2` file in a `.fb2.zip`.
+fn read_fb2(file: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let is_zip = file.to_string_lossy().to_lowercase().ends_with(".zip");
+
+    if is_zip {
+        let mut zip = ZipArchive::new(File::open(file)?)?;
+        let name = zip.file_names()
+            .find(|name| name.to_lowercase().ends_with(".fb2"))
+            .map(String::from)
+            .ok_or_else(|| anyhow!("No .fb2 file in the archive"))?;
+        zip.by_name(&name)?.read_to_end(&mut bytes)?;
+    } else {
+        bytes = fs::read(file)?;
+    }
+
+    Ok(bytes)
+}
+
+/// Decode using the byte order mark or the encoding in the XML declaration,
+/// many FictionBooks are in windows-1251.
+fn decode(bytes: &[u8]) -> String {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return encoding.decode_without_bom_handling(&bytes[bom_length..]).0.into_owned();
+    }
+
+    let declaration = String::from_utf8_lossy(&bytes[..bytes.len().min(200)]).to_string();
+    let encoding = Regex::new(r#"encoding\s*=\s*["']([^"']+)["']"#)
+        .expect("Bad encoding regex")
+        .captures(&declaration)
+        .and_then(|captures| Encoding::for_label(captures[1].as_bytes()))
+        .unwrap_or(UTF_8);
+
+    encoding.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+fn children<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// Text of the node with the whitespace collapsed.
+fn plain_text(node: Node) -> String {
+    node.descendants()
+        .filter(|node| node.is_text())
+        .filter_map(|node| node.text())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `l:href` or `xlink:href`, the prefix of the namespace varies between books.
+fn href<'a>(node: Node<'a, '_>) -> Option<&'a str> {
+    node.attributes()
+        .iter()
+        .find(|attribute| attribute.name() == "href")
+        .map(|attribute| attribute.value())
+}
+
+#[derive(Default)]
+struct Converter {
+    chapters: Vec<GeneratedChapter>,
+    toc: Vec<TocEntry>,
+    images: Vec<GeneratedImage>,
+    /// `<binary>` id -> image file name.
+    image_files: HashMap<String, String>,
+    /// Element id -> chapter file name, for the links.
+    ids: HashMap<String, String>,
+    current_file: String,
+}
+
+impl Converter {
+    fn add_binaries(&mut self, root: Node) {
+        for binary in children(root, "binary") {
+            let id = match binary.attribute("id") {
+                Some(id) => id,
+                None => continue,
+            };
+            let data: String = binary.text().unwrap_or_default().split_whitespace().collect();
+            let data = match base64::decode(&data) {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!("Cannot decode the FictionBook image {}. {}", id, e);
+                    continue;
+                }
+            };
+
+            let safe_id: String = id
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+                .collect();
+            let file_name = format!("images/{:03}-{}", self.images.len() + 1, safe_id);
+
+            self.image_files.insert(id.to_string(), file_name.clone());
+            self.images.push(GeneratedImage {
+                file_name,
+                media_type: binary.attribute("content-type").unwrap_or("image/jpeg").to_string(),
+                data,
+            });
+        }
+    }
+
+    /// The book metadata from `<title-info>` and `<document-info>`.
+    fn description(&self, root: Node, file: &Path) -> GeneratedBook {
+        let description = child(root, "description");
+        let title_info = description.and_then(|description| child(description, "title-info"));
+        let document_info = description.and_then(|description| child(description, "document-info"));
+
+        let title = title_info
+            .and_then(|info| child(info, "book-title"))
+            .map(plain_text)
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| {
+                file.file_stem()
+                    .map(|stem| stem.to_string_lossy().trim_end_matches(".fb2").to_string())
+                    .unwrap_or_default()
+            });
+
+        let authors: Vec<String> = title_info
+            .map(|info| children(info, "author").map(author_name).filter(|name| !name.is_empty()).collect())
+            .unwrap_or_default();
+
+        let cover = title_info
+            .and_then(|info| child(info, "coverpage"))
+            .and_then(|coverpage| child(coverpage, "image"))
+            .and_then(href)
+            .and_then(|href| self.image_files.get(href.trim_start_matches('#')))
+            .cloned();
+
+        GeneratedBook {
+            identifier: document_info
+                .and_then(|info| child(info, "id"))
+                .map(plain_text)
+                .unwrap_or_default(),
+            title,
+            author: if authors.is_empty() { None } else { Some(authors.join(", ")) },
+            language: title_info.and_then(|info| child(info, "lang")).map(plain_text),
+            description: title_info.and_then(|info| child(info, "annotation")).map(plain_text),
+            cover,
+            ..Default::default()
+        }
+    }
+
+    fn body(&mut self, body: Node) {
+        // The title, epigraphs and images before the first section.
+        let mut intro = String::new();
+        self.start_chapter();
+        for node in body.children().filter(|node| node.is_element() && node.tag_name().name() != "section") {
+            self.block(node, 1, &mut intro);
+        }
+        if !intro.is_empty() {
+            let title = child(body, "title").map(plain_text).unwrap_or_default();
+            self.finish_chapter(title, intro);
+        }
+
+        for section in children(body, "section") {
+            let entries = self.section(section, 1);
+            self.toc.extend(entries);
+        }
+    }
+
+    /// A section with no subsections is a chapter, a section with subsections gets a chapter for
+    /// its own content and then the subsections get theirs. Returns the section's toc entries.
+    fn section(&mut self, section: Node, depth: usize) -> Vec<TocEntry> {
+        let title = child(section, "title").map(plain_text).filter(|title| !title.is_empty());
+
+        self.start_chapter();
+        self.add_id(section);
+        let href = self.current_file.clone();
+        let mut content = String::new();
+        let mut entries = vec![];
+
+        for node in section.children().filter(|node| node.is_element()) {
+            if node.tag_name().name() == "section" {
+                if !content.is_empty() {
+                    self.finish_chapter(title.clone().unwrap_or_default(), std::mem::take(&mut content));
+                }
+                entries.extend(self.section(node, depth + 1));
+                self.start_chapter();
+            } else {
+                self.block(node, depth, &mut content);
+            }
+        }
+        if !content.is_empty() {
+            self.finish_chapter(title.clone().unwrap_or_default(), content);
+        }
+
+        // Sections without a title don't get an entry, their subsections move up a level.
+        match title {
+            Some(title) => vec![TocEntry { title, href, children: entries }],
+            None => entries,
+        }
+    }
+
+    /// The notes are in one chapter and open in the footnote popover from the note links.
+    fn notes(&mut self, body: Node) {
+        self.current_file = NOTES_FILE.to_string();
+
+        let mut content = String::new();
+        for node in body.children().filter(|node| node.is_element()) {
+            match node.tag_name().name() {
+                "section" => {
+                    self.add_id(node);
+                    content.push_str(&id_attribute(node).map_or_else(|| "<div>".to_string(), |id| format!("<div id=\"{}\">", id)));
+                    for child in node.children().filter(|child| child.is_element()) {
+                        self.block(child, 3, &mut content);
+                    }
+                    content.push_str("</div>\n");
+                },
+                _ => self.block(node, 2, &mut content),
+            }
+        }
+
+        let title = child(body, "title").map(plain_text).unwrap_or_else(|| "Notes".to_string());
+        self.toc.push(TocEntry {
+            title: title.clone(),
+            href: NOTES_FILE.to_string(),
+            children: vec![],
+        });
+        self.chapters.push(GeneratedChapter {
+            file_name: NOTES_FILE.to_string(),
+            title,
+            body: content,
+        });
+    }
+
+    /// Set the file name for the next chapter, so the ids found before it's finished point to it.
+    fn start_chapter(&mut self) {
+        self.current_file = format!("chapter_{:04}.xhtml", self.chapters.len() + 1);
+    }
+
+    fn finish_chapter(&mut self, title: String, body: String) {
+        self.chapters.push(GeneratedChapter {
+            file_name: self.current_file.clone(),
+            title,
+            body,
+        });
+    }
+
+    fn add_id(&mut self, node: Node) {
+        if let Some(id) = node.attribute("id") {
+            self.ids.insert(id.to_string(), self.current_file.clone());
+        }
+    }
+
+    /// Write the block element `node` as xhtml. `depth` is the section depth for the heading levels.
+    fn block(&mut self, node: Node, depth: usize, out: &mut String) {
+        self.add_id(node);
+        let id = id_attribute(node).map(|id| format!(" id=\"{}\"", id)).unwrap_or_default();
+
+        match node.tag_name().name() {
+            "title" => {
+                let level = (depth + 1).min(6);
+                let lines: Vec<String> = children(node, "p").map(|p| self.inline_children(p)).collect();
+                let text = if lines.is_empty() { self.inline_children(node) } else { lines.join("<br/>") };
+                out.push_str(&format!("<h{0}{1}>{2}</h{0}>\n", level, id, text));
+            },
+            "subtitle" => {
+                let level = (depth + 2).min(6);
+                out.push_str(&format!("<h{0}{1}>{2}</h{0}>\n", level, id, self.inline_children(node)));
+            },
+            "p" | "v" => {
+                out.push_str(&format!("<p{}>{}</p>\n", id, self.inline_children(node)));
+            },
+            "text-author" => {
+                out.push_str(&format!("<p{}><em>{}</em></p>\n", id, self.inline_children(node)));
+            },
+            "empty-line" => out.push_str("<br/>\n"),
+            "image" => {
+                out.push_str("<p>");
+                self.image(node, out);
+                out.push_str("</p>\n");
+            },
+            "epigraph" | "cite" | "annotation" => {
+                out.push_str(&format!("<blockquote{}>\n", id));
+                self.blocks(node, depth, out);
+                out.push_str("</blockquote>\n");
+            },
+            "poem" | "stanza" => {
+                out.push_str(&format!("<div{}>\n", id));
+                self.blocks(node, depth, out);
+                out.push_str("</div>\n");
+            },
+            "table" => {
+                out.push_str(&format!("<table{}>\n", id));
+                for row in children(node, "tr") {
+                    out.push_str("<tr>");
+                    for cell in row.children().filter(|cell| cell.is_element()) {
+                        let name = if cell.tag_name().name() == "th" { "th" } else { "td" };
+                        out.push_str(&format!("<{0}>{1}</{0}>", name, self.inline_children(cell)));
+                    }
+                    out.push_str("</tr>\n");
+                }
+                out.push_str("</table>\n");
+            },
+            // Sections inside the other blocks, e.g. in the notes.
+            "section" => self.blocks(node, depth + 1, out),
+            _ => {
+                let text = self.inline_children(node);
+                if !text.trim().is_empty() {
+                    out.push_str(&format!("<p{}>{}</p>\n", id, text));
+                }
+            },
+        }
+    }
+
+    fn blocks(&mut self, node: Node, depth: usize, out: &mut String) {
+        for child in node.children().filter(|child| child.is_element()) {
+            self.block(child, depth, out);
+        }
+    }
+
+    fn inline_children(&mut self, node: Node) -> String {
+        let mut out = String::new();
+        for child in node.children() {
+            self.inline(child, &mut out);
+        }
+
+        out
+    }
+
+    fn inline(&mut self, node: Node, out: &mut String) {
+        if node.is_text() {
+            out.push_str(&escape(node.text().unwrap_or_default()));
+            return;
+        }
+        if !node.is_element() {
+            return;
+        }
+        self.add_id(node);
+
+        let tag = match node.tag_name().name() {
+            "emphasis" => "em",
+            "strong" => "strong",
+            "strikethrough" => "s",
+            "sub" => "sub",
+            "sup" => "sup",
+            "code" => "code",
+            "image" => return self.image(node, out),
+            "a" => return self.link(node, out),
+            "p" | "v" => {
+                // Paragraphs inside the cells and titles are separate lines.
+                if !out.is_empty() {
+                    out.push_str("<br/>");
+                }
+                out.push_str(&self.inline_children(node));
+                return;
+            },
+            _ => {
+                out.push_str(&self.inline_children(node));
+                return;
+            },
+        };
+
+        out.push_str(&format!("<{0}>{1}</{0}>", tag, self.inline_children(node)));
+    }
+
+    fn image(&mut self, node: Node, out: &mut String) {
+        let file_name = href(node).and_then(|href| self.image_files.get(href.trim_start_matches('#')));
+        if let Some(file_name) = file_name {
+            let alt = node.attribute("alt").unwrap_or_default();
+            out.push_str(&format!("<img src=\"{}\" alt=\"{}\"/>", escape(file_name), escape(alt)));
+        }
+    }
+
+    fn link(&mut self, node: Node, out: &mut String) {
+        let text = self.inline_children(node);
+        let target = match href(node) {
+            Some(target) => target,
+            None => {
+                out.push_str(&text);
+                return;
+            }
+        };
+
+        match target.strip_prefix('#') {
+            Some(id) => {
+                let noteref = if node.attribute("type") == Some("note") { " epub:type=\"noteref\"" } else { "" };
+                out.push_str(&format!("<a{} href=\"{}{}\">{}</a>", noteref, LINK_MARKER, escape(id), text));
+            },
+            None => out.push_str(&format!("<a href=\"{}\">{}</a>", escape(target), text)),
+        }
+    }
+
+    /// Point the links to the chapters that have the ids, now that all chapters are done.
+    fn resolve_links(&mut self) -> Vec<GeneratedChapter> {
+        let marker = Regex::new(&format!(r#"{}([^"]*)"#, LINK_MARKER)).expect("Bad link regex");
+        let ids = &self.ids;
+
+        std::mem::take(&mut self.chapters)
+            .into_iter()
+            .map(|mut chapter| {
+                chapter.body = marker
+                    .replace_all(&chapter.body, |captures: &regex::Captures| {
+                        let id = &captures[1];
+                        match ids.get(&unescape_attribute(id)) {
+                            Some(file_name) => format!("{}#{}", file_name, id),
+                            None => format!("#{}", id),
+                        }
+                    })
+                    .to_string();
+                chapter
+            })
+            .collect()
+    }
+}
+
+/// The value of an attribute written with `escape`, with the entities decoded by the XML parser.
+fn unescape_attribute(value: &str) -> String {
+    Document::parse(&format!("<a value=\"{}\"/>", value))
+        .ok()
+        .and_then(|document| document.root_element().attribute("value").map(String::from))
+        .unwrap_or_else(|| value.to_string())
+}
+
+fn id_attribute(node: Node) -> Option<String> {
+    node.attribute("id").map(escape)
+}
+
+fn author_name(author: Node) -> String {
+    let names: Vec<String> = ["first-name", "middle-name", "last-name"]
+        .iter()
+        .filter_map(|name| child(author, *name))
+        .map(plain_text)
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    if names.is_empty() {
+        child(author, "nickname").map(plain_text).unwrap_or_default()
+    } else {
+        names.join(" ")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_to_ids_with_entities() {
+        let xml = r##"<FictionBook xmlns:l="http://www.w3.org/1999/xlink">
+            <body>
+                <section><p><a l:href="#fish&amp;chips&lt;&quot;&apos;">Next</a></p></section>
+                <section id="fish&amp;chips&lt;&quot;&apos;"><p>Here.</p></section>
+            </body>
+        </FictionBook>"##;
+        let document = Document::parse(xml).unwrap();
+        let mut converter = Converter::default();
+        for body in children(document.root_element(), "body") {
+            converter.body(body);
+        }
+
+        let file_name = converter.ids["fish&chips<\"'"].clone();
+        let chapters = converter.resolve_links();
+        let link = format!("href=\"{}#fish&amp;chips&lt;&quot;'\"", file_name);
+        assert!(chapters.iter().any(|chapter| chapter.body.contains(&link)));
+    }
+
+    #[test]
+    fn unescapes_attributes() {
+        assert_eq!(unescape_attribute("a&amp;b&lt;c&gt;d&quot;e"), "a&b<c>d\"e");
+        assert_eq!(unescape_attribute("plain"), "plain");
+    }
+}
@@ -22,6 +22,24 @@ pub fn new(appop: &AppOp) {
     let scroll_down_amount = SimpleAction::new("scroll_down_amount", None);
     let scroll_up_amount = SimpleAction::new("scroll_up_amount", None);
     let page_manual = SimpleAction::new("page", None);
+    let find = SimpleAction::new("find", None);
+    let toggle_search = SimpleAction::new("toggle_search", None);
+    let toggle_bookmarks = SimpleAction::new("toggle_bookmarks", None);
+    let toggle_bookmark = SimpleAction::new("toggle_bookmark", None);
+    let toggle_highlights = SimpleAction::new("toggle_highlights", None);
+    let export_library = SimpleAction::new("export_library", None);
+    let import_library = SimpleAction::new("import_library", None);
+    let comic_toggle_fit = SimpleAction::new("comic_toggle_fit", None);
+    let comic_zoom_in = SimpleAction::new("comic_zoom_in", None);
+    let comic_zoom_out = SimpleAction::new("comic_zoom_out", None);
+    let comic_zoom_reset = SimpleAction::new("comic_zoom_reset", None);
+    let toggle_right_to_left = SimpleAction::new("toggle_right_to_left", None);
+    let page_left = SimpleAction::new("page_left", None);
+    let page_right = SimpleAction::new("page_right", None);
+    let scroll_line_up = SimpleAction::new("scroll_line_up", None);
+    let scroll_line_down = SimpleAction::new("scroll_line_down", None);
+    let toggle_paginated = SimpleAction::new("toggle_paginated", None);
+    let toggle_continuous = SimpleAction::new("toggle_continuous", None);
     
     app.add_action(&toggle_fullscreen);
     app.add_action(&toggle_toc);
@@ -36,23 +54,29 @@ pub fn new(appop: &AppOp) {
     app.add_action(&scroll_down_amount);
     app.add_action(&scroll_up_amount);
     app.add_action(&page_manual);
-    
-    // Some of these accels are set in the menubar via glade but during the fullscreen
-    // when the menubar is hidden they don't work and they don't seem to get duplicated so
-    // this is fine.
-    app.set_accels_for_action("app.toggle_fullscreen", &["F11", "F"]);
-    app.set_accels_for_action("app.toggle_toc", &["C"]);
-    app.set_accels_for_action("app.toggle_recent", &["R"]);
-    app.set_accels_for_action("app.open_about", &["F1"]);
-    app.set_accels_for_action("app.open_settings", &["F2"]);
-    app.set_accels_for_action("app.quit", &["<Primary>Q"]);
-    app.set_accels_for_action("app.auto_scroll", &["section", "F8"]);
-    app.set_accels_for_action("app.open_file_chooser", &["<Primary>O"]);
-    app.set_accels_for_action("app.scroll_down", &["End"]);
-    app.set_accels_for_action("app.scroll_up", &["Home"]);
-    app.set_accels_for_action("app.scroll_down_amount", &["Page_Down", "space"]);
-    app.set_accels_for_action("app.scroll_up_amount", &["Page_Up"]);
-    app.set_accels_for_action("app.page", &["G"]);
+    app.add_action(&find);
+    app.add_action(&toggle_search);
+    app.add_action(&toggle_bookmarks);
+    app.add_action(&toggle_bookmark);
+    app.add_action(&toggle_highlights);
+    app.add_action(&export_library);
+    app.add_action(&import_library);
+    app.add_action(&comic_toggle_fit);
+    app.add_action(&comic_zoom_in);
+    app.add_action(&comic_zoom_out);
+    app.add_action(&comic_zoom_reset);
+    app.add_action(&toggle_right_to_left);
+    app.add_action(&page_left);
+    app.add_action(&page_right);
+    app.add_action(&scroll_line_up);
+    app.add_action(&scroll_line_down);
+    app.add_action(&toggle_paginated);
+    app.add_action(&toggle_continuous);
+    
+    // The shortcuts come from the keymap. They are set on the application and not on the
+    // menu items so that they also work in the fullscreen when the menubar is hidden.
+    appop.keymap.apply(app);
+    appop.ui.update_menu_accels(&appop.keymap);
     
     
     auto_scroll.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
@@ -76,17 +100,41 @@ pub fn new(appop: &AppOp) {
     scroll_down_amount.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
         app_runtime.update_state_with(|state| {
             let height = state.ui.main_window.size().1 as f64;
-            state.ui.scroll_down(height * 0.8);
+            state.scroll_forward(height * 0.8);
         });
     }));
     
     scroll_up_amount.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
         app_runtime.update_state_with(|state| {
             let height = state.ui.main_window.size().1 as f64;
-            state.ui.scroll_up(height * 0.8);
+            state.scroll_backward(height * 0.8);
         });
     }));
     
+    scroll_line_down.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.scroll_forward(crate::ui::SCROLL_AMOUNT));
+    }));
+    
+    scroll_line_up.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.scroll_backward(crate::ui::SCROLL_AMOUNT));
+    }));
+    
+    toggle_paginated.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.toggle_paginated());
+    }));
+    
+    toggle_continuous.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.toggle_continuous());
+    }));
+    
+    page_left.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.page_left());
+    }));
+    
+    page_right.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.page_right());
+    }));
+    
     quit.connect_activate(glib::clone!(@strong app_runtime => move |_action, _param| {
         app_runtime.update_state_with(|state| {
             state.ui.main_window.close();
@@ -111,6 +159,28 @@ pub fn new(appop: &AppOp) {
         });
     }));
     
+    toggle_search.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            state.ui.toggle_search();
+        });
+    }));
+    
+    toggle_bookmarks.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            state.ui.toggle_bookmarks();
+        });
+    }));
+    
+    toggle_bookmark.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.toggle_bookmark());
+    }));
+    
+    toggle_highlights.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            state.ui.toggle_highlights();
+        });
+    }));
+    
     about.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
         app_runtime.update_state_with(|state| state.ui.about_dialog());
     }));
@@ -119,6 +189,10 @@ pub fn new(appop: &AppOp) {
         app_runtime.update_state_with(|state| state.toggle_page_dialog());
     }));
     
+    find.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.ui.toggle_find_bar());
+    }));
+    
     settings.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
         app_runtime.update_state_with(|state| {
             state.ui.show_settings_dialog(state.settings.read().clone());
@@ -129,6 +203,34 @@ pub fn new(appop: &AppOp) {
         app_runtime.update_state_with(|state| state.ui.file_chooser_dialog(state.app_runtime.clone()));
     }));
     
+    export_library.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.ui.export_library_chooser(state.app_runtime.clone()));
+    }));
+    
+    import_library.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.ui.import_library_chooser(state.app_runtime.clone()));
+    }));
+    
+    comic_toggle_fit.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.ui.toggle_comic_fit());
+    }));
+    
+    comic_zoom_in.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.ui.comic_zoom_in());
+    }));
+    
+    comic_zoom_out.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.ui.comic_zoom_out());
+    }));
+    
+    comic_zoom_reset.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.ui.reset_comic_zoom());
+    }));
+    
+    toggle_right_to_left.connect_activate(glib::clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.toggle_right_to_left());
+    }));
+    
     // Keep track of the fullscren state.
     appop.ui.main_window.connect_window_state_event(glib::clone!(@strong app_runtime => move |_window, event| {
         let is_fullscreen = event.new_window_state().contains(gdk::WindowState::FULLSCREEN);
@@ -0,0 +1,218 @@
+//! Keyboard shortcuts of the actions. They are loaded from a keymap file
+//! that can be edited by hand or from the settings.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use gtk::prelude::*;
+use crate::app::error::StorageError;
+use crate::app::paths::{self, Location};
+
+
+pub static KEYMAP_NAME: &str = "keymap.conf";
+
+/// An action that can have keyboard shortcuts.
+pub struct KeyAction {
+    /// Name of the `app.` action.
+    pub name: &'static str,
+    /// Shown in the settings.
+    pub label: &'static str,
+    pub default_accels: &'static [&'static str],
+}
+
+/// Every action that can have a shortcut, in the order they are listed in the settings.
+pub const ACTIONS: &[KeyAction] = &[
+    KeyAction { name: "open_file_chooser", label: "Open file", default_accels: &["<Primary>O"] },
+    KeyAction { name: "quit", label: "Quit", default_accels: &["<Primary>Q"] },
+    KeyAction { name: "page_left", label: "Page left", default_accels: &["Left"] },
+    KeyAction { name: "page_right", label: "Page right", default_accels: &["Right"] },
+    KeyAction { name: "page", label: "Go to page", default_accels: &["G"] },
+    KeyAction { name: "scroll_line_up", label: "Scroll up", default_accels: &["Up"] },
+    KeyAction { name: "scroll_line_down", label: "Scroll down", default_accels: &["Down"] },
+    KeyAction { name: "scroll_up_amount", label: "Scroll up a screen", default_accels: &["Page_Up"] },
+    KeyAction { name: "scroll_down_amount", label: "Scroll down a screen", default_accels: &["Page_Down", "space"] },
+    KeyAction { name: "scroll_up", label: "Scroll to the top", default_accels: &["Home"] },
+    KeyAction { name: "scroll_down", label: "Scroll to the bottom", default_accels: &["End"] },
+    KeyAction { name: "toggle_paginated", label: "Paginated", default_accels: &["P"] },
+    KeyAction { name: "toggle_continuous", label: "Continuous scrolling", default_accels: &[] },
+    KeyAction { name: "auto_scroll", label: "Auto scroll", default_accels: &["section", "F8"] },
+    KeyAction { name: "find", label: "Find in chapter", default_accels: &["<Primary>F"] },
+    KeyAction { name: "toggle_search", label: "Search the book", default_accels: &["<Primary><Shift>F"] },
+    KeyAction { name: "toggle_toc", label: "Table of contents", default_accels: &["C"] },
+    KeyAction { name: "toggle_recent", label: "Library", default_accels: &["R"] },
+    KeyAction { name: "toggle_bookmarks", label: "Bookmarks", default_accels: &["B"] },
+    KeyAction { name: "toggle_bookmark", label: "Add or remove a bookmark", default_accels: &["<Primary>D"] },
+    KeyAction { name: "toggle_highlights", label: "Highlights", default_accels: &["H"] },
+    KeyAction { name: "toggle_fullscreen", label: "Fullscreen", default_accels: &["F11", "F"] },
+    KeyAction { name: "comic_toggle_fit", label: "Comic: fit width / height", default_accels: &["W"] },
+    KeyAction { name: "comic_zoom_in", label: "Comic: zoom in", default_accels: &["<Primary>plus", "<Primary>equal", "<Primary>KP_Add"] },
+    KeyAction { name: "comic_zoom_out", label: "Comic: zoom out", default_accels: &["<Primary>minus", "<Primary>KP_Subtract"] },
+    KeyAction { name: "comic_zoom_reset", label: "Comic: reset zoom", default_accels: &["<Primary>0"] },
+    KeyAction { name: "toggle_right_to_left", label: "Comic: right to left", default_accels: &[] },
+    KeyAction { name: "export_library", label: "Export library", default_accels: &[] },
+    KeyAction { name: "import_library", label: "Import library", default_accels: &[] },
+    KeyAction { name: "open_settings", label: "Settings", default_accels: &["F2"] },
+    KeyAction { name: "open_about", label: "About", default_accels: &["F1"] },
+];
+
+/// The action called `name`.
+pub fn action(name: &str) -> Option<&'static KeyAction> {
+    ACTIONS.iter().find(|action| action.name == name)
+}
+
+/// The `accel` in the form GTK gives it, so the same shortcut written differently
+/// (`<Control>d`, `<Primary>D`) compares equal. `None` if it's not a valid shortcut.
+pub fn normalize(accel: &str) -> Option<String> {
+    let (key, modifiers) = gtk::accelerator_parse(accel);
+    if key == 0 {
+        return None;
+    }
+
+    gtk::accelerator_name(key, modifiers).map(|name| name.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    /// Shortcuts of every action in `ACTIONS`, in the format of `gtk::accelerator_parse`.
+    bindings: HashMap<&'static str, Vec<String>>,
+    path: PathBuf,
+}
+
+impl Keymap {
+    /// The default shortcuts.
+    pub fn new() -> Keymap {
+        let bindings = ACTIONS
+            .iter()
+            .map(|action| (action.name, defaults(action)))
+            .collect();
+
+        Keymap {
+            bindings,
+            path: paths::path(Location::Config, KEYMAP_NAME),
+        }
+    }
+
+    /// Open the keymap file, the actions that are not in it keep the default shortcuts.
+    /// Lines that cannot be understood are skipped and logged.
+    pub fn open() -> Result<Keymap, StorageError> {
+        let mut keymap = Keymap::new();
+        if !keymap.path.exists() {
+            return Ok(keymap);
+        }
+
+        let content = fs::read_to_string(&keymap.path)?;
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, accels) = match line.split_once('=') {
+                Some((name, accels)) => (name.trim(), accels),
+                None => {
+                    warn!("{}:{}: expected `action = shortcut, shortcut`", KEYMAP_NAME, number + 1);
+                    continue;
+                }
+            };
+            let action = match action(name) {
+                Some(action) => action,
+                None => {
+                    warn!("{}:{}: unknown action {:?}", KEYMAP_NAME, number + 1, name);
+                    continue;
+                }
+            };
+
+            let mut valid = vec![];
+            for accel in accels.split(',').map(str::trim).filter(|accel| !accel.is_empty()) {
+                match normalize(accel) {
+                    Some(accel) => valid.push(accel),
+                    None => warn!("{}:{}: invalid shortcut {:?}", KEYMAP_NAME, number + 1, accel),
+                }
+            }
+            keymap.bindings.insert(action.name, valid);
+        }
+
+        Ok(keymap)
+    }
+
+    pub fn write(&self) -> Result<(), StorageError> {
+        let mut content = String::from(
+            "# Keyboard shortcuts of Bad Reader, one action per line: action = shortcut, shortcut\n\
+             # Shortcuts are written like <Primary>O, <Shift>Left or F11. Nothing after `=` removes the shortcuts.\n\
+             # Actions that are not listed keep their default shortcuts.\n"
+        );
+        for action in ACTIONS {
+            let _ = writeln!(content, "{} = {}", action.name, self.accels(action.name).join(", "));
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, content)?;
+
+        Ok(())
+    }
+
+    pub fn accels(&self, action: &str) -> &[String] {
+        self.bindings.get(action).map(|accels| accels.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn is_default(&self, action: &KeyAction) -> bool {
+        self.accels(action.name) == defaults(action).as_slice()
+    }
+
+    /// Replace the shortcuts of the `action`.
+    pub fn set_accels(&mut self, action: &str, accels: Vec<String>) {
+        if let Some(action) = self::action(action) {
+            self.bindings.insert(action.name, accels);
+        }
+    }
+
+    /// Remove the `accel` from every action.
+    pub fn unbind(&mut self, accel: &str) {
+        for accels in self.bindings.values_mut() {
+            accels.retain(|other| other != accel);
+        }
+    }
+
+    pub fn reset(&mut self, action: &str) {
+        if let Some(action) = self::action(action) {
+            self.bindings.insert(action.name, defaults(action));
+        }
+    }
+
+    pub fn reset_all(&mut self) {
+        self.bindings = Keymap::new().bindings;
+    }
+
+    /// The action other than `action` that already has the `accel`.
+    pub fn conflict(&self, action: &str, accel: &str) -> Option<&'static KeyAction> {
+        ACTIONS
+            .iter()
+            .filter(|other| other.name != action)
+            .find(|other| self.accels(other.name).iter().any(|other_accel| other_accel == accel))
+    }
+
+    /// Bind the shortcuts to the actions of the `app`.
+    pub fn apply(&self, app: &gtk::Application) {
+        for action in ACTIONS {
+            let accels: Vec<&str> = self.accels(action.name).iter().map(String::as_str).collect();
+            app.set_accels_for_action(&format!("app.{}", action.name), &accels);
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::new()
+    }
+}
+
+/// The default shortcuts of the `action` in the normalized form.
+fn defaults(action: &KeyAction) -> Vec<String> {
+    action.default_accels
+        .iter()
+        .filter_map(|accel| normalize(accel))
+        .collect()
+}
@@ -0,0 +1,171 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use anyhow::anyhow;
+use gdk_pixbuf::{Pixbuf, InterpType};
+use crate::app::book::Book;
+use crate::app::epub_book::EpubBook;
+use crate::app::database::{BookIdentity, BookMetadata, DatabaseRow};
+use crate::app::paths::{self, Location};
+use crate::app::{fb2_book, text_book};
+use crate::app::comic_book::ComicBook;
+use crate::app::utils::hash_file;
+
+
+/// Cover thumbnails are scaled to fit in this size.
+const THUMBNAIL_WIDTH: i32 = 120;
+const THUMBNAIL_HEIGHT: i32 = 180;
+
+/// Metadata of a book found by `scan_missing_metadata`.
+pub struct ScannedBook {
+    pub file: PathBuf,
+    pub identity: BookIdentity,
+    pub metadata: BookMetadata,
+}
+
+/// The kinds of books that can be opened. The other formats are converted to an epub in the cache.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BookFormat {
+    Epub,
+    Text,
+    FictionBook,
+    /// `.cbz`, shown as images instead of text.
+    Comic,
+}
+
+impl BookFormat {
+    /// Format of the book by the file extension, `None` if it's not a book.
+    pub fn from_path(file: &Path) -> Option<BookFormat> {
+        let name = file.file_name()?.to_string_lossy().to_lowercase();
+        
+        if name.ends_with(".epub") {
+            Some(BookFormat::Epub)
+        } else if name.ends_with(".txt") {
+            Some(BookFormat::Text)
+        } else if name.ends_with(".fb2") || name.ends_with(".fb2.zip") {
+            Some(BookFormat::FictionBook)
+        } else if name.ends_with(".cbz") {
+            Some(BookFormat::Comic)
+        } else {
+            None
+        }
+    }
+}
+
+/// Open the book in `file`. Books in other formats are opened from the epub they are converted to.
+/// Comic books are not text and are opened with `ComicBook::open` instead.
+pub fn open_book(file: &Path) -> anyhow::Result<Box<dyn Book>> {
+    let path = match BookFormat::from_path(file) {
+        Some(BookFormat::Text) => text_book::converted_epub(file)?,
+        Some(BookFormat::FictionBook) => fb2_book::converted_epub(file)?,
+        Some(BookFormat::Comic) => return Err(anyhow!("{:?} is a comic book", file)),
+        Some(BookFormat::Epub) | None => file.to_path_buf(),
+    };
+    
+    Ok(Box::new(EpubBook::open(&path)?))
+}
+
+/// Identity of the book, from its metadata and the file content.
+pub fn book_identity(book: &dyn Book, file: &Path) -> BookIdentity {
+    let content_hash = hash_file(file).unwrap_or_else(|e| {
+        error!("Cannot hash file: {:?}. Error: {}", file, e);
+        0
+    });
+    
+    BookIdentity {
+        identifier: book.info().identifier,
+        content_hash
+    }
+}
+
+/// Read the library details from the book and save the cover thumbnail to the cache.
+/// This opens the cover image so it should be called outside of the main thread.
+pub fn read_metadata(book: &mut dyn Book, identity: &BookIdentity) -> BookMetadata {
+    let info = book.info();
+    
+    BookMetadata {
+        title: info.title,
+        author: info.author,
+        num_pages: book.num_sections(),
+        thumbnail: cache_thumbnail(identity, || book.cover()),
+    }
+}
+
+/// Save the cover of the book as a thumbnail, unless it has been cached already.
+/// `cover` is only called when the thumbnail isn't in the cache.
+pub fn cache_thumbnail(identity: &BookIdentity, cover: impl FnOnce() -> Option<Vec<u8>>) -> Option<PathBuf> {
+    let path = paths::path(Location::Cache, &format!("thumbnails/{:016x}.png", identity.content_hash));
+    if path.exists() {
+        return Some(path);
+    }
+    
+    let cover = cover()?;
+    let pixbuf = match Pixbuf::from_read(Cursor::new(cover)) {
+        Ok(pixbuf) => pixbuf,
+        Err(e) => {
+            warn!("Cannot load the cover image. {}", e);
+            return None;
+        }
+    };
+    
+    let scale = f64::min(
+        THUMBNAIL_WIDTH as f64 / pixbuf.width() as f64,
+        THUMBNAIL_HEIGHT as f64 / pixbuf.height() as f64
+    );
+    let width = ((pixbuf.width() as f64 * scale).round() as i32).max(1);
+    let height = ((pixbuf.height() as f64 * scale).round() as i32).max(1);
+    let thumbnail = pixbuf.scale_simple(width, height, InterpType::Bilinear)?;
+    
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    match thumbnail.savev(&path, "png", &[]) {
+        Ok(_) => Some(path),
+        Err(e) => {
+            error!("Cannot save the thumbnail {:?}. {}", path, e);
+            None
+        }
+    }
+}
+
+/// Used in non-main thread.
+/// Opens the books of the rows that don't have metadata yet and sends it to the main thread,
+/// after that the library doesn't need to open the books again.
+pub fn scan_missing_metadata(rows: Vec<DatabaseRow>, tx: glib::Sender<ScannedBook>) {
+    for row in rows.into_iter().filter(|row| row.metadata.is_none()) {
+        let scanned = if BookFormat::from_path(&row.file) == Some(BookFormat::Comic) {
+            match ComicBook::open(&row.file) {
+                Ok(comic) => ScannedBook {
+                    file: row.file,
+                    identity: comic.identity,
+                    metadata: comic.metadata
+                },
+                Err(e) => {
+                    warn!("Cannot open {:?} for the library. {}", row.file, e);
+                    continue;
+                }
+            }
+        } else {
+            let mut book = match open_book(&row.file) {
+                Ok(book) => book,
+                Err(e) => {
+                    warn!("Cannot open {:?} for the library. {}", row.file, e);
+                    continue;
+                }
+            };
+            
+            let identity = book_identity(book.as_ref(), &row.file);
+            let metadata = read_metadata(book.as_mut(), &identity);
+            ScannedBook {
+                file: row.file,
+                identity,
+                metadata
+            }
+        };
+        
+        if tx.send(scanned).is_err() {
+            error!("Could not send the library metadata from another thread!");
+            return;
+        }
+    }
+}
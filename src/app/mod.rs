@@ -5,16 +5,31 @@ use gtk::prelude::*;
 use crate::app::window_state::WindowState;
 use crate::ui;
 use crate::appop::AppOp;
-use clap::ArgMatches;
+use std::ffi::OsString;
 use std::path::PathBuf;
 use crate::ui::Theme;
 use crate::app::utils::Resources;
 
 pub mod window_state;
+pub mod archive;
+pub mod book;
+pub mod cli;
+pub mod comic_book;
 pub mod error;
 pub mod database;
+pub mod epub_book;
+pub mod epub_writer;
+pub mod fb2_book;
 pub mod globals;
+pub mod keymap;
+pub mod library;
+pub mod search;
+pub mod paths;
+pub mod profile_lock;
+pub mod progress;
 pub mod settings;
+pub mod storage;
+pub mod text_book;
 pub mod utils;
 
 
@@ -69,7 +84,11 @@ fn new(gtk_app: gtk::Application) -> AppRuntime {
         // Do this first because `toggle_fullscreen` does visibility things.
         state.ui.main_window.show_all();
         // Load window states from the file or use defaults.
-        if let Some(window_state) = window_state.unwrap() {
+        let window_state = window_state.unwrap_or_else(|err| {
+            state.storage_errors.push(err.to_string());
+            None
+        });
+        if let Some(window_state) = window_state {
             state.ui.main_window.set_default_size(window_state.width, window_state.height);
             if window_state.is_fullscreen {
                 state.ui.toggle_fullscreen();
@@ -86,9 +105,14 @@ fn new(gtk_app: gtk::Application) -> AppRuntime {
         state.ui.set_font_size(settings.general.font_size);
         state.ui.set_font_family(&settings.general.font_family);
         state.ui.set_custom_colors(settings.general.use_custom_color, &settings.general.background_color, &settings.general.text_color);
+        state.ui.set_paginated(settings.general.paginated);
+        state.ui.continuous = settings.general.continuous;
         
         state.ui.connect(state.app_runtime.clone());
         state.ui.settings_dialog.connect(&state.ui.builder, state.app_runtime.clone());
+        state.ui.find_bar.connect(&state.ui.builder, state.app_runtime.clone());
+        state.ui.connect_shortcuts(state.app_runtime.clone());
+        state.ui.update_shortcuts(state.app_runtime.clone(), &state.keymap);
         state.ui.update_recent(state.app_runtime.clone(), &state.db.read().rows.clone().unwrap());
 
         globals::new(state);
@@ -99,37 +123,33 @@ fn new(gtk_app: gtk::Application) -> AppRuntime {
     app_runtime
 }
 
-pub fn on_startup(gtk_app: &gtk::Application, matches: &ArgMatches) {
+pub fn on_startup(gtk_app: &gtk::Application) {
     let app_runtime = new(gtk_app.clone());
     
     debug!("app::on_startup");
     
-    let file = matches.value_of("file");
-    let page = matches.value_of("page");
-    
-    let filepath = file.map(PathBuf::from);
-    let num = page.map(|page| page.parse::<usize>().unwrap_or(1));
-    
     gtk_app.connect_activate(clone!(@strong app_runtime => move |_| {
-        let filepath = filepath.clone();
-        let num = num;
-        
         app_runtime.update_state_with(move |state| {
             debug!("app::on_startup -> connect_activate");
             on_activate(&state.ui);
-        
-            // Open the file from path if it was supplied with CLI.
-            if let Some(filepath) = filepath {
-                // Since left revealer is open by default, hide it when opening via CLI.
-                // Simply using the `set_reveal_child()` won't work because of the slide animation
-                // lag so it's half visible when the file is loading.
-                state.ui.left_revealer.set_visible(false);
-                // Open the file
-                state.open_file_from_path(filepath, num);
-            }
         });
     }));
     
+    // The arguments of this instance and of the ones started while this is running.
+    gtk_app.connect_command_line(clone!(@strong app_runtime => move |gtk_app, command_line| {
+        on_command_line(&app_runtime, gtk_app, command_line)
+    }));
+    
+    // Files opened from a file manager or another program.
+    gtk_app.connect_open(clone!(@strong app_runtime => move |gtk_app, files, _| {
+        gtk_app.activate();
+        
+        match files.first().and_then(|file| file.path()) {
+            Some(filepath) => open_file(&app_runtime, filepath, None),
+            None => warn!("Only local files can be opened: {:?}", files.first().map(|file| file.uri())),
+        }
+    }));
+    
     app_runtime.update_state_with(|state| {
         state.init();
     });
@@ -141,6 +161,63 @@ pub fn on_startup(gtk_app: &gtk::Application, matches: &ArgMatches) {
     });
 }
 
+/// Show the window and open the file in the `command_line` arguments, if there is one.
+/// Relative paths are resolved against the directory the command was run in.
+///
+/// GLib takes the options added with `add_main_option` out of the arguments into the options,
+/// only the book given as a plain argument is left in the arguments.
+fn on_command_line(app_runtime: &AppRuntime, gtk_app: &gtk::Application, command_line: &gio::ApplicationCommandLine) -> i32 {
+    gtk_app.activate();
+    
+    let options = command_line.options_dict();
+    let file = options
+        .lookup_value("file", glib::VariantTy::new("ay").ok())
+        .map(|value| filename_option(value.data()))
+        .or_else(|| command_line.arguments().into_iter().nth(1));
+    let num = options
+        .lookup_value("page", glib::VariantTy::new("i").ok())
+        .and_then(|value| value.get::<i32>())
+        .map(|page| page.max(0) as usize);
+    
+    let filepath = file.as_ref().and_then(|file| command_line.create_file_for_arg(file).path());
+    
+    if let Some(filepath) = filepath {
+        open_file(app_runtime, filepath, num);
+    } else if let Some(file) = file {
+        warn!("Only local files can be opened: {:?}", file);
+    }
+    
+    0
+}
+
+/// A filename option is a null terminated byte string, in the encoding of the file system
+/// on Unix and in UTF-8 on Windows.
+fn filename_option(bytes: &[u8]) -> OsString {
+    let bytes = bytes.split(|byte| *byte == 0).next().unwrap_or_default();
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        std::ffi::OsStr::from_bytes(bytes).to_os_string()
+    }
+    #[cfg(not(unix))]
+    {
+        OsString::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Open the file from path that was given on the command line or by another program.
+fn open_file(app_runtime: &AppRuntime, filepath: PathBuf, num: Option<usize>) {
+    app_runtime.update_state_with(move |state| {
+        // Since left revealer is open by default, hide it when opening via CLI.
+        // Simply using the `set_reveal_child()` won't work because of the slide animation
+        // lag so it's half visible when the file is loading.
+        state.ui.left_revealer.set_visible(false);
+        // Open the file
+        state.open_file_from_path(filepath, num);
+    });
+}
+
 
 fn on_activate(ui: &ui::UI) {
     ui.main_window.show();
@@ -0,0 +1,179 @@
+use std::env;
+use std::env::current_exe;
+use std::fs;
+use std::path::{Path, PathBuf};
+use parking_lot::{RwLock, const_rwlock};
+use crate::app::database::DB_FILE;
+use crate::app::{settings, window_state};
+
+
+/// Directory name used under the XDG base directories.
+const APP_DIR: &str = "bad-reader";
+/// If this file is next to the executable the files are kept there, like with `--portable`.
+pub const PORTABLE_MARKER: &str = "bad-reader.portable";
+
+static PATHS: RwLock<Option<Paths>> = const_rwlock(None);
+
+/// Kinds of files the app saves, following the XDG base directory spec.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Location {
+    /// Settings. `$XDG_CONFIG_HOME/bad-reader`
+    Config,
+    /// Reading progress. `$XDG_DATA_HOME/bad-reader`
+    Data,
+    /// Window state and the log. `$XDG_STATE_HOME/bad-reader`
+    State,
+    /// Things that can be rebuilt. `$XDG_CACHE_HOME/bad-reader`
+    Cache,
+}
+
+#[derive(Debug, Clone)]
+struct Paths {
+    config: PathBuf,
+    data: PathBuf,
+    state: PathBuf,
+    cache: PathBuf,
+    /// Everything is in one directory chosen by the user, nothing to migrate.
+    is_single_dir: bool,
+}
+
+impl Paths {
+    fn xdg() -> Paths {
+        Paths {
+            config: glib::user_config_dir().join(APP_DIR),
+            data: glib::user_data_dir().join(APP_DIR),
+            state: user_state_dir().join(APP_DIR),
+            cache: glib::user_cache_dir().join(APP_DIR),
+            is_single_dir: false,
+        }
+    }
+    
+    fn single_dir(dir: PathBuf) -> Paths {
+        Paths {
+            config: dir.clone(),
+            data: dir.clone(),
+            state: dir.clone(),
+            cache: dir.join("cache"),
+            is_single_dir: true,
+        }
+    }
+}
+
+/// Decide where the files go. Has to be called before anything is read or written.
+///
+/// `config_dir` puts everything in that directory, e.g. for a separate profile.
+/// Portable mode, from `portable` or the marker file, keeps the files next to the executable.
+pub fn init(portable: bool, config_dir: Option<PathBuf>) {
+    let paths = if let Some(dir) = config_dir {
+        Paths::single_dir(dir)
+    } else if portable || executable_dir().join(PORTABLE_MARKER).exists() {
+        Paths::single_dir(executable_dir())
+    } else {
+        Paths::xdg()
+    };
+    
+    for dir in [&paths.config, &paths.data, &paths.state, &paths.cache].iter() {
+        // Logging isn't set up yet since the log file goes in these directories.
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("Cannot create directory {:?}. {}", dir, e);
+        }
+    }
+    
+    *PATHS.write() = Some(paths);
+}
+
+/// Full path of the `file` in the directory of the `location`.
+pub fn path(location: Location, file: &str) -> PathBuf {
+    let paths = PATHS.read();
+    let paths = paths.as_ref().expect("paths::init has not been called");
+    
+    let dir = match location {
+        Location::Config => &paths.config,
+        Location::Data => &paths.data,
+        Location::State => &paths.state,
+        Location::Cache => &paths.cache,
+    };
+    
+    dir.join(file)
+}
+
+/// The files are in one directory from `--config-dir` or the portable mode
+/// instead of the XDG directories shared by the readers of the user.
+pub fn is_single_dir() -> bool {
+    PATHS.read().as_ref().map_or(false, |paths| paths.is_single_dir)
+}
+
+/// Move the files that older versions saved next to the executable to their XDG locations.
+/// Files that already exist in the new location are left alone.
+pub fn migrate_legacy_files() {
+    let is_single_dir = PATHS.read().as_ref().map_or(true, |paths| paths.is_single_dir);
+    if is_single_dir {
+        return;
+    }
+    
+    let legacy_dir = executable_dir();
+    let files = [
+        (Location::Config, settings::CONFIG_NAME),
+        (Location::Data, DB_FILE),
+        (Location::State, window_state::CONFIG_NAME),
+    ];
+    
+    for (location, file) in files.iter() {
+        let old_path = legacy_dir.join(file);
+        let new_path = path(*location, file);
+        if !old_path.exists() || new_path.exists() {
+            continue;
+        }
+        
+        match move_file(&old_path, &new_path) {
+            Ok(_) => info!("Moved {:?} to {:?}", old_path, new_path),
+            Err(e) => error!("Cannot move {:?} to {:?}. {}", old_path, new_path, e),
+        }
+    }
+}
+
+/// Rename the file, or copy it if it's on another file system.
+/// The old file is left in place if it cannot be removed, e.g. in a read-only install.
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    
+    fs::copy(from, to)?;
+    if let Err(e) = fs::remove_file(from) {
+        warn!("Cannot remove {:?} after copying it. {}", from, e);
+    }
+    
+    Ok(())
+}
+
+/// `$XDG_STATE_HOME` isn't available from glib yet.
+fn user_state_dir() -> PathBuf {
+    match env::var_os("XDG_STATE_HOME").map(PathBuf::from) {
+        // The spec says relative paths are invalid and should be ignored.
+        Some(dir) if dir.is_absolute() => dir,
+        _ if cfg!(windows) => glib::user_data_dir(),
+        _ => glib::home_dir().join(".local").join("state"),
+    }
+}
+
+/// The directory of the executable, where the files were kept before.
+/// In debug builds it's the project root instead of `target/debug`.
+pub fn executable_dir() -> PathBuf {
+    let mut dir = match current_exe() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Could not get current_exe path. {:?}", e);
+            return PathBuf::new();
+        }
+    };
+    
+    // The executable itself
+    dir.pop();
+    if cfg!(debug_assertions) {
+        dir.pop();
+        dir.pop();
+    }
+    
+    dir
+}
@@ -0,0 +1,41 @@
+//! A lock on the files of the profile, held by the reader for as long as it runs.
+//!
+//! The reader keeps the library in memory and saves it over the file, so the commands
+//! that write the library refuse to run while the profile is locked.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use crate::app::paths::{self, Location};
+
+
+pub const LOCK_FILE: &str = "bad-reader.lock";
+
+/// The lock is released when this is dropped, or by the system when the reader exits or crashes.
+pub struct ProfileLock {
+    _file: File,
+}
+
+impl ProfileLock {
+    /// Lock the profile, `None` if another reader already has it locked.
+    pub fn acquire() -> io::Result<Option<ProfileLock>> {
+        let file = open_lock_file()?;
+        
+        match file.try_lock() {
+            Ok(()) => Ok(Some(ProfileLock { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e),
+        }
+    }
+}
+
+/// A reader is running with this profile.
+pub fn is_locked() -> io::Result<bool> {
+    Ok(ProfileLock::acquire()?.is_none())
+}
+
+fn open_lock_file() -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(paths::path(Location::Data, LOCK_FILE))
+}
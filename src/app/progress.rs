@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use crate::app::library;
+use crate::ui::renderer::chapter_text;
+
+
+/// Shortest time the reading speed is measured over.
+const MIN_SAMPLE_TIME: Duration = Duration::from_secs(30);
+/// Longer than this without moving on isn't reading anymore.
+const MAX_SAMPLE_TIME: Duration = Duration::from_secs(5 * 60);
+/// Faster than this, in chars per minute, is skimming or jumping around.
+const MAX_READING_SPEED: f64 = 5_000.0;
+/// How much a new measurement changes the reading speed.
+const SAMPLE_WEIGHT: f64 = 0.2;
+
+/// Lengths of the chapters of a book, measured by `measure_book`.
+#[derive(Debug, Clone)]
+pub struct ChapterLengths {
+    pub file: PathBuf,
+    /// Chars in every spine item. Measured from the plain text of the renderer, which can be off
+    /// after images, and replaced with the length in the reader once the chapter is rendered.
+    pub lengths: Vec<usize>,
+}
+
+impl ChapterLengths {
+    pub fn total(&self) -> usize {
+        self.lengths.iter().sum()
+    }
+
+    /// Set the `length` of the chapter `page` as it was rendered in the reader.
+    pub fn record(&mut self, page: usize, length: usize) {
+        if let Some(measured) = self.lengths.get_mut(page) {
+            *measured = length;
+        }
+    }
+
+    /// Book-wide char position of the char `offset` in the chapter `page`.
+    pub fn position(&self, page: usize, offset: usize) -> usize {
+        let before: usize = self.lengths.iter().take(page).sum();
+
+        before + offset
+    }
+
+    /// Part of the book before the char `offset` in the chapter `page`, from 0 to 1.
+    pub fn progress(&self, page: usize, offset: usize) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => (self.position(page, offset) as f64 / total as f64).min(1.0),
+        }
+    }
+
+    /// Chars from the char `offset` to the end of the chapter `page`.
+    pub fn chapter_left(&self, page: usize, offset: usize) -> usize {
+        self.lengths.get(page).map_or(0, |length| length.saturating_sub(offset))
+    }
+
+    /// Chars from the char `offset` in the chapter `page` to the end of the book.
+    pub fn book_left(&self, page: usize, offset: usize) -> usize {
+        self.total().saturating_sub(self.position(page, offset))
+    }
+}
+
+/// Measure the text of every chapter of the book in `file` and send the lengths.
+/// Runs outside of the main thread.
+pub fn measure_book(file: PathBuf, tx: glib::Sender<ChapterLengths>) {
+    let mut book = match library::open_book(&file) {
+        Ok(book) => book,
+        Err(e) => {
+            error!("Cannot open the book for measuring: {:?}. Error: {}", file, e);
+            return;
+        }
+    };
+
+    let lengths = (0..book.num_sections())
+        .map(|page| match book.section_content(page) {
+            Ok(document) => chapter_text(&document).chars().count(),
+            Err(_) => 0,
+        })
+        .collect();
+
+    let _ = tx.send(ChapterLengths { file, lengths });
+}
+
+/// Measures the reading speed from how fast the position in the book moves forward.
+#[derive(Debug, Default)]
+pub struct ReadingClock {
+    /// When the measuring started and the book-wide char position then.
+    start: Option<(Instant, usize)>,
+}
+
+impl ReadingClock {
+    /// Note that the reader is at the book-wide char `position`. Returns the reading speed in
+    /// chars per minute once it has been measured for long enough, `None` until then or if
+    /// the position moved in a way that isn't reading.
+    pub fn update(&mut self, position: usize) -> Option<f64> {
+        let now = Instant::now();
+        let (start_time, start_position) = match self.start {
+            Some(start) if position >= start.1 => start,
+            // Just started or went back, measure from here
+            _ => {
+                self.start = Some((now, position));
+                return None;
+            }
+        };
+
+        let elapsed = now - start_time;
+        if elapsed < MIN_SAMPLE_TIME {
+            return None;
+        }
+
+        self.start = Some((now, position));
+        let speed = (position - start_position) as f64 / (elapsed.as_secs_f64() / 60.0);
+        if elapsed > MAX_SAMPLE_TIME || speed > MAX_READING_SPEED || position == start_position {
+            return None;
+        }
+
+        Some(speed)
+    }
+
+    /// Start measuring again, e.g. in another book.
+    pub fn reset(&mut self) {
+        self.start = None;
+    }
+}
+
+/// The reading `speed` with the new `measured` speed taken into account.
+pub fn update_speed(speed: Option<f64>, measured: f64) -> f64 {
+    match speed {
+        Some(speed) => speed + SAMPLE_WEIGHT * (measured - speed),
+        None => measured,
+    }
+}
+
+/// Time it takes to read `chars` at the `speed` in chars per minute, e.g. "1 h 5 min".
+pub fn format_reading_time(chars: usize, speed: f64) -> String {
+    let minutes = (chars as f64 / speed).ceil() as u64;
+
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{} min", minutes),
+        (hours, 0) => format!("{} h", hours),
+        (hours, minutes) => format!("{} h {} min", hours, minutes),
+    }
+}
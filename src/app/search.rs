@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::app::library;
+use crate::ui::renderer::{chapter_text, IMAGE_CHAR};
+
+
+/// Characters of the chapter text shown around the match in the results.
+const SNIPPET_BEFORE: usize = 40;
+const SNIPPET_AFTER: usize = 80;
+
+/// A match of the book search.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    /// Spine index of the chapter.
+    pub page: usize,
+    pub chapter_title: String,
+    /// The match and some text around it.
+    pub snippet: String,
+    /// Which match in the chapter this is, counted from zero.
+    pub occurrence: usize,
+}
+
+/// Sent from the search thread to the main loop.
+#[derive(Debug)]
+pub enum SearchEvent {
+    Hit(u64, SearchHit),
+    /// The search with the id has gone through the whole book.
+    Finished(u64),
+}
+
+/// Search every chapter of the book for `query` and send the matches as they're found.
+/// Stops early when `cancel` is set. Runs outside of the main thread.
+pub fn search_book(file: PathBuf, query: String, search_id: u64, cancel: Arc<AtomicBool>, tx: glib::Sender<SearchEvent>) {
+    let mut book = match library::open_book(&file) {
+        Ok(book) => book,
+        Err(e) => {
+            error!("Cannot open the book for searching: {:?}. Error: {}", file, e);
+            let _ = tx.send(SearchEvent::Finished(search_id));
+            return;
+        }
+    };
+
+    let query: Vec<char> = query.chars().collect();
+
+    for page in 0..book.num_sections() {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let document = match book.section_content(page) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+
+        let chapter_title = book.sections()[page].title_or_file_name();
+
+        let text: Vec<char> = chapter_text(&document).chars().collect();
+        for (occurrence, start) in find_all(&text, &query).into_iter().enumerate() {
+            let hit = SearchHit {
+                page,
+                chapter_title: chapter_title.clone(),
+                snippet: snippet(&text, start, query.len()),
+                occurrence,
+            };
+
+            if tx.send(SearchEvent::Hit(search_id, hit)).is_err() {
+                return;
+            }
+        }
+    }
+
+    let _ = tx.send(SearchEvent::Finished(search_id));
+}
+
+/// Char indices of the non overlapping, case insensitive matches of `query` in `text`.
+fn find_all(text: &[char], query: &[char]) -> Vec<usize> {
+    let mut found = vec![];
+    if query.is_empty() || query.len() > text.len() {
+        return found;
+    }
+
+    let mut i = 0;
+    while i + query.len() <= text.len() {
+        let is_match = text[i..i + query.len()]
+            .iter()
+            .zip(query)
+            .all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()));
+
+        if is_match {
+            found.push(i);
+            i += query.len();
+        } else {
+            i += 1;
+        }
+    }
+
+    found
+}
+
+/// The match at `start` with some text around it on a single line.
+fn snippet(text: &[char], start: usize, len: usize) -> String {
+    let from = start.saturating_sub(SNIPPET_BEFORE);
+    let to = (start + len + SNIPPET_AFTER).min(text.len());
+
+    let mut snippet: String = text[from..to]
+        .iter()
+        .filter(|c| **c != IMAGE_CHAR)
+        .map(|c| if *c == '\n' { ' ' } else { *c })
+        .collect();
+    snippet = snippet.trim().to_string();
+
+    if from > 0 {
+        snippet.insert(0, '…');
+    }
+    if to < text.len() {
+        snippet.push('…');
+    }
+
+    snippet
+}
@@ -1,54 +1,105 @@
-use crate::app::error::SettingsError;
-use std::path::{Path, PathBuf};
-use std::io::{BufWriter, BufReader};
-use std::fs::File;
+use crate::app::error::StorageError;
+use crate::app::storage::{self, Versioned};
+use std::path::PathBuf;
 use std::fmt;
-use anyhow::{Context};
-use bincode::{deserialize_from, serialize_into};
 use serde::{Deserialize, Serialize};
 use crate::ui::{Theme};
-use crate::app::utils::working_dir;
+use crate::app::paths::{self, Location};
 use std::str::FromStr;
 
 
-static CONFIG_NAME: &str = "bad-reader.conf";
+pub static CONFIG_NAME: &str = "bad-reader.conf";
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(rename = "settings")]
 pub struct Settings {
     pub general: GeneralSettings,
     pub file: FileSettings,
+    /// Where the settings are saved, set when opening.
+    #[serde(skip)]
     path: PathBuf,
 }
 
 impl Settings {
-    pub fn open() -> Result<Self, SettingsError> {
-        let path = Path::new(&working_dir(CONFIG_NAME)).to_path_buf();
-        if path.as_path().exists() {
-            let f = File::open(&path)
-                .context(SettingsError::ReadFromDisk)?;
-            if let Ok(settings) = deserialize_from(BufReader::new(&f))
-                .context(SettingsError::Unknown) {
-                return Ok(settings);
-            }
+    /// Default settings that will be saved to the config file.
+    pub fn new() -> Settings {
+        Settings {
+            general: GeneralSettings::new(),
+            file: FileSettings::new(),
+            path: paths::path(Location::Config, CONFIG_NAME)
+        }
+    }
+    
+    /// Open the settings from the config file, or create the file with
+    /// the default settings if it doesn't exist.
+    ///
+    /// An unreadable config is kept as a backup instead of being overwritten.
+    pub fn open() -> Result<Self, StorageError> {
+        let path = paths::path(Location::Config, CONFIG_NAME);
+        if let Some(mut settings) = storage::load::<Settings>(&path)? {
+            settings.path = path;
+            return Ok(settings);
         }
         
         // If file doesn't exist then set then create it
-        let settings = Settings {
-            general: GeneralSettings::new(),
-            file: FileSettings::new(),
-            path
-        };
+        let settings = Settings::new();
+        settings.write()?;
+        Ok(settings)
+    }
+    
+    /// Open the settings without creating the config file or moving it aside when it cannot be read.
+    /// A missing config file gives the default settings.
+    pub fn open_read_only() -> Result<Self, StorageError> {
+        let path = paths::path(Location::Config, CONFIG_NAME);
+        let mut settings = storage::load_read_only::<Settings>(&path)?.unwrap_or_else(Settings::new);
+        settings.path = path;
         
-        settings.write().context(SettingsError::WriteToDisk)?;
         Ok(settings)
     }
     
-    pub fn write(&self) -> Result<(), SettingsError> {
-        let mut f = BufWriter::new(File::create(&self.path).unwrap());
-        serialize_into(&mut f, self)
-            .context(SettingsError::WriteToDisk)?;
-        Ok(())
+    pub fn write(&self) -> Result<(), StorageError> {
+        storage::save(&self.path, self)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::new()
+    }
+}
+
+impl Versioned for Settings {
+    const VERSION: u32 = 3;
+    
+    fn migrate(version: u32, bytes: &[u8]) -> bincode::Result<Self> {
+        match version {
+            // 1.0 saved the path of the config file too.
+            0 => {
+                let (general, file, _path): (legacy::GeneralSettingsV1, FileSettings, PathBuf) = bincode::deserialize(bytes)?;
+                Ok(Settings {
+                    general: legacy::GeneralSettingsV2::from(general).into(),
+                    file,
+                    path: PathBuf::new()
+                })
+            },
+            1 => {
+                let (general, file): (legacy::GeneralSettingsV1, FileSettings) = bincode::deserialize(bytes)?;
+                Ok(Settings {
+                    general: legacy::GeneralSettingsV2::from(general).into(),
+                    file,
+                    path: PathBuf::new()
+                })
+            },
+            2 => {
+                let (general, file): (legacy::GeneralSettingsV2, FileSettings) = bincode::deserialize(bytes)?;
+                Ok(Settings {
+                    general: general.into(),
+                    file,
+                    path: PathBuf::new()
+                })
+            },
+            _ => Err(Box::new(bincode::ErrorKind::Custom(format!("Unknown settings version {}", version)))),
+        }
     }
 }
 
@@ -63,6 +114,12 @@ pub struct GeneralSettings {
     pub auto_scroll_speed: f64,
     pub show_page_num: bool,
     pub show_page_file: bool,
+    /// Split the chapters into screen sized pages instead of scrolling them.
+    #[serde(default)]
+    pub paginated: bool,
+    /// Load the next and the previous chapter when scrolling past the ends of the current one.
+    #[serde(default)]
+    pub continuous: bool,
 }
 
 impl GeneralSettings {
@@ -76,7 +133,9 @@ impl GeneralSettings {
             text_color: "#000000".to_string(),
             auto_scroll_speed: 3.8,
             show_page_num: true,
-            show_page_file: true
+            show_page_file: true,
+            paginated: false,
+            continuous: false
         }
     }
 }
@@ -86,14 +145,17 @@ pub enum FileOpenPreference {
     // Open the current chapter (page)
     CurrentChapter,
     // Open the next chapter (page)
-    NextChapter
+    NextChapter,
+    // Open the current chapter (page) where the reading stopped
+    ExactPosition
 }
 
 impl FileOpenPreference {
     pub fn to_usize(&self) -> usize {
         match self {
             FileOpenPreference::CurrentChapter => 0,
-            FileOpenPreference::NextChapter => 1
+            FileOpenPreference::NextChapter => 1,
+            FileOpenPreference::ExactPosition => 0
         }
     }
 }
@@ -102,7 +164,8 @@ impl fmt::Display for FileOpenPreference {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             FileOpenPreference::CurrentChapter => write!(f, "current"),
-            FileOpenPreference::NextChapter => write!(f, "next")
+            FileOpenPreference::NextChapter => write!(f, "next"),
+            FileOpenPreference::ExactPosition => write!(f, "exact")
         }
     }
 }
@@ -114,6 +177,7 @@ impl FromStr for FileOpenPreference {
         match input {
             "current"  => Ok(FileOpenPreference::CurrentChapter),
             "next"  => Ok(FileOpenPreference::NextChapter),
+            "exact"  => Ok(FileOpenPreference::ExactPosition),
             _      => Err(()),
         }
     }
@@ -126,10 +190,81 @@ pub struct FileSettings {
 
 impl FileSettings {
     fn new() -> FileSettings {
-        let file_open_preference = FileOpenPreference::NextChapter;
+        let file_open_preference = FileOpenPreference::ExactPosition;
         
         FileSettings {
             file_open_preference,
         }
     }
 }
+
+/// Earlier formats of the settings, kept for migrating old files.
+mod legacy {
+    use super::GeneralSettings;
+    use crate::ui::Theme;
+    use serde::Deserialize;
+    
+    /// The general settings before the paginated mode.
+    #[derive(Deserialize)]
+    pub struct GeneralSettingsV1 {
+        pub theme: Theme,
+        pub font_size: f64,
+        pub font_family: String,
+        pub use_custom_color: bool,
+        pub background_color: String,
+        pub text_color: String,
+        pub auto_scroll_speed: f64,
+        pub show_page_num: bool,
+        pub show_page_file: bool,
+    }
+    
+    impl From<GeneralSettingsV1> for GeneralSettingsV2 {
+        fn from(general: GeneralSettingsV1) -> Self {
+            GeneralSettingsV2 {
+                theme: general.theme,
+                font_size: general.font_size,
+                font_family: general.font_family,
+                use_custom_color: general.use_custom_color,
+                background_color: general.background_color,
+                text_color: general.text_color,
+                auto_scroll_speed: general.auto_scroll_speed,
+                show_page_num: general.show_page_num,
+                show_page_file: general.show_page_file,
+                paginated: false
+            }
+        }
+    }
+    
+    /// The general settings before the continuous mode.
+    #[derive(Deserialize)]
+    pub struct GeneralSettingsV2 {
+        pub theme: Theme,
+        pub font_size: f64,
+        pub font_family: String,
+        pub use_custom_color: bool,
+        pub background_color: String,
+        pub text_color: String,
+        pub auto_scroll_speed: f64,
+        pub show_page_num: bool,
+        pub show_page_file: bool,
+        pub paginated: bool,
+    }
+    
+    impl From<GeneralSettingsV2> for GeneralSettings {
+        fn from(general: GeneralSettingsV2) -> Self {
+            GeneralSettings {
+                theme: general.theme,
+                font_size: general.font_size,
+                font_family: general.font_family,
+                use_custom_color: general.use_custom_color,
+                background_color: general.background_color,
+                text_color: general.text_color,
+                auto_scroll_speed: general.auto_scroll_speed,
+                show_page_num: general.show_page_num,
+                show_page_file: general.show_page_file,
+                paginated: general.paginated,
+                continuous: false
+            }
+        }
+    }
+}
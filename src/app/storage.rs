@@ -0,0 +1,138 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use crate::app::error::StorageError;
+
+
+/// Every file written by `save` starts with these bytes followed by the format version.
+/// Files from before the versioned format don't have them and are version 0.
+const MAGIC: &[u8; 4] = b"BADR";
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+/// Data that is saved on disk in the versioned format.
+///
+/// When the data changes the old struct is kept around, `VERSION` is bumped
+/// and `migrate` gets a case for converting the old struct to the new one.
+pub trait Versioned: Serialize + DeserializeOwned + Sized {
+    /// Version of the current format.
+    const VERSION: u32;
+    
+    /// Decode data written with an older format `version`.
+    fn migrate(version: u32, bytes: &[u8]) -> bincode::Result<Self>;
+}
+
+/// Load the data from `path`, migrating it from an older format if needed.
+/// Returns `None` if the file doesn't exist.
+///
+/// A file that cannot be read is renamed so it won't be overwritten
+/// and can be recovered, the error tells where it was moved.
+/// A file written by a newer version of the program is left as it is.
+pub fn load<T: Versioned>(path: &Path) -> Result<Option<T>, StorageError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let bytes = fs::read(path)?;
+    match decode(path, &bytes)? {
+        Ok(data) => Ok(Some(data)),
+        Err(reason) => {
+            let backup = backup_path(path);
+            fs::rename(path, &backup)?;
+            error!("Cannot read {:?}: {}. Moved it to {:?}", path, reason, backup);
+            
+            Err(StorageError::Unreadable(path.to_path_buf(), reason, backup))
+        }
+    }
+}
+
+/// Load the data from `path` like `load`, but leave an unreadable file where it is.
+/// For reading the data without changing anything on disk.
+pub fn load_read_only<T: Versioned>(path: &Path) -> Result<Option<T>, StorageError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let bytes = fs::read(path)?;
+    decode(path, &bytes)?
+        .map(Some)
+        .map_err(|reason| StorageError::Invalid(path.to_path_buf(), reason))
+}
+
+/// Decode the `bytes` of the file at `path`, migrating them from an older format if needed.
+/// The inner error tells why the data cannot be read.
+fn decode<T: Versioned>(path: &Path, bytes: &[u8]) -> Result<Result<T, String>, StorageError> {
+    let (version, payload) = split_header(bytes);
+    if version > T::VERSION {
+        error!("Cannot read {:?}: format version {} is newer than {}", path, version, T::VERSION);
+        return Err(StorageError::UnsupportedVersion(path.to_path_buf(), version));
+    }
+    
+    if version == T::VERSION {
+        Ok(bincode::deserialize(payload).map_err(|e| e.to_string()))
+    } else {
+        debug!("Migrating {:?} from version {} to {}", path, version, T::VERSION);
+        Ok(T::migrate(version, payload).map_err(|e| e.to_string()))
+    }
+}
+
+/// Save the data to `path` in the current format.
+///
+/// The data is written to a temporary file first and then renamed over
+/// the old file so a crash in the middle of writing cannot truncate it.
+/// A file written by a newer version of the program is never replaced.
+pub fn save<T: Versioned>(path: &Path, data: &T) -> Result<(), StorageError> {
+    if let Some(version) = file_version(path) {
+        if version > T::VERSION {
+            return Err(StorageError::UnsupportedVersion(path.to_path_buf(), version));
+        }
+    }
+    
+    let mut bytes = Vec::with_capacity(HEADER_LEN);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&T::VERSION.to_le_bytes());
+    bincode::serialize_into(&mut bytes, data)?;
+    
+    let temp_path = append_to_file_name(path, ".tmp");
+    let mut file = File::create(&temp_path)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    
+    fs::rename(&temp_path, path)?;
+    
+    Ok(())
+}
+
+/// Format version and the data after the header.
+fn split_header(bytes: &[u8]) -> (u32, &[u8]) {
+    if bytes.len() >= HEADER_LEN && bytes.starts_with(MAGIC) {
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&bytes[MAGIC.len()..HEADER_LEN]);
+        (u32::from_le_bytes(version), &bytes[HEADER_LEN..])
+    } else {
+        (0, bytes)
+    }
+}
+
+/// Format version of the file at `path`, `None` if it cannot be read.
+fn file_version(path: &Path) -> Option<u32> {
+    let mut header = [0u8; HEADER_LEN];
+    File::open(path).ok()?.read_exact(&mut header).ok()?;
+    
+    Some(split_header(&header).0)
+}
+
+/// E.g: `bad-reader.db` -> `bad-reader.db.corrupt-20210512-183005`
+fn backup_path(path: &Path) -> PathBuf {
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    
+    append_to_file_name(path, &format!(".corrupt-{}", timestamp))
+}
+
+fn append_to_file_name(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(suffix);
+    
+    path.with_file_name(file_name)
+}
@@ -0,0 +1,307 @@
+//! Plain text books. They are converted to an epub in the cache once,
+//! after that they open, render and get searched like any other book.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use encoding_rs::{Encoding, WINDOWS_1252};
+use regex::Regex;
+use crate::app::epub_writer::{self, GeneratedBook, GeneratedChapter, TocEntry, escape};
+use crate::app::utils::hash_file;
+
+
+/// Bumped when the conversion changes so the old cached epubs aren't used.
+const CONVERTER_VERSION: u32 = 3;
+/// Chapters longer than this are split into several pages, long pages are slow to render.
+const MAX_CHAPTER_CHARS: usize = 40_000;
+const MAX_HEADING_CHARS: usize = 80;
+/// Blank lines before a short line that make it a heading, when there are no `CHAPTER X` headings.
+const HEADING_BLANK_LINES: usize = 3;
+
+/// A run of non-blank lines.
+struct Block<'a> {
+    lines: Vec<&'a str>,
+    blank_lines_before: usize,
+}
+
+struct Chapter {
+    /// `None` for the text before the first heading.
+    title: Option<String>,
+    paragraphs: Vec<String>,
+}
+
+/// Path of the epub converted from the text `file`, converting it first if it's not in the cache.
+pub fn converted_epub(file: &Path) -> io::Result<PathBuf> {
+    let content_hash = hash_file(file)?;
+    let path = epub_writer::cache_path("txt", CONVERTER_VERSION, content_hash);
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let text = decode(&fs::read(file)?);
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let (title, author) = gutenberg_metadata(&text);
+    let title = title.unwrap_or_else(|| {
+        file.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default()
+    });
+
+    let mut book = GeneratedBook {
+        identifier: format!("urn:bad-reader:txt:{:016x}", content_hash),
+        author,
+        ..Default::default()
+    };
+
+    for (i, chapter) in split_chapters(strip_gutenberg_license(&text)).into_iter().enumerate() {
+        let file_name = format!("chapter_{:04}.xhtml", i + 1);
+
+        let mut body = String::new();
+        if let Some(chapter_title) = &chapter.title {
+            body.push_str(&format!("<h2>{}</h2>\n", escape(chapter_title)));
+            book.toc.push(TocEntry {
+                title: chapter_title.clone(),
+                href: file_name.clone(),
+                children: vec![],
+            });
+        }
+        for paragraph in &chapter.paragraphs {
+            body.push_str(&format!("<p>{}</p>\n", escape(paragraph)));
+        }
+
+        book.chapters.push(GeneratedChapter {
+            file_name,
+            title: chapter.title.unwrap_or_else(|| title.clone()),
+            body,
+        });
+    }
+    book.title = title;
+
+    epub_writer::save(&path, &book)?;
+    debug!("Converted {:?} to {:?}", file, path);
+
+    Ok(path)
+}
+
+/// Decode the text using the byte order mark, or as UTF-8 if it's valid
+/// and as Windows-1252 (a superset of Latin-1) if not.
+fn decode(bytes: &[u8]) -> String {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return encoding.decode_without_bom_handling(&bytes[bom_length..]).0.into_owned();
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => WINDOWS_1252.decode_without_bom_handling(bytes).0.into_owned(),
+    }
+}
+
+/// Title and author from the `Title:` and `Author:` lines in the header of Project Gutenberg books.
+fn gutenberg_metadata(text: &str) -> (Option<String>, Option<String>) {
+    let mut title = None;
+    let mut author = None;
+
+    for line in text.lines().take(100) {
+        if line.starts_with("*** START OF") {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Title:") {
+            title = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("Author:") {
+            author = Some(value.trim().to_string());
+        }
+    }
+
+    (title.filter(|t| !t.is_empty()), author.filter(|a| !a.is_empty()))
+}
+
+/// The book without the Project Gutenberg header and license, if it has them.
+fn strip_gutenberg_license(text: &str) -> &str {
+    let start = text
+        .find("*** START OF")
+        .and_then(|start| text[start..].find('\n').map(|end| start + end + 1))
+        .unwrap_or(0);
+    let end = text[start..]
+        .find("*** END OF")
+        .map(|end| start + end)
+        .unwrap_or_else(|| text.len());
+
+    &text[start..end]
+}
+
+fn blocks(text: &str) -> Vec<Block> {
+    let mut blocks = vec![];
+    let mut blank_lines = 0;
+    let mut current: Option<Block> = None;
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if let Some(block) = current.take() {
+                blocks.push(block);
+            }
+            blank_lines += 1;
+            continue;
+        }
+
+        current
+            .get_or_insert_with(|| Block { lines: vec![], blank_lines_before: blank_lines })
+            .lines
+            .push(line.trim());
+        blank_lines = 0;
+    }
+    blocks.extend(current);
+
+    blocks
+}
+
+/// A lone short line after a run of blank lines.
+fn is_spaced_heading(block: &Block) -> bool {
+    block.lines.len() == 1
+        && block.blank_lines_before >= HEADING_BLANK_LINES
+        && block.lines[0].chars().any(char::is_alphabetic)
+        && !block.lines[0].ends_with(|c| c == '.' || c == ',' || c == ';')
+}
+
+/// Split the text into chapters at the headings, or into equal parts if no headings were found.
+fn split_chapters(text: &str) -> Vec<Chapter> {
+    let blocks = blocks(text);
+    // Headings like `CHAPTER XII.`, `Chapter 3: The Storm`, `Part One`, `Prologue` or `IV`.
+    // The keyword needs a number after it or a line of its own, `Part of me...` is not a heading.
+    let named_heading = Regex::new(concat!(
+        r"^(?:",
+        r"(?i:chapter|book|part|volume|act|scene|letter|section)\s+(?:\d+\b|(?:[IVXLCDM]+|",
+        r"(?i:one|two|three|four|five|six|seven|eight|nine|ten|eleven|twelve|thirteen|fourteen|fifteen|",
+        r"sixteen|seventeen|eighteen|nineteen|twenty|thirty|forty|fifty|sixty|seventy|eighty|ninety)(?:-\w+)?)(?:[.:\-]|$))",
+        r"|(?i:chapter|book|part|volume|act|scene|letter|section|prologue|epilogue)[.:]?$",
+        r"|(?i:prologue|epilogue)[.:]",
+        r"|[IVXLCDM]+\.?$",
+        r")"
+    )).expect("Bad heading regex");
+    let is_short = |block: &Block| block.lines.len() <= 2 && block.lines.iter().all(|line| line.chars().count() <= MAX_HEADING_CHARS);
+
+    let mut headings: Vec<bool> = blocks.iter().map(|block| is_short(block) && named_heading.is_match(block.lines[0])).collect();
+    if headings.iter().filter(|is_heading| **is_heading).count() < 2 {
+        headings = blocks.iter().map(|block| is_short(block) && is_spaced_heading(block)).collect();
+    }
+
+    // The first chapter is the text before the first heading, it's always there.
+    let mut chapters = vec![Chapter { title: None, paragraphs: vec![] }];
+    for (block, is_heading) in blocks.iter().zip(headings) {
+        if is_heading {
+            // A heading right after another one is most likely a line in the table of contents.
+            let previous = chapters.last_mut().unwrap();
+            if previous.paragraphs.is_empty() {
+                if let Some(title) = previous.title.take() {
+                    chapters.pop();
+                    chapters.last_mut().unwrap().paragraphs.push(title);
+                }
+            }
+
+            chapters.push(Chapter { title: Some(block.lines.join(" ")), paragraphs: vec![] });
+        } else {
+            chapters.last_mut().unwrap().paragraphs.push(block.lines.join(" "));
+        }
+    }
+
+    chapters.retain(|chapter| chapter.title.is_some() || !chapter.paragraphs.is_empty());
+    let has_headings = chapters.iter().any(|chapter| chapter.title.is_some());
+    // An empty file is still a book, with one empty chapter.
+    if chapters.is_empty() {
+        chapters.push(Chapter { title: None, paragraphs: vec![] });
+    }
+
+    let mut split = vec![];
+    for chapter in chapters {
+        for (i, paragraphs) in split_long(chapter.paragraphs).into_iter().enumerate() {
+            let title = if i == 0 { chapter.title.clone() } else { None };
+            split.push(Chapter { title, paragraphs });
+        }
+    }
+
+    // Without headings the parts get numbers so there is something in the table of contents.
+    if !has_headings {
+        for (i, chapter) in split.iter_mut().enumerate() {
+            chapter.title = Some(format!("Part {}", i + 1));
+        }
+    }
+
+    split
+}
+
+/// Split the paragraphs into parts of about `MAX_CHAPTER_CHARS`.
+fn split_long(paragraphs: Vec<String>) -> Vec<Vec<String>> {
+    let mut parts = vec![vec![]];
+    let mut length = 0;
+
+    for paragraph in paragraphs {
+        let paragraph_length = paragraph.chars().count();
+        if length > 0 && length + paragraph_length > MAX_CHAPTER_CHARS {
+            parts.push(vec![]);
+            length = 0;
+        }
+        length += paragraph_length;
+        parts.last_mut().unwrap().push(paragraph);
+    }
+
+    parts
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(chapters: &[Chapter]) -> Vec<Option<&str>> {
+        chapters.iter().map(|chapter| chapter.title.as_deref()).collect()
+    }
+
+    #[test]
+    fn splits_at_chapter_headings() {
+        let text = "Foreword.\n\nCHAPTER I.\n\nIt was a dark night.\n\nCHAPTER II.\n\nPart of me stayed.\n";
+        let chapters = split_chapters(text);
+
+        assert_eq!(titles(&chapters), vec![None, Some("CHAPTER I."), Some("CHAPTER II.")]);
+        assert_eq!(chapters[0].paragraphs, vec!["Foreword."]);
+        assert_eq!(chapters[2].paragraphs, vec!["Part of me stayed."]);
+    }
+
+    #[test]
+    fn table_of_contents_is_not_split() {
+        let text = "Chapter 1\n\nChapter 2\n\nChapter 1\n\nOne.\n\nChapter 2\n\nTwo.\n";
+        let chapters = split_chapters(text);
+
+        assert_eq!(titles(&chapters), vec![None, Some("Chapter 1"), Some("Chapter 2")]);
+        assert_eq!(chapters[0].paragraphs, vec!["Chapter 1", "Chapter 2"]);
+    }
+
+    #[test]
+    fn numbers_the_parts_without_headings() {
+        let paragraph = "word ".repeat(MAX_CHAPTER_CHARS / 5 / 2);
+        let text = vec![paragraph.as_str(); 3].join("\n\n");
+        let chapters = split_chapters(&text);
+
+        assert_eq!(titles(&chapters), vec![Some("Part 1"), Some("Part 2")]);
+    }
+
+    #[test]
+    fn empty_text_has_one_chapter() {
+        for text in &["", " \n\t\n\n"] {
+            let chapters = split_chapters(text);
+
+            assert_eq!(titles(&chapters), vec![Some("Part 1")]);
+            assert!(chapters[0].paragraphs.is_empty());
+        }
+    }
+
+    #[test]
+    fn decodes_with_the_byte_order_mark() {
+        assert_eq!(decode(b"\xEF\xBB\xBFcaf\xC3\xA9"), "café");
+        assert_eq!(decode(b"\xFF\xFEc\x00a\x00f\x00\xE9\x00"), "café");
+        assert_eq!(decode(b"\xFE\xFF\x00c\x00a\x00f\x00\xE9"), "café");
+    }
+
+    #[test]
+    fn decodes_utf8_and_legacy_text() {
+        assert_eq!(decode("café".as_bytes()), "café");
+        // Not valid UTF-8, read as Windows-1252.
+        assert_eq!(decode(b"caf\xE9 \x93quoted\x94"), "café \u{201C}quoted\u{201D}");
+    }
+}
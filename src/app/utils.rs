@@ -1,9 +1,11 @@
 use glib::{IsA, Object};
 use gtk::prelude::{BuilderExt, BuilderExtManual, CssProviderExt};
 use gtk::{CssProvider, Builder};
-use std::env::current_exe;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{self, Read, BufReader};
 use rust_embed::RustEmbed;
+use select::node::Node;
 
 
 #[derive(RustEmbed)]
@@ -72,38 +74,88 @@ pub fn remove_style(provider: CssProvider) {
     );
 }
 
-pub fn working_dir(file: &str) -> String {
-    #[cfg(debug_assertions)]
-    let dir = match current_exe() {
-        Ok(mut path) => {
-            debug!("current_exe: {:?}", path);
-            path.pop();
-            path.pop();
-            path.pop();
-            path
-        },
-        Err(e) => {
-            error!("Could not get current_exe path. {:?}", e);
-            PathBuf::new()
-        }
+/// Resolve a `href` found in a book file against the path of that file.
+/// E.g: `OEBPS/text/ch1.xhtml` + `../images/map%201.png#top` -> `OEBPS/images/map 1.png`.
+pub fn resolve_href(base: &Path, href: &str) -> PathBuf {
+    let href = href.split(|c| c == '#' || c == '?').next().unwrap_or("");
+    
+    // Absolute hrefs start from the root of the book
+    let mut path = if href.starts_with('/') {
+        PathBuf::new()
+    } else {
+        base.parent().map(Path::to_path_buf).unwrap_or_default()
     };
     
-    #[cfg(not(debug_assertions))]
-        let dir = match current_exe() {
-        Ok(mut path) => {
-            debug!("current_exe: {:?}", path);
-            path.pop();
-            path
-        },
-        Err(e) => {
-            error!("Could not get current_exe path. {:?}", e);
-            PathBuf::new()
+    for component in percent_decode(href).split('/') {
+        match component {
+            "" | "." => {},
+            ".." => {
+                path.pop();
+            },
+            component => path.push(component),
         }
-    };
+    }
+    
+    path
+}
+
+/// Decode `%XX` escapes in an url. Invalid escapes are kept as they are.
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if let Some(byte) = hex {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        
+        decoded.push(bytes[i]);
+        i += 1;
+    }
     
-    if let Some(filepath) = dir.join(file).to_str() {
-        return filepath.to_string()
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Check the `epub:type` and its `role` counterpart for any of the `types`.
+/// E.g. `epub:type="noteref"` or `role="doc-noteref"`.
+pub fn has_epub_type(node: Node, types: &[&str]) -> bool {
+    let epub_types = node.attr("epub:type").unwrap_or("");
+    let roles = node.attr("role").unwrap_or("");
+    
+    epub_types
+        .split_ascii_whitespace()
+        .chain(roles.split_ascii_whitespace().map(|role| role.trim_start_matches("doc-")))
+        .any(|t| types.contains(&t))
+}
+
+/// Hash the content of a file with 64 bit FNV-1a.
+/// Unlike `DefaultHasher` the result stays the same between Rust versions so it can be saved.
+pub fn hash_file(path: &Path) -> io::Result<u64> {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buffer = [0u8; 64 * 1024];
+    let mut hash = OFFSET_BASIS;
+    
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        
+        for byte in &buffer[..read] {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
     }
     
-    "".to_string()
-}
\ No newline at end of file
+    Ok(hash)
+}
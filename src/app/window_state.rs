@@ -1,16 +1,12 @@
 use gtk::prelude::*;
 
-use crate::app::error::SettingsError;
-use std::path::{Path};
-use std::io::{BufWriter, BufReader};
-use std::fs::File;
-use anyhow::{Context};
-use bincode::{deserialize_from, serialize_into};
+use crate::app::error::StorageError;
+use crate::app::storage::{self, Versioned};
 use serde::{Deserialize, Serialize};
-use crate::app::utils::working_dir;
+use crate::app::paths::{self, Location};
 
 
-static CONFIG_NAME: &str = "bad-reader.state";
+pub static CONFIG_NAME: &str = "bad-reader.state";
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct WindowState {
@@ -55,25 +51,25 @@ impl WindowState {
         }
     }
     
-    pub fn write(&self) -> Result<(), SettingsError> {
-        let path = Path::new(&working_dir(CONFIG_NAME)).to_path_buf();
-        let mut f = BufWriter::new(File::create(&path).unwrap());
-        serialize_into(&mut f, self)
-            .context(SettingsError::WriteToDisk)?;
-        Ok(())
+    pub fn write(&self) -> Result<(), StorageError> {
+        let path = paths::path(Location::State, CONFIG_NAME);
+        storage::save(&path, self)
     }
     
-    pub fn open() -> Result<Option<Self>, SettingsError> {
-        let path = Path::new(&working_dir(CONFIG_NAME)).to_path_buf();
-        if path.as_path().exists() {
-            let f = File::open(&path)
-                .context(SettingsError::ReadFromDisk)?;
-            if let Ok(settings) = deserialize_from(BufReader::new(&f))
-                .context(SettingsError::Unknown) {
-                return Ok(Some(settings));
-            }
+    pub fn open() -> Result<Option<Self>, StorageError> {
+        let path = paths::path(Location::State, CONFIG_NAME);
+        storage::load::<WindowState>(&path)
+    }
+}
+
+impl Versioned for WindowState {
+    const VERSION: u32 = 1;
+    
+    fn migrate(version: u32, bytes: &[u8]) -> bincode::Result<Self> {
+        match version {
+            // Unchanged since 1.0, only the header was added.
+            0 => bincode::deserialize(bytes),
+            _ => Err(Box::new(bincode::ErrorKind::Custom(format!("Unknown window state version {}", version)))),
         }
-        
-        Ok(None)
     }
 }
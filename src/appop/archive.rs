@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+use crate::appop::AppOp;
+use crate::app::archive;
+
+
+impl AppOp {
+    /// Export the library and the settings to the archive at `path`.
+    pub fn export_library(&mut self, path: PathBuf) {
+        // Save the position of the open book so it's in the archive too.
+        self.save_position();
+
+        let result = archive::export_library(&path, &self.db.read(), &self.settings.read());
+        match result {
+            Ok(count) => info!("Exported {} books to {:?}", count, path),
+            Err(err) => {
+                error!("Cannot export the library: {}", err);
+                self.ui.error_dialog(&format!("Could not export the library. {}", err));
+            }
+        }
+    }
+
+    /// Read the archive at `path` and show what importing it would change.
+    pub fn preview_import(&mut self, path: PathBuf) {
+        let library = match archive::read_archive(&path) {
+            Ok(library) => library,
+            Err(err) => {
+                error!("Cannot read the library archive: {}", err);
+                self.ui.error_dialog(&format!("Could not read the library archive. {}", err));
+                return;
+            }
+        };
+
+        // Dry run on a copy of the db.
+        let mut db = self.db.read().clone();
+        let report = library.merge_into(&mut db, &self.settings.read());
+
+        self.ui.import_library_dialog(self.app_runtime.clone(), &report.to_string(), report.settings_changed);
+        self.pending_import = Some(library);
+    }
+
+    /// Merge the archive that was previewed into the db, and replace the settings if `include_settings`.
+    pub fn import_library(&mut self, include_settings: bool) {
+        let library = match self.pending_import.take() {
+            Some(library) => library,
+            None => return,
+        };
+
+        // Save the position first so an older position in the archive doesn't win.
+        self.save_position();
+
+        let report = library.merge_into(&mut self.db.write(), &self.settings.read());
+        info!("Imported the library:\n{}", report);
+        self.save_to_file();
+
+        // The open book keeps its position but gets the imported bookmarks and highlights.
+        let open_row = self.book
+            .as_ref()
+            .and_then(|book| self.db.read().get_by_book(&book.identity, &book.file));
+        if let (Some(book), Some(row)) = (self.book.as_mut(), open_row) {
+            book.bookmarks = row.bookmarks;
+            book.highlights = row.highlights;
+
+            self.ui.update_bookmarks(self.app_runtime.clone(), &book.bookmarks);
+            self.ui.update_highlights(&book.highlights);
+            self.ui.apply_highlights(&book.highlights);
+            self.update_bookmark_marker();
+        }
+
+        self.refresh_recent();
+        self.scan_library();
+
+        if include_settings && report.settings_changed {
+            let mut new_settings = self.settings.read().clone();
+            library.apply_settings(&mut new_settings);
+            self.apply_settings(new_settings);
+        }
+    }
+}
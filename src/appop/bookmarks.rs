@@ -0,0 +1,104 @@
+use chrono::Local;
+use crate::appop::AppOp;
+use crate::app::database::Bookmark;
+
+
+impl AppOp {
+    /// Bookmark the position at the top of the reader, or remove the
+    /// bookmarks if there are any in the visible part of the page.
+    pub fn toggle_bookmark(&mut self) {
+        self.follow_chapter_in_view();
+        let (top, bottom) = self.ui.visible_offsets();
+        let book = match self.book.as_mut() {
+            Some(book) => book,
+            None => return,
+        };
+        let page = book.current_page;
+
+        let count = book.bookmarks.len();
+        book.bookmarks.retain(|bookmark| !is_visible(bookmark, page, top, bottom));
+
+        if book.bookmarks.len() == count {
+            book.bookmarks.push(Bookmark {
+                page,
+                chapter_offset: top as usize,
+                created: Local::now(),
+                label: None
+            });
+            book.bookmarks.sort_by_key(|bookmark| (bookmark.page, bookmark.chapter_offset));
+        }
+
+        self.save_bookmarks();
+    }
+
+    /// Open the page of the bookmark at `index` and scroll to it.
+    pub fn open_bookmark(&mut self, index: usize) {
+        let bookmark = match self.book.as_ref().and_then(|book| book.bookmarks.get(index)) {
+            Some(bookmark) => bookmark.clone(),
+            None => return,
+        };
+
+        self.open_page_at_offset(bookmark.page, bookmark.chapter_offset);
+    }
+
+    pub fn toggle_rename_bookmark_dialog(&mut self, index: usize) {
+        let label = match self.book.as_ref().and_then(|book| book.bookmarks.get(index)) {
+            Some(bookmark) => bookmark.label.clone().unwrap_or_default(),
+            None => return,
+        };
+
+        self.ui.rename_bookmark_dialog(self.app_runtime.clone(), index, &label);
+    }
+
+    /// Change the label of the bookmark at `index`, an empty `label` removes it.
+    pub fn rename_bookmark(&mut self, index: usize, label: String) {
+        let bookmark = self.book.as_mut().and_then(|book| book.bookmarks.get_mut(index));
+        if let Some(bookmark) = bookmark {
+            let label = label.trim();
+            bookmark.label = if label.is_empty() { None } else { Some(label.to_string()) };
+
+            self.save_bookmarks();
+        }
+    }
+
+    pub fn delete_bookmark(&mut self, index: usize) {
+        if let Some(book) = self.book.as_mut() {
+            if index < book.bookmarks.len() {
+                book.bookmarks.remove(index);
+
+                self.save_bookmarks();
+            }
+        }
+    }
+
+    /// Show the marker in the reader header if a bookmark is in the visible part of the page.
+    pub fn update_bookmark_marker(&mut self) {
+        let is_bookmarked = match self.book.as_ref() {
+            Some(book) => {
+                let (top, bottom) = self.ui.visible_offsets();
+                let page = book.current_page;
+
+                book.bookmarks.iter().any(|bookmark| is_visible(bookmark, page, top, bottom))
+            },
+            None => false,
+        };
+
+        self.ui.set_bookmark_marker(is_bookmarked);
+    }
+
+    /// Save the bookmarks of the open book to the db and update the bookmarks panel.
+    fn save_bookmarks(&mut self) {
+        let (row, bookmarks) = match self.book.as_ref() {
+            Some(book) => (book.to_db_row(self.ui.offset_at_top() as usize), book.bookmarks.clone()),
+            None => return,
+        };
+
+        self.update_db(row);
+        self.ui.update_bookmarks(self.app_runtime.clone(), &bookmarks);
+        self.update_bookmark_marker();
+    }
+}
+
+fn is_visible(bookmark: &Bookmark, page: usize, top: i32, bottom: i32) -> bool {
+    bookmark.page == page && (top..=bottom).contains(&(bookmark.chapter_offset as i32))
+}
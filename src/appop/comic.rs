@@ -0,0 +1,73 @@
+use crate::appop::AppOp;
+use crate::app::comic_book::ComicBook;
+
+
+impl AppOp {
+    /// Used for receiving the `ComicBook` result from another thread
+    /// in which the file was opened.
+    pub fn post_open_comic_book(&mut self, mut comic: ComicBook) {
+        let db_row = self.db.read().get_by_book(&comic.identity, &comic.file);
+        let saved_page = db_row.map(|row| {
+            comic.right_to_left = row.right_to_left;
+            row.current_page
+        });
+        let page = comic.initial_page.or(saved_page).unwrap_or(0);
+        comic.current_page = page.min(comic.num_pages() - 1);
+        
+        // Keep the exact position in the book that was open
+        self.save_position();
+        self.book = None;
+        self.ui.set_comic_mode(true);
+        // Comics don't have bookmarks or highlights
+        self.ui.update_bookmarks(self.app_runtime.clone(), &[]);
+        self.ui.update_highlights(&[]);
+        self.ui.set_bookmark_marker(false);
+        
+        self.ui.show_comic_page(&mut comic, &self.settings.read());
+        let row = comic.to_db_row();
+        self.comic_book = Some(comic);
+        self.update_db(row);
+        
+        // Results of the previous book are no use anymore
+        self.search_book(String::new());
+        
+        // Turn the spinner off
+        self.ui.toggle_spinner(false);
+        
+        if !self.ui.left_revealer.is_visible() {
+            self.ui.left_revealer.set_visible(true);
+        }
+    }
+    
+    /// Show the page `num` of the open comic book.
+    /// Does nothing if the page number is not valid or is already open.
+    pub fn open_comic_page(&mut self, num: usize) {
+        let comic = match self.comic_book.as_mut() {
+            Some(comic) => comic,
+            None => return,
+        };
+        if num == comic.current_page || num >= comic.num_pages() {
+            return;
+        }
+        
+        comic.current_page = num;
+        self.ui.show_comic_page(comic, &self.settings.read());
+        
+        let row = comic.to_db_row();
+        self.update_db(row);
+    }
+    
+    /// Switch the reading order of the open comic between left-to-right and right-to-left.
+    /// The order is remembered for the book.
+    pub fn toggle_right_to_left(&mut self) {
+        let row = match self.comic_book.as_mut() {
+            Some(comic) => {
+                comic.right_to_left = !comic.right_to_left;
+                comic.to_db_row()
+            },
+            None => return,
+        };
+        
+        self.update_db(row);
+    }
+}
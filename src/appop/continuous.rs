@@ -0,0 +1,79 @@
+use crate::appop::AppOp;
+use crate::ui::chapters::{ChapterPlace, ViewAnchor};
+
+
+impl AppOp {
+    /// Follow the chapter in view and load the next chapter before the end of the
+    /// loaded ones is reached.
+    pub fn update_continuous(&mut self) {
+        self.follow_chapter_in_view();
+
+        if self.ui.is_continuous() && self.ui.is_near_end() {
+            let next = self.ui.chapters.last().map(|chapter| chapter.page + 1);
+            if let Some(next) = next {
+                self.load_chapter(next, ChapterPlace::After);
+            }
+        }
+    }
+
+    /// Add the chapter before the loaded ones when scrolling above the top.
+    pub fn load_previous_chapter(&mut self) {
+        if !self.ui.is_continuous() {
+            return;
+        }
+
+        let first = self.ui.chapters.first().map(|chapter| chapter.page);
+        if let Some(first) = first.filter(|first| *first > 0) {
+            self.load_chapter(first - 1, ChapterPlace::Before);
+        }
+    }
+
+    /// Make the chapter at the top of the reader the current page, so the header, the table
+    /// of contents and the saved position follow the chapter that is being read.
+    /// Only changes the book in memory, `save_position` saves it with the periodic saves.
+    pub fn follow_chapter_in_view(&mut self) {
+        let page = match self.ui.page_at_top() {
+            Some(page) => page,
+            None => return,
+        };
+        let book = match self.book.as_mut() {
+            Some(book) if book.current_page != page => book,
+            _ => return,
+        };
+
+        book.current_page = page;
+        self.ui.update_chapter_info(book, &self.settings.read());
+        self.update_bookmark_marker();
+    }
+
+    /// Switch the loading of the chapters before and after the current one on or off
+    /// and save it to the settings.
+    pub fn toggle_continuous(&mut self) {
+        let mut new_settings = self.settings.read().clone();
+        new_settings.general.continuous = !new_settings.general.continuous;
+
+        self.apply_settings(new_settings);
+    }
+
+    fn load_chapter(&mut self, page: usize, place: ChapterPlace) {
+        // Wait until the view is back in place after the previous chapter
+        if self.ui.is_chapter_loading() {
+            return;
+        }
+        let book = match self.book.as_mut() {
+            Some(book) if page < book.num_pages() => book,
+            _ => return,
+        };
+
+        if let Some(anchor) = self.ui.load_chapter(book, page, place) {
+            self.after_layout(move |state| state.restore_view(anchor));
+        }
+    }
+
+    /// Keep the text in view in place after the chapters changed, until no more are removed.
+    fn restore_view(&mut self, anchor: ViewAnchor) {
+        if let Some(anchor) = self.ui.restore_view(anchor) {
+            self.after_layout(move |state| state.restore_view(anchor));
+        }
+    }
+}
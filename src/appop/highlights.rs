@@ -0,0 +1,111 @@
+use chrono::Local;
+use gtk::prelude::*;
+use crate::appop::AppOp;
+use crate::app::database::{Highlight, HighlightColor};
+
+
+impl AppOp {
+    /// Highlight the text between the char offsets of the reader buffer.
+    pub fn add_highlight(&mut self, start: i32, end: i32, color: HighlightColor, note: String) {
+        let buffer = self.ui.reader.buffer().expect("Could not get buffer");
+        let text = buffer
+            .text(&buffer.iter_at_offset(start), &buffer.iter_at_offset(end), false)
+            .map(|text| text.to_string())
+            .unwrap_or_default();
+
+        // Highlights are saved by the chapter they start in
+        let (page, chapter_start) = match self.ui.chapter_position(start) {
+            Some((page, offset)) => (page, start - offset),
+            None => return,
+        };
+        let book = match self.book.as_mut() {
+            Some(book) => book,
+            None => return,
+        };
+
+        book.highlights.push(Highlight {
+            page,
+            start: (start - chapter_start) as usize,
+            end: (end - chapter_start) as usize,
+            text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+            color,
+            note: note_or_none(note),
+            created: Local::now(),
+        });
+        book.highlights.sort_by_key(|highlight| (highlight.page, highlight.start));
+        buffer.select_range(&buffer.iter_at_offset(end), &buffer.iter_at_offset(end));
+
+        self.save_highlights();
+    }
+
+    /// Show the edit popover of the highlight at the char `offset` of the reader buffer,
+    /// if there is one. Returns whether there was a highlight.
+    pub fn edit_highlight_at(&mut self, offset: i32) -> bool {
+        let book = match self.book.as_ref() {
+            Some(book) => book,
+            None => return false,
+        };
+        let (page, offset) = match self.ui.chapter_position(offset) {
+            Some((page, offset)) => (page, offset as usize),
+            None => return false,
+        };
+
+        let found = book.highlights
+            .iter()
+            .position(|highlight| highlight.page == page && highlight.start <= offset && offset < highlight.end);
+
+        if let Some(index) = found {
+            self.ui.edit_highlight_popover(self.app_runtime.clone(), index, &book.highlights[index]);
+        }
+
+        found.is_some()
+    }
+
+    pub fn update_highlight(&mut self, index: usize, color: HighlightColor, note: String) {
+        let highlight = self.book.as_mut().and_then(|book| book.highlights.get_mut(index));
+        if let Some(highlight) = highlight {
+            highlight.color = color;
+            highlight.note = note_or_none(note);
+
+            self.save_highlights();
+        }
+    }
+
+    pub fn delete_highlight(&mut self, index: usize) {
+        if let Some(book) = self.book.as_mut() {
+            if index < book.highlights.len() {
+                book.highlights.remove(index);
+
+                self.save_highlights();
+            }
+        }
+    }
+
+    /// Open the page of the highlight at `index` and scroll to it.
+    pub fn open_highlight(&mut self, index: usize) {
+        let highlight = match self.book.as_ref().and_then(|book| book.highlights.get(index)) {
+            Some(highlight) => highlight.clone(),
+            None => return,
+        };
+
+        self.open_page_at_offset(highlight.page, highlight.start);
+    }
+
+    /// Save the highlights of the open book to the db and show them in the reader and the panel.
+    fn save_highlights(&mut self) {
+        let (row, highlights) = match self.book.as_ref() {
+            Some(book) => (book.to_db_row(self.ui.offset_at_top() as usize), book.highlights.clone()),
+            None => return,
+        };
+
+        self.update_db(row);
+        self.ui.apply_highlights(&highlights);
+        self.ui.update_highlights(&highlights);
+    }
+}
+
+fn note_or_none(note: String) -> Option<String> {
+    let note = note.trim();
+
+    if note.is_empty() { None } else { Some(note.to_string()) }
+}
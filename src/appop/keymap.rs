@@ -0,0 +1,55 @@
+use crate::appop::AppOp;
+
+
+impl AppOp {
+    /// Make the `accel` the only shortcut of the `action`, asking first if another action already has it.
+    pub fn bind_shortcut(&mut self, action: &'static str, accel: String) {
+        if let Some(other) = self.keymap.conflict(action, &accel) {
+            self.ui.shortcut_conflict_dialog(self.app_runtime.clone(), action, accel, other.label);
+            return;
+        }
+
+        self.keymap.set_accels(action, vec![accel]);
+        self.save_keymap();
+    }
+
+    /// Move the `accel` from the action that has it to the `action`.
+    pub fn replace_shortcut(&mut self, action: &'static str, accel: String) {
+        self.keymap.unbind(&accel);
+        self.keymap.set_accels(action, vec![accel]);
+        self.save_keymap();
+    }
+
+    pub fn clear_shortcut(&mut self, action: &'static str) {
+        self.keymap.set_accels(action, vec![]);
+        self.save_keymap();
+    }
+
+    /// Give the `action` its default shortcuts. They are taken away from the other actions that have them.
+    pub fn reset_shortcut(&mut self, action: &'static str) {
+        self.keymap.reset(action);
+        for accel in self.keymap.accels(action).to_vec() {
+            while let Some(other) = self.keymap.conflict(action, &accel) {
+                let accels = self.keymap.accels(other.name).iter().filter(|other| **other != accel).cloned().collect();
+                self.keymap.set_accels(other.name, accels);
+            }
+        }
+        self.save_keymap();
+    }
+
+    pub fn reset_all_shortcuts(&mut self) {
+        self.keymap.reset_all();
+        self.save_keymap();
+    }
+
+    /// Save the keymap and use the new shortcuts right away.
+    fn save_keymap(&mut self) {
+        if let Err(err) = self.keymap.write() {
+            self.ui.error_dialog(&format!("Could not save the shortcuts. {}", err));
+        }
+
+        self.keymap.apply(&self.ui.gtk_app);
+        self.ui.update_menu_accels(&self.keymap);
+        self.ui.update_shortcuts(self.app_runtime.clone(), &self.keymap);
+    }
+}
@@ -1,4 +1,8 @@
-use crate::appop::{AppOp, EpubBook};
+use crate::appop::{AppOp, OpenBook};
+use crate::app::library::ScannedBook;
+use crate::app::comic_book::ComicBook;
+use crate::app::search::SearchEvent;
+use crate::app::progress::ChapterLengths;
 
 
 impl AppOp {
@@ -24,12 +28,12 @@ impl AppOp {
             .send(num);
     }
     
-    pub fn open_epub_book_message(&self) -> glib::Sender<EpubBook> {
+    pub fn open_book_message(&self) -> glib::Sender<OpenBook> {
         let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
         
         rx.attach(None, glib::clone!(@strong self.app_runtime as app_runtime => @default-return glib:Continue(false), move |data| {
             app_runtime.update_state_with(move |state| {
-                state.post_open_epub_book(data);
+                state.post_open_book(data);
             });
             
             glib::Continue(true)
@@ -38,9 +42,79 @@ impl AppOp {
         tx
     }
     
-    // pub fn open_epub_book_send(&self, book: EpubBook) {
+    pub fn open_comic_book_message(&self) -> glib::Sender<ComicBook> {
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        
+        rx.attach(None, glib::clone!(@strong self.app_runtime as app_runtime => @default-return glib::Continue(false), move |data| {
+            app_runtime.update_state_with(move |state| {
+                state.post_open_comic_book(data);
+            });
+            
+            glib::Continue(true)
+        }));
+        
+        tx
+    }
+    
+    pub fn open_file_failed_message(&self) -> glib::Sender<String> {
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        
+        rx.attach(None, glib::clone!(@strong self.app_runtime as app_runtime => @default-return glib::Continue(false), move |message| {
+            app_runtime.update_state_with(move |state| {
+                state.post_open_file_failed(message);
+            });
+            
+            glib::Continue(true)
+        }));
+        
+        tx
+    }
+    
+    pub fn scanned_book_message(&self) -> glib::Sender<ScannedBook> {
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        
+        rx.attach(None, glib::clone!(@strong self.app_runtime as app_runtime => @default-return glib::Continue(false), move |data| {
+            app_runtime.update_state_with(move |state| {
+                state.post_scanned_book(data);
+            });
+            
+            glib::Continue(true)
+        }));
+        
+        tx
+    }
+    
+    pub fn search_message(&self) -> glib::Sender<SearchEvent> {
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        
+        rx.attach(None, glib::clone!(@strong self.app_runtime as app_runtime => @default-return glib::Continue(false), move |data| {
+            app_runtime.update_state_with(move |state| {
+                state.post_search_event(data);
+            });
+            
+            glib::Continue(true)
+        }));
+        
+        tx
+    }
+    
+    pub fn chapter_lengths_message(&self) -> glib::Sender<ChapterLengths> {
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        
+        rx.attach(None, glib::clone!(@strong self.app_runtime as app_runtime => @default-return glib::Continue(false), move |data| {
+            app_runtime.update_state_with(move |state| {
+                state.post_chapter_lengths(data);
+            });
+            
+            glib::Continue(true)
+        }));
+        
+        tx
+    }
+    
+    // pub fn open_book_send(&self, book: OpenBook) {
     //     self
-    //         .open_epub_book_sender
+    //         .open_book_sender
     //         .as_ref()
     //         .unwrap()
     //         .send(book)
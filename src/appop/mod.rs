@@ -1,5 +1,13 @@
 pub mod messages;
 pub mod settings;
+pub mod bookmarks;
+pub mod highlights;
+pub mod archive;
+pub mod comic;
+pub mod keymap;
+pub mod pager;
+pub mod continuous;
+pub mod progress;
 
 use std::sync::Arc;
 
@@ -8,33 +16,98 @@ use parking_lot::RwLock;
 
 use crate::app::{AppRuntime};
 use crate::ui;
-use std::path::{PathBuf, Path};
-use epub::doc::EpubDoc;
-use std::io::BufReader;
-use std::fs::File;
-use std::ffi::{OsStr, OsString};
-use crate::app::database::{Database, read_database, DatabaseRow};
+use std::path::PathBuf;
+use crate::app::book::{Book, Section};
+use crate::app::database::{Database, read_database, DatabaseRow, BookIdentity, BookMetadata, Bookmark, Highlight};
+use crate::app::library::{self, ScannedBook};
+use crate::app::search::{self, SearchEvent};
+use crate::app::archive::LibraryArchive;
+use crate::app::comic_book::ComicBook;
+use crate::app::keymap::Keymap;
+use crate::app::profile_lock::ProfileLock;
+use crate::app::progress::{ChapterLengths, ReadingClock};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use core::mem;
-use crate::app::settings::Settings;
+use crate::app::settings::{Settings, FileOpenPreference};
 use crate::app::window_state::WindowState;
+use crate::app::utils::{resolve_href, percent_decode};
+use select::document::Document;
+use select::predicate::Attr;
 
 
-pub struct EpubBook {
+/// How often the reading position is saved, in seconds.
+const SAVE_POSITION_INTERVAL: u32 = 30;
+
+/// The book open in the reader.
+pub struct OpenBook {
     pub file: PathBuf,
-    pub doc: EpubDoc<BufReader<File>>,
+    pub doc: Box<dyn Book>,
+    /// Index of the section that is open.
+    pub current_page: usize,
     pub initial_page: Option<usize>,
+    pub identity: BookIdentity,
+    pub metadata: BookMetadata,
+    /// Loaded from the db when the book is opened.
+    pub bookmarks: Vec<Bookmark>,
+    pub highlights: Vec<Highlight>,
+    /// Measured in another thread after the book is opened, for the progress in the book.
+    pub lengths: Option<ChapterLengths>,
 }
 
-impl EpubBook {
-    /// Prettify current chapter file name. E.g: `EPUB/chapter_1.xml` -> `chapter_1.xml`
-    pub fn current_chapter_file_name(&self, current_chapter_id: &str) -> String {
-        // let current_chapter_id = self.doc.get_current_id().unwrap();
-        let current_chapter_file = self.doc.resources.get(current_chapter_id).unwrap().0.to_str().unwrap();
+impl OpenBook {
+    /// Database row for the current page of the book.
+    pub fn to_db_row(&self, chapter_offset: usize) -> DatabaseRow {
+        DatabaseRow {
+            identity: self.identity.clone(),
+            file: self.file.clone(),
+            filename: self.file.file_name().unwrap().to_os_string(),
+            current_page: self.current_page,
+            chapter_offset,
+            metadata: Some(self.metadata.clone()),
+            bookmarks: self.bookmarks.clone(),
+            highlights: self.highlights.clone(),
+            last_read: Some(chrono::Local::now()),
+            right_to_left: false,
+            progress: self.lengths.as_ref().map(|lengths| lengths.progress(self.current_page, chapter_offset))
+        }
+    }
+    
+    pub fn num_pages(&self) -> usize {
+        self.doc.num_sections()
+    }
+    
+    /// Change the current page. Returns `false` and does nothing if there's no such page.
+    pub fn set_current_page(&mut self, num: usize) -> bool {
+        if num >= self.num_pages() {
+            return false;
+        }
+        
+        self.current_page = num;
+        true
+    }
+    
+    pub fn current_section(&self) -> &Section {
+        &self.doc.sections()[self.current_page]
+    }
+    
+    /// Text of the element the `href` points to, e.g. the footnote of `notes.xhtml#note-1`.
+    /// Relative hrefs are resolved against the chapter `page` that has the link.
+    pub fn note_text(&mut self, href: &str, page: usize) -> Option<String> {
+        let (path, id) = href.split_once('#')?;
+        let chapter_path = self.doc.sections()[page].path.clone();
+        let path = if path.is_empty() {
+            chapter_path
+        } else {
+            resolve_href(&chapter_path, path)
+        };
+        
+        let content = self.doc.resource(&path)?;
+        let document = Document::from(String::from_utf8_lossy(&content).as_ref());
+        let id = percent_decode(id);
+        let note = document.select(Attr("id", id.as_str())).next()?;
         
-        current_chapter_file
-            .replace("EPUB/", "")
-            .replace("OEBPS/", "")
+        Some(note.text().split_ascii_whitespace().collect::<Vec<_>>().join(" "))
     }
 }
 
@@ -43,20 +116,59 @@ pub struct AppOp {
     pub ui: ui::UI,
     pub settings: Arc<RwLock<Settings>>,
     pub db: Arc<RwLock<Database>>,
+    pub keymap: Keymap,
     
-    pub epub_book: Option<EpubBook>,
+    pub book: Option<OpenBook>,
+    /// Open comic book, only one of it and `book` is open at a time.
+    pub comic_book: Option<ComicBook>,
+    /// Anchor to scroll to after the requested page has been opened.
+    pub pending_anchor: Option<String>,
+    /// Char offset to scroll to once the chapter is opened.
+    pub pending_offset: Option<usize>,
+    /// Match of the book search to show once the chapter is opened.
+    pub pending_search_hit: Option<usize>,
+    /// Show the last screen page of the chapter once it's opened, when paging backwards.
+    pub pending_last_screen_page: bool,
+    /// Query of the latest book search, the results of older searches are ignored.
+    pub search_query: String,
+    pub search_id: u64,
+    pub search_cancel: Option<Arc<AtomicBool>>,
+    /// Library archive waiting for the user to confirm the import.
+    pub pending_import: Option<LibraryArchive>,
+    /// Problems with reading the saved data, shown in `init`.
+    pub storage_errors: Vec<String>,
     
     pub open_page_sender: Option<glib::Sender<usize>>,
-    pub open_epub_book_sender: Option<glib::Sender<EpubBook>>,
+    pub open_book_sender: Option<glib::Sender<OpenBook>>,
+    pub open_comic_book_sender: Option<glib::Sender<ComicBook>>,
+    pub open_file_failed_sender: Option<glib::Sender<String>>,
+    pub scanned_book_sender: Option<glib::Sender<ScannedBook>>,
+    pub search_sender: Option<glib::Sender<SearchEvent>>,
+    pub chapter_lengths_sender: Option<glib::Sender<ChapterLengths>>,
+    /// Measures the reading speed for the time left estimates.
+    pub reading_clock: ReadingClock,
+    /// Tells the command line import that the library is in use, `None` if another reader has it.
+    pub profile_lock: Option<ProfileLock>,
 }
 
 impl AppOp {
     pub fn new(ui: ui::UI, app_runtime: AppRuntime) -> AppOp {
         debug!("appop::new");
         
+        // Errors are shown to the user once the window is up.
+        let mut storage_errors = vec![];
+        
+        let profile_lock = ProfileLock::acquire().unwrap_or_else(|err| {
+            error!("Cannot lock the profile. {}", err);
+            None
+        });
+        
         let settings = Arc::new(
             RwLock::new(
-                Settings::open().expect("Failed to open settings file.")
+                Settings::open().unwrap_or_else(|err| {
+                    storage_errors.push(err.to_string());
+                    Settings::new()
+                })
             )
         );
 
@@ -83,16 +195,42 @@ impl AppOp {
             }
         });
         
-        let db = Arc::new(RwLock::new(read_database()));
+        let db = Arc::new(RwLock::new(read_database().unwrap_or_else(|err| {
+            storage_errors.push(err.to_string());
+            Database::new(Some(vec![]))
+        })));
+        
+        let keymap = Keymap::open().unwrap_or_else(|err| {
+            storage_errors.push(err.to_string());
+            Keymap::new()
+        });
         
         AppOp {
             app_runtime,
             ui,
             settings,
             db,
-            epub_book: None,
+            keymap,
+            book: None,
+            comic_book: None,
+            pending_anchor: None,
+            pending_offset: None,
+            pending_search_hit: None,
+            pending_last_screen_page: false,
+            search_query: String::new(),
+            search_id: 0,
+            search_cancel: None,
+            pending_import: None,
+            storage_errors,
             open_page_sender: None,
-            open_epub_book_sender: None,
+            open_book_sender: None,
+            open_comic_book_sender: None,
+            open_file_failed_sender: None,
+            scanned_book_sender: None,
+            search_sender: None,
+            chapter_lengths_sender: None,
+            reading_clock: ReadingClock::default(),
+            profile_lock,
         }
     }
     
@@ -109,13 +247,38 @@ impl AppOp {
                 if let Err(err) = window_state.write() {
                     error!("Cannot save the window state: {:?}", err);
                 }
+                
+                // Write the db right away since the app is closing and
+                // the saving thread might not finish.
+                state.save_position();
+                state.db.write().write_database();
             });
 
             Inhibit(false)
         }));
         
+        // Save the reading position every now and then in case the app doesn't close cleanly.
+        glib::timeout_add_seconds_local(SAVE_POSITION_INTERVAL, glib::clone!(@strong self.app_runtime as app_runtime => move || {
+            app_runtime.update_state_with(|state| {
+                state.save_position();
+            });
+            
+            glib::Continue(true)
+        }));
+        
         self.open_page_sender = Some(self.open_page_message());
-        self.open_epub_book_sender = Some(self.open_epub_book_message());
+        self.open_book_sender = Some(self.open_book_message());
+        self.open_comic_book_sender = Some(self.open_comic_book_message());
+        self.open_file_failed_sender = Some(self.open_file_failed_message());
+        self.scanned_book_sender = Some(self.scanned_book_message());
+        self.search_sender = Some(self.search_message());
+        self.chapter_lengths_sender = Some(self.chapter_lengths_message());
+        
+        self.scan_library();
+        
+        for message in self.storage_errors.drain(..) {
+            self.ui.error_dialog(&message);
+        }
     }
     
     pub fn quit(&self) {
@@ -124,8 +287,12 @@ impl AppOp {
     
     /// Open the next page.
     pub fn next_page(&mut self) {
-        if let Some(book) = &mut self.epub_book {
-            let num = book.doc.get_current_page() + 1;
+        if let Some(comic) = &self.comic_book {
+            let num = comic.current_page + 1;
+            self.open_page_send(num);
+        }
+        if let Some(book) = &mut self.book {
+            let num = book.current_page + 1;
             self.open_page_send(num);
         }
     }
@@ -133,8 +300,14 @@ impl AppOp {
     /// Open the previous page and check that the page number doesn't
     /// go below 0 because `usize` cannot handle that.
     pub fn previous_page(&mut self) {
-        if let Some(book) = &mut self.epub_book {
-            let current_page = book.doc.get_current_page();
+        if let Some(comic) = &self.comic_book {
+            if comic.current_page > 0 {
+                let num = comic.current_page - 1;
+                self.open_page_send(num);
+            }
+        }
+        if let Some(book) = &mut self.book {
+            let current_page = book.current_page;
             // Do nothing if current page is already at 0
             if current_page == 0 {
                 return;
@@ -145,43 +318,101 @@ impl AppOp {
         }
     }
     
+    /// Turn the page towards the left, which is the next page in right-to-left comics.
+    /// The paginated reader turns the screen pages.
+    pub fn page_left(&mut self) {
+        if self.ui.is_paginated() {
+            self.previous_screen_page();
+        } else if self.comic_book.as_ref().map_or(false, |comic| comic.right_to_left) {
+            self.next_page();
+        } else {
+            self.previous_page();
+        }
+    }
+    
+    /// Turn the page towards the right, which is the previous page in right-to-left comics.
+    /// The paginated reader turns the screen pages.
+    pub fn page_right(&mut self) {
+        if self.ui.is_paginated() {
+            self.next_screen_page();
+        } else if self.comic_book.as_ref().map_or(false, |comic| comic.right_to_left) {
+            self.previous_page();
+        } else {
+            self.next_page();
+        }
+    }
+    
     pub fn toggle_page_dialog(&mut self) {
-        if let Some(book) = &mut self.epub_book {
+        if let Some(comic) = &self.comic_book {
+            self.ui.page_dialog(
+                self.app_runtime.clone(),
+                comic.current_page,
+                comic.num_pages()
+            );
+        }
+        if let Some(book) = &mut self.book {
             self.ui.page_dialog(
                 self.app_runtime.clone(),
-                book.doc.get_current_page(),
-                book.doc.get_num_pages()
+                book.current_page,
+                book.num_pages()
             );
         }
     }
     
     /// Open a new file from `PathBuf` in a new thread.
-    /// Does nothing if the exact file is already open.
+    /// Only opens the `initial_page` if the exact file is already open.
     pub fn open_file_from_path(&mut self, file: PathBuf, initial_page: Option<usize>) {
-        // If the file is already open then do nothing
-        if let Some(book) = self.epub_book.as_ref() {
-            if book.file == file {
-                return;
+        // If the file is already open then only change the page
+        let is_open = self.book.as_ref().map_or(false, |book| book.file == file)
+            || self.comic_book.as_ref().map_or(false, |comic| comic.file == file);
+        if is_open {
+            if let Some(num) = initial_page {
+                self.open_page_send(num);
             }
+            return;
         }
     
         debug!("Open file from path: {:?} | initial page: {:?}", file, initial_page);
         
-        if let Some(extension) = get_extension_from_path(&file) {
-            if extension == "epub" {
-                // Open epub file in a new thread and then send
-                // the result back to the main thread via a message.
-                // Correct file extension so hide the left revealer if visible
-                // and then show the loading indicator spinner.
-                self.ui.left_revealer.set_reveal_child(false);
-                self.ui.toggle_spinner(true);
-                
-                let tx = self.open_epub_book_sender.as_ref().unwrap().clone();
-                thread::spawn(move || {
-                    open_epub_file(file, initial_page, tx);
-                });
-            }
+        let format = match library::BookFormat::from_path(&file) {
+            Some(format) => format,
+            None => return,
+        };
+        
+        // Open the book in a new thread and then send
+        // the result back to the main thread via a message.
+        // Correct file extension so hide the left revealer if visible
+        // and then show the loading indicator spinner.
+        self.ui.left_revealer.set_reveal_child(false);
+        self.ui.toggle_spinner(true);
+        
+        let failed_tx = self.open_file_failed_sender.as_ref().unwrap().clone();
+        if format == library::BookFormat::Comic {
+            let tx = self.open_comic_book_sender.as_ref().unwrap().clone();
+            thread::spawn(move || {
+                open_comic_file(file, initial_page, tx, failed_tx);
+            });
+        } else {
+            let tx = self.open_book_sender.as_ref().unwrap().clone();
+            thread::spawn(move || {
+                open_book_file(file, initial_page, tx, failed_tx);
+            });
+        }
+    }
+    
+    /// Used for receiving the error from the thread in which the file could not be opened.
+    /// Stops the loading and brings the library back so another book can be picked.
+    pub fn post_open_file_failed(&mut self, message: String) {
+        self.ui.toggle_spinner(false);
+        
+        if !self.ui.left_revealer.is_visible() {
+            self.ui.left_revealer.set_visible(true);
+        }
+        if self.book.is_none() && self.comic_book.is_none() {
+            self.ui.left_revealer.set_reveal_child(true);
         }
+        
+        self.ui.error_dialog(&message);
     }
     
     /// Things to do after a file is open.
@@ -189,46 +420,84 @@ impl AppOp {
     ///
     /// Opens the correct page from either the database or `book.initial_page`.
     pub fn handle_open_file(&mut self) {
-        if let Some(book) = self.epub_book.as_mut() {
+        // Row to save if the book was found in the db with a different file or identity.
+        let mut changed_row = None;
+        
+        if let Some(book) = self.book.as_mut() {
             // Open the page that was open previously if found in the db
-            let db_row = self.db.read().get_by_row_file(&book.file);
-            if let Some(row) = db_row {
-                // If the `initial_page` was set in `EpubBook` then open that page
-                // instead of looking for the page number in the database.
-
-                debug!("Handle open file from path: {:?} | initial page: {:?}", book.file, book.initial_page);
+            let db_row = self.db.read().get_by_book(&book.identity, &book.file);
+            // Position inside the page to restore after it has been rendered.
+            let mut chapter_offset = 0;
+            if let Some(row) = &db_row {
+                book.bookmarks = row.bookmarks.clone();
+                book.highlights = row.highlights.clone();
                 
-                if let Some(num) = book.initial_page {
-                    // Set the requested page or fall back to 0 in case of bad page number.
-                    match book.doc.set_current_page(num) {
-                        Ok(_) => {},
-                        Err(_) => {
-                            book.doc.set_current_page(0).expect("Cannot set the page to 0 o.O");
-                        }
-                    }
-                } else {
-                    let page_pref = self.settings.read().file.file_open_preference.to_usize();
-                    match book.doc.set_current_page(row.current_page + page_pref) {
-                        Ok(_) => {},
-                        Err(_) => {
-                            // Open the last page since the previous try failed.
-                            book.doc.set_current_page(book.doc.get_num_pages() - 1).expect("Bad page o.O");
-                        }
-                    }
+                // The book was moved or renamed since it was last open, remember the new location.
+                // Rows from before books had an identity get it here too.
+                if row.file != book.file || row.identity != book.identity {
+                    changed_row = Some(DatabaseRow {
+                        identity: book.identity.clone(),
+                        file: book.file.clone(),
+                        filename: book.file.file_name().unwrap().to_os_string(),
+                        ..row.clone()
+                    });
+                }
+            }
+            
+            debug!("Handle open file from path: {:?} | initial page: {:?}", book.file, book.initial_page);
+            
+            // If the `initial_page` was set in `OpenBook` then open that page
+            // instead of looking for the page number in the database.
+            // It's used for the books that aren't in the database yet too.
+            if let Some(num) = book.initial_page {
+                // Set the requested page or fall back to 0 in case of bad page number.
+                if !book.set_current_page(num) {
+                    book.current_page = 0;
+                }
+            } else if let Some(row) = &db_row {
+                let file_open_preference = self.settings.read().file.file_open_preference.clone();
+                if file_open_preference == FileOpenPreference::ExactPosition {
+                    chapter_offset = row.chapter_offset;
+                }
+                
+                if !book.set_current_page(row.current_page + file_open_preference.to_usize()) {
+                    // Open the last page since the previous try failed.
+                    book.current_page = book.num_pages() - 1;
+                    chapter_offset = 0;
                 }
             }
             
             self.ui.update_toc(self.app_runtime.clone(), book);
+            self.ui.update_bookmarks(self.app_runtime.clone(), &book.bookmarks);
+            self.ui.update_highlights(&book.highlights);
             self.ui.update(book, &self.settings.read());
-            self.ui.scroller.vadjustment().set_value(0.0);
+            self.ui.scroll_to_top();
+            
+            if chapter_offset > 0 {
+                self.after_layout(move |state| {
+                    state.ui.scroll_to_offset(chapter_offset as i32);
+                });
+            }
+        }
+        
+        if let Some(row) = changed_row {
+            self.update_db(row);
         }
     }
     
-    /// Used for receiving the `EpubBook` result from another thread
+    /// Used for receiving the `OpenBook` result from another thread
     /// in which the file was opened.
-    pub fn post_open_epub_book(&mut self, book: EpubBook) {
-        self.epub_book = Some(book);
+    pub fn post_open_book(&mut self, book: OpenBook) {
+        // Keep the exact position in the book that was open
+        self.save_position();
+        if self.comic_book.take().is_some() {
+            self.ui.set_comic_mode(false);
+        }
+        self.book = Some(book);
         self.handle_open_file();
+        // Results of the previous book are no use anymore
+        self.search_book(String::new());
+        self.measure_book();
         
         // Turn the spinner off
         self.ui.toggle_spinner(false);
@@ -244,46 +513,201 @@ impl AppOp {
     ///
     /// TODO: Do most of this stuff in another fn and it should probably trigger at page end and file open in addition of opening a new page.
     pub fn open_page(&mut self, num: usize) {
-        if let Some(book) = self.epub_book.as_mut() {
+        if self.comic_book.is_some() {
+            self.open_comic_page(num);
+            return;
+        }
+        
+        if let Some(book) = self.book.as_mut() {
             // Do nothing if the requested page is already open
-            if book.doc.get_current_page() != num {
+            if book.current_page != num {
                 // Do nothing if trying to set a bad page number
-                match book.doc.set_current_page(num) {
-                    Ok(_) => {},
-                    Err(_) => {
-                        return;
-                    }
+                if !book.set_current_page(num) {
+                    return;
                 }
                 
                 // Update reader ui
                 self.ui.update(book, &self.settings.read());
                 self.ui.scroll_to_top();
+                if mem::take(&mut self.pending_last_screen_page) {
+                    self.ui.scroll_to_bottom();
+                }
     
-                let file = book.file.clone();
-                let filename = book.file.file_name().unwrap().to_os_string();
                 // Update database
-                self.update_db(file, filename, num);
+                let row = book.to_db_row(0);
+                self.update_db(row);
+                
+                if let Some(anchor) = self.pending_anchor.take() {
+                    self.scroll_to_anchor(anchor);
+                }
+                if let Some(offset) = self.pending_offset.take() {
+                    self.after_layout(move |state| {
+                        state.ui.scroll_to_offset(offset as i32);
+                    });
+                }
+                if let Some(occurrence) = self.pending_search_hit.take() {
+                    let query = self.search_query.clone();
+                    self.after_layout(move |state| {
+                        state.ui.show_search_hit(&query, occurrence);
+                    });
+                }
+                self.update_progress();
+            }
+        }
+    }
+    
+    /// Follow the link at the char `offset` in the reader, if there is one.
+    pub fn open_link_at(&mut self, offset: i32) {
+        let (chapter, chapter_offset) = match self.ui.chapter_at(offset) {
+            Some(chapter) => chapter,
+            None => return,
+        };
+        let page = chapter.page;
+        
+        if let Some(link) = chapter.rendered.link_at(chapter_offset) {
+            let href = link.href.clone();
+            if link.is_noteref {
+                let start = offset - chapter_offset + link.start;
+                self.open_footnote(start, &href, page);
+            } else {
+                self.open_link(&href, page);
+            }
+        }
+    }
+    
+    /// Show the note that the noteref at the char `offset` points to in a popover.
+    /// Follows the link instead if the note cannot be found.
+    pub fn open_footnote(&mut self, offset: i32, href: &str, page: usize) {
+        let text = self.book
+            .as_mut()
+            .and_then(|book| book.note_text(href, page));
+        
+        match text {
+            Some(text) if !text.is_empty() => self.ui.footnote_popover(offset, &text),
+            _ => self.open_link(href, page),
+        }
+    }
+    
+    /// Open the chapter the `href` in the chapter `page` points to and scroll to its fragment.
+    /// External links are only shown so they can be copied.
+    pub fn open_link(&mut self, href: &str, page: usize) {
+        if is_external_link(href) {
+            self.ui.external_link_dialog(href);
+            return;
+        }
+        
+        let book = match self.book.as_ref() {
+            Some(book) => book,
+            None => return,
+        };
+        
+        let (path, fragment) = match href.split_once('#') {
+            Some((path, fragment)) => (path, Some(percent_decode(fragment))),
+            None => (href, None),
+        };
+        
+        // Link inside the same chapter
+        let chapter = if path.is_empty() {
+            Some(page)
+        } else {
+            let chapter_path = &book.doc.sections()[page].path;
+            book.doc.section_index(&resolve_href(chapter_path, path))
+        };
+        
+        match chapter {
+            Some(num) => self.open_page_at_anchor(num, fragment),
+            None => {
+                warn!("Link target is not in the spine: {}", href);
+            }
+        }
+    }
+    
+    /// Open the page `num` and scroll to the element with the id `anchor`.
+    /// Only scrolls if the page is already open.
+    pub fn open_page_at_anchor(&mut self, num: usize, anchor: Option<String>) {
+        let current_page = match self.book.as_ref() {
+            Some(book) => book.current_page,
+            None => return,
+        };
+        
+        if num == current_page {
+            match anchor {
+                Some(anchor) => self.ui.scroll_to_anchor(&anchor),
+                None => self.ui.scroll_to_top(),
             }
+        } else {
+            self.pending_anchor = anchor;
+            self.open_page_send(num);
         }
     }
     
-    pub fn update_db(&mut self, file: PathBuf, filename: OsString, current_page: usize) {
-        let db = {
-            self.db.read().clone()
+    /// Open the page `num` and scroll to the char `offset` in it.
+    pub fn open_page_at_offset(&mut self, num: usize, offset: usize) {
+        let current_page = match self.book.as_ref() {
+            Some(book) => book.current_page,
+            None => return,
+        };
+        
+        if num == current_page {
+            self.ui.scroll_to_offset(offset as i32);
+        } else {
+            self.pending_offset = Some(offset);
+            self.open_page_send(num);
+        }
+    }
+    
+    /// Scroll to the `anchor` once the chapter that was just rendered has been laid out.
+    pub fn scroll_to_anchor(&self, anchor: String) {
+        self.after_layout(move |state| {
+            state.ui.scroll_to_anchor(&anchor);
+        });
+    }
+    
+    /// Run `f` once the chapter that was just rendered has been laid out.
+    /// Before that the positions of the text in the reader are not known.
+    pub fn after_layout(&self, f: impl FnOnce(&mut AppOp) + 'static) {
+        let app_runtime = self.app_runtime.clone();
+        let mut f = Some(f);
+        glib::idle_add_local(move || {
+            if let Some(f) = f.take() {
+                app_runtime.update_state_with(f);
+            }
+            
+            glib::Continue(false)
+        });
+    }
+    
+    /// Save the current page and the position inside it to the database.
+    /// Does nothing if the position hasn't changed since the last save.
+    pub fn save_position(&mut self) {
+        self.follow_chapter_in_view();
+        let new_row = match self.book.as_ref() {
+            Some(book) => book.to_db_row(self.ui.offset_at_top() as usize),
+            None => return,
+        };
+        
+        if let Some(row) = self.db.read().get_by_book(&new_row.identity, &new_row.file) {
+            if row.current_page == new_row.current_page && row.chapter_offset == new_row.chapter_offset {
+                return;
+            }
+        }
+        
+        self.update_db(new_row);
+    }
+    
+    pub fn update_db(&mut self, new_row: DatabaseRow) {
+        let (db, index) = {
+            let db = self.db.read();
+            (db.clone(), db.position(&new_row.identity, &new_row.file))
         };
         
         // Add or replace `DatabaseRow` in `Database`.
         if let Some(mut rows) = db.rows {
-            // New row that will be either replace an existing one
-            // or be added as a new one
-            let new_row = DatabaseRow {
-                file,
-                filename: filename.clone(),
-                current_page
-            };
-        
+            // The library only changes when a book is added or it looks different
+            let library_changed = index.map_or(true, |index| !ui::library::is_same_item(&rows[index], &new_row));
+            
             // Update or add a row in db
-            if let Some(index) = rows.iter().position(|n| n.filename == filename) {
+            if let Some(index) = index {
                 // Replace some data in memory
                 let _ = mem::replace(&mut rows[index], new_row);
                 // Update the rows in db
@@ -298,7 +722,126 @@ impl AppOp {
             // Save to a file
             self.save_to_file();
             
+            if library_changed {
+                self.refresh_recent();
+            }
+        }
+    }
+    
+    /// Rebuild the library if it's shown, otherwise the next time it's shown.
+    pub fn refresh_recent(&mut self) {
+        if self.ui.is_recent_shown() {
+            let rows = self.db.read().rows.clone().unwrap_or_default();
             self.ui.update_recent(self.app_runtime.clone(), &rows);
+        } else {
+            self.ui.recent_outdated = true;
+        }
+    }
+    
+    /// Rebuild the library if the books changed while it was hidden.
+    pub fn refresh_outdated_recent(&mut self) {
+        if self.ui.recent_outdated {
+            self.refresh_recent();
+        }
+    }
+    
+    /// Read the metadata of the books in the db that don't have it yet in a new thread.
+    pub fn scan_library(&self) {
+        let rows = self.db.read().rows.clone().unwrap_or_default();
+        if rows.iter().all(|row| row.metadata.is_some()) {
+            return;
+        }
+        
+        let tx = self.scanned_book_sender.as_ref().unwrap().clone();
+        thread::spawn(move || {
+            library::scan_missing_metadata(rows, tx);
+        });
+    }
+    
+    /// Used for receiving the metadata of a book in the db from another thread.
+    pub fn post_scanned_book(&mut self, scanned: ScannedBook) {
+        {
+            let mut db = self.db.write();
+            let row = db.rows
+                .as_mut()
+                .and_then(|rows| rows.iter_mut().find(|row| row.file == scanned.file));
+            if let Some(row) = row {
+                row.identity = scanned.identity;
+                row.metadata = Some(scanned.metadata);
+            }
+        }
+        
+        self.save_to_file();
+        self.refresh_recent();
+    }
+    
+    /// Search the whole open book for `query` in a new thread.
+    /// The results are added to the search panel as they're found.
+    /// An empty `query` only stops the previous search.
+    pub fn search_book(&mut self, query: String) {
+        if let Some(cancel) = self.search_cancel.take() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        self.search_id += 1;
+        self.search_query = query.trim().to_string();
+        self.ui.clear_search_results();
+        
+        let file = match self.book.as_ref() {
+            Some(book) if !self.search_query.is_empty() => book.file.clone(),
+            _ => return,
+        };
+        
+        self.ui.set_search_status("Searching...");
+        
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.search_cancel = Some(cancel.clone());
+        
+        let query = self.search_query.clone();
+        let search_id = self.search_id;
+        let tx = self.search_sender.as_ref().unwrap().clone();
+        thread::spawn(move || {
+            search::search_book(file, query, search_id, cancel, tx);
+        });
+    }
+    
+    /// Used for receiving the book search results from another thread.
+    pub fn post_search_event(&mut self, event: SearchEvent) {
+        match event {
+            SearchEvent::Hit(search_id, hit) if search_id == self.search_id => {
+                self.ui.add_search_result(hit);
+                self.ui.set_search_status(&format!("Searching... {} found", self.ui.search_hits.len()));
+            },
+            SearchEvent::Finished(search_id) if search_id == self.search_id => {
+                self.search_cancel = None;
+                let status = match self.ui.search_hits.len() {
+                    0 => "No results".to_string(),
+                    1 => "1 result".to_string(),
+                    count => format!("{} results", count),
+                };
+                self.ui.set_search_status(&status);
+            },
+            // Results of an older search
+            _ => {},
+        }
+    }
+    
+    /// Open the chapter of the search result at `index`, scroll to the match and highlight it.
+    pub fn open_search_hit(&mut self, index: usize) {
+        let hit = match self.ui.search_hits.get(index) {
+            Some(hit) => hit.clone(),
+            None => return,
+        };
+        let current_page = match self.book.as_ref() {
+            Some(book) => book.current_page,
+            None => return,
+        };
+        
+        // The occurrences are counted in the chapter, so it should be the only one in the reader
+        if hit.page == current_page && self.ui.chapters.len() == 1 {
+            self.ui.show_search_hit(&self.search_query, hit.occurrence);
+        } else {
+            self.pending_search_hit = Some(hit.occurrence);
+            self.open_page_send(hit.page);
         }
     }
     
@@ -314,37 +857,67 @@ impl AppOp {
     }
 }
 
-/// Get a file extension from a filename
-/// E.g: `foo.txt` -> `txt`.
-fn get_extension_from_path(filename: &Path) -> Option<&str> {
-    filename
-        .extension()
-        .and_then(OsStr::to_str)
+/// Links that point outside of the book.
+fn is_external_link(href: &str) -> bool {
+    let href = href.to_lowercase();
+    
+    ["http://", "https://", "mailto:", "ftp://"]
+        .iter()
+        .any(|scheme| href.starts_with(scheme))
 }
 
 /// This method is used in non-main thread.
-/// It will open an epub file from `PathBuf` and sends the result
-/// to the main thread.
-fn open_epub_file(file: PathBuf, initial_page: Option<usize>, tx: glib::Sender<EpubBook>) {
-    let epub_doc = EpubDoc::new(&file);
-    match epub_doc {
-        Ok(doc) => {
-            let book = EpubBook {
+/// It will open a book from `PathBuf` and sends the result
+/// to the main thread, or the error to `failed_tx` if it can't be opened.
+fn open_book_file(file: PathBuf, initial_page: Option<usize>, tx: glib::Sender<OpenBook>, failed_tx: glib::Sender<String>) {
+    match library::open_book(&file) {
+        Ok(mut doc) => {
+            let identity = library::book_identity(doc.as_ref(), &file);
+            let metadata = library::read_metadata(doc.as_mut(), &identity);
+            
+            let book = OpenBook {
                 file,
                 doc,
-                initial_page
+                current_page: 0,
+                initial_page,
+                identity,
+                metadata,
+                bookmarks: vec![],
+                highlights: vec![],
+                lengths: None
             };
     
             match tx.send(book) {
                 Ok(_) => {},
                 Err(e) => {
-                    error!("Could not send `open_epub_book_message` from another thread!");
+                    error!("Could not send `open_book_message` from another thread!");
                     error!("{}", e);
                 }
             }
         },
         Err(e) => {
-            error!("Cannot open epub file: {:?}. Error: {}", file, e);
+            error!("Cannot open book file: {:?}. Error: {}", file, e);
+            let _ = failed_tx.send(format!("Could not open {}. {}", file.display(), e));
         }
     }
-}
\ No newline at end of file
+}
+
+/// This method is used in non-main thread.
+/// It will open a comic book from `PathBuf` and sends the result
+/// to the main thread, or the error to `failed_tx` if it can't be opened.
+fn open_comic_file(file: PathBuf, initial_page: Option<usize>, tx: glib::Sender<ComicBook>, failed_tx: glib::Sender<String>) {
+    match ComicBook::open(&file) {
+        Ok(mut comic) => {
+            comic.initial_page = initial_page;
+            
+            if let Err(e) = tx.send(comic) {
+                error!("Could not send `open_comic_book_message` from another thread!");
+                error!("{}", e);
+            }
+        },
+        Err(e) => {
+            error!("Cannot open comic book: {:?}. Error: {}", file, e);
+            let _ = failed_tx.send(format!("Could not open {}. {}", file.display(), e));
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use gtk::prelude::*;
+use crate::appop::AppOp;
+
+
+impl AppOp {
+    /// Show the next screen page of the paginated reader, or the next chapter after the last one.
+    pub fn next_screen_page(&mut self) {
+        if !self.ui.next_screen_page() {
+            self.next_page();
+        }
+    }
+
+    /// Show the previous screen page of the paginated reader, or the end of the previous chapter
+    /// before the first one.
+    pub fn previous_screen_page(&mut self) {
+        if self.ui.previous_screen_page() {
+            return;
+        }
+
+        if self.book.as_ref().map_or(false, |book| book.current_page > 0) {
+            self.pending_last_screen_page = true;
+            self.previous_page();
+        }
+    }
+
+    /// Scroll down by `amount`, the paginated reader shows the next screen page instead.
+    pub fn scroll_forward(&mut self, amount: f64) {
+        if self.ui.is_paginated() {
+            self.next_screen_page();
+        } else {
+            self.ui.scroll_down(amount);
+        }
+    }
+
+    /// Scroll up by `amount`, the paginated reader shows the previous screen page instead.
+    pub fn scroll_backward(&mut self, amount: f64) {
+        if self.ui.is_paginated() {
+            self.previous_screen_page();
+        } else {
+            // The continuous reader adds the previous chapter above the top
+            if self.ui.scroller.vadjustment().value() <= 0.0 {
+                self.load_previous_chapter();
+            }
+            self.ui.scroll_up(amount);
+        }
+    }
+
+    /// Switch between the paginated and the scrolling reader and save it to the settings.
+    pub fn toggle_paginated(&mut self) {
+        let mut new_settings = self.settings.read().clone();
+        new_settings.general.paginated = !new_settings.general.paginated;
+
+        self.apply_settings(new_settings);
+    }
+}
@@ -0,0 +1,78 @@
+use std::thread;
+use crate::appop::AppOp;
+use crate::app::progress::{self, ChapterLengths};
+
+
+impl AppOp {
+    /// Measure the chapters of the open book in a new thread, the progress is shown once
+    /// they are known.
+    pub fn measure_book(&mut self) {
+        self.reading_clock.reset();
+        self.ui.hide_progress();
+
+        let file = match self.book.as_ref() {
+            Some(book) => book.file.clone(),
+            None => return,
+        };
+
+        let tx = self.chapter_lengths_sender.as_ref().unwrap().clone();
+        thread::spawn(move || {
+            progress::measure_book(file, tx);
+        });
+    }
+
+    /// Used for receiving the chapter lengths from the thread that measured them.
+    pub fn post_chapter_lengths(&mut self, lengths: ChapterLengths) {
+        let book = match self.book.as_mut() {
+            // Another book may have been opened meanwhile
+            Some(book) if book.file == lengths.file => book,
+            _ => return,
+        };
+        book.lengths = Some(lengths);
+        self.record_chapter_lengths();
+
+        // Save the progress for the library
+        let row = book.to_db_row(self.ui.offset_at_top() as usize);
+        self.update_db(row);
+        self.update_progress();
+    }
+
+    /// Show the progress in the book at the position in view and measure the reading speed.
+    pub fn update_progress(&mut self) {
+        self.record_chapter_lengths();
+
+        let lengths = match self.book.as_ref().and_then(|book| book.lengths.as_ref()) {
+            Some(lengths) => lengths,
+            None => return,
+        };
+        let page = match self.ui.page_at_top() {
+            Some(page) => page,
+            None => return,
+        };
+        let offset = self.ui.offset_at_top() as usize;
+
+        if let Some(measured) = self.reading_clock.update(lengths.position(page, offset)) {
+            let mut db = self.db.write();
+            db.reading_speed = Some(progress::update_speed(db.reading_speed, measured));
+        }
+
+        self.ui.show_progress(
+            lengths.progress(page, offset),
+            lengths.chapter_left(page, offset),
+            lengths.book_left(page, offset),
+            self.db.read().reading_speed
+        );
+    }
+
+    /// Use the lengths of the chapters in the reader, the measured ones can be off after images.
+    fn record_chapter_lengths(&mut self) {
+        let lengths = match self.book.as_mut().and_then(|book| book.lengths.as_mut()) {
+            Some(lengths) => lengths,
+            None => return,
+        };
+
+        for chapter in &self.ui.chapters {
+            lengths.record(chapter.page, chapter.rendered.length as usize);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::appop::AppOp;
 use gtk::prelude::{ComboBoxExt, SpinButtonExt, ToggleButtonExt, EntryExt};
-use crate::app::settings::FileOpenPreference;
+use crate::app::settings::{Settings, FileOpenPreference};
 use std::sync::Arc;
 use parking_lot::lock_api::RwLock;
 use crate::ui::{Theme};
@@ -18,6 +18,8 @@ impl AppOp {
         let auto_scroll_speed = builder.get::<gtk::SpinButton>( "auto_scroll_speed");
         let show_page_num = builder.get::<gtk::CheckButton>( "show_page_num");
         let show_page_file = builder.get::<gtk::CheckButton>( "show_page_file");
+        let paginated = builder.get::<gtk::CheckButton>( "paginated");
+        let continuous = builder.get::<gtk::CheckButton>( "continuous");
         let fontsize = builder.get::<gtk::SpinButton>( "fontsize_spin");
         let fontfamily = builder.get::<gtk::Entry>( "fontfamily_entry");
         let use_custom_color = builder.get::<gtk::CheckButton>( "use_custom_color");
@@ -32,6 +34,8 @@ impl AppOp {
         new_settings.general.auto_scroll_speed = auto_scroll_speed.value();
         new_settings.general.show_page_num = show_page_num.is_active();
         new_settings.general.show_page_file = show_page_file.is_active();
+        new_settings.general.paginated = paginated.is_active();
+        new_settings.general.continuous = continuous.is_active();
         new_settings.general.font_size = fontsize.value();
         new_settings.general.font_family = fontfamily.text().to_string();
         new_settings.general.use_custom_color = use_custom_color.is_active();
@@ -41,18 +45,31 @@ impl AppOp {
         new_settings.file.file_open_preference = FileOpenPreference::from_str(file_open_preference_combobox.active_id().unwrap().as_str()).unwrap();
         
         self.app_runtime.update_state_with(move |state| {
-            state.ui.set_theme(state.settings.read().general.theme.clone(), new_settings.general.theme.clone());
-            state.ui.set_font_family(&new_settings.general.font_family);
-            state.ui.set_custom_colors(new_settings.general.use_custom_color, &new_settings.general.background_color, &new_settings.general.text_color);
-            
-            // Update the reader header if a book is loaded
-            if let Some(book) = state.epub_book.as_mut() {
-                state.ui.update(book, &new_settings);
-            }
-    
-            // Save new settings to file and app
-            new_settings.write().expect("Cannot write to the settings file.");
-            state.settings = Arc::new(RwLock::new(new_settings));
+            state.apply_settings(new_settings);
         });
     }
+    
+    /// Show the `new_settings` in the ui and save them.
+    pub fn apply_settings(&mut self, new_settings: Settings) {
+        self.ui.set_theme(self.settings.read().general.theme.clone(), new_settings.general.theme.clone());
+        self.ui.set_font_family(&new_settings.general.font_family);
+        self.ui.set_custom_colors(new_settings.general.use_custom_color, &new_settings.general.background_color, &new_settings.general.text_color);
+        // Only the current chapter is kept, at the same position
+        self.follow_chapter_in_view();
+        let offset = self.ui.offset_at_top();
+        self.ui.continuous = new_settings.general.continuous;
+        self.ui.set_paginated(new_settings.general.paginated);
+        
+        // Update the reader header if a book is loaded
+        if let Some(book) = self.book.as_mut() {
+            self.ui.update(book, &new_settings);
+            self.after_layout(move |state| state.ui.scroll_to_offset(offset));
+        }
+        
+        // Save new settings to file and app
+        if let Err(err) = new_settings.write() {
+            error!("Cannot write to the settings file. {}", err);
+        }
+        self.settings = Arc::new(RwLock::new(new_settings));
+    }
 }
\ No newline at end of file
@@ -9,19 +9,21 @@ mod ui;
 mod appop;
 
 use std::error::Error;
-use std::path::Path;
+use std::path::PathBuf;
 use std::fs::File;
 use clap::{App, Arg};
 use gtk::prelude::{ApplicationExt, ApplicationExtManual};
-use crate::app::utils::working_dir;
+use crate::app::paths::{self, Location};
 
 pub const PROGRAM_NAME: &str = "Bad Reader";
 pub const VERSION: &str = "1.0";
 pub const ABOUT: &str = "Bad epub reader made with Rust.";
 pub const LOG_FILE: &str = "bad-reader.log";
+/// Name of the running reader on the session bus.
+pub const APP_ID: &str = "com.github.temeez.badreader";
 
 fn setup_logging() -> Result<(), fern::InitError> {
-    let path = Path::new(&working_dir(LOG_FILE)).to_path_buf();
+    let path = paths::path(Location::State, LOG_FILE);
     
     // Clear the log file if it exists
     if path.as_path().exists() {
@@ -53,10 +55,10 @@ fn setup_logging() -> Result<(), fern::InitError> {
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    setup_logging().expect("failed to initialize logging.");
-    
-    let matches = App::new(PROGRAM_NAME)
+/// The command line arguments. The running instance reads the arguments of the instances
+/// started after it from the options added with `add_main_option` instead.
+fn arg_parser() -> App<'static> {
+    App::new(PROGRAM_NAME)
         .version(VERSION)
         .author("Teemu N.")
         .about(ABOUT)
@@ -66,17 +68,124 @@ fn main() -> Result<(), Box<dyn Error>> {
                  .value_name("FILE")
                  .about("Set the epub file to open.")
                  .takes_value(true))
+        .arg(Arg::new("uri")
+                 .value_name("FILE_OR_URI")
+                 .about("A book to open, as a path or a file:// URI. Same as --file.")
+                 .index(1))
         .arg(Arg::new("page")
                  .short('p')
                  .long("page")
                  .value_name("PAGE")
                  .about("Optianlly open a specific page. Page numbers start from zero (0).")
                  .takes_value(true))
-        .get_matches();
+        .arg(Arg::new("portable")
+                 .long("portable")
+                 .about("Keep the settings, database and log next to the executable."))
+        .arg(Arg::new("config-dir")
+                 .long("config-dir")
+                 .value_name("DIR")
+                 .about("Keep the settings, database and log in DIR, e.g. for a separate profile.")
+                 .takes_value(true))
+        .arg(Arg::new("new-instance")
+                 .long("new-instance")
+                 .about("Open a new window instead of handing the book to the reader that is already running."))
+        .subcommand(App::new("export-library")
+                 .about("Export the library, reading positions and settings to a zip archive of JSON files.")
+                 .arg(Arg::new("archive")
+                          .value_name("ARCHIVE")
+                          .about("The archive file to write.")
+                          .required(true)))
+        .subcommand(App::new("import-library")
+                 .about("Merge an exported library into this one. The newest reading position wins.")
+                 .arg(Arg::new("archive")
+                          .value_name("ARCHIVE")
+                          .about("The archive file to read.")
+                          .required(true))
+                 .arg(Arg::new("dry-run")
+                          .long("dry-run")
+                          .about("Only show what would change."))
+                 .arg(Arg::new("include-settings")
+                          .long("include-settings")
+                          .about("Replace the settings with the ones in the archive.")))
+        .subcommand(App::new("info")
+                 .about("Show the metadata, the number of chapters and the table of contents of a book.")
+                 .arg(Arg::new("file")
+                          .value_name("FILE")
+                          .about("The book to inspect.")
+                          .required(true)))
+        .subcommand(App::new("toc")
+                 .about("Show the table of contents of a book with the chapter number of each entry.")
+                 .arg(Arg::new("file")
+                          .value_name("FILE")
+                          .about("The book to inspect.")
+                          .required(true)))
+        .subcommand(App::new("text")
+                 .about("Print the text of a chapter the way the reader shows it.")
+                 .arg(Arg::new("file")
+                          .value_name("FILE")
+                          .about("The book to read.")
+                          .required(true))
+                 .arg(Arg::new("chapter")
+                          .short('c')
+                          .long("chapter")
+                          .value_name("N")
+                          .about("The chapter to print. Chapter numbers start from zero (0).")
+                          .takes_value(true)
+                          .required(true)))
+        .subcommand(App::new("recent")
+                 .about("Show the books in the library and the reading progress, the most recently read first.")
+                 .arg(Arg::new("json")
+                          .long("json")
+                          .about("Print every saved detail of the books as JSON.")))
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = arg_parser().get_matches();
+    
+    paths::init(matches.is_present("portable"), matches.value_of("config-dir").map(PathBuf::from));
+    
+    // The subcommands run without the gui and exit.
+    if let Some((command, args)) = matches.subcommand() {
+        paths::migrate_legacy_files();
+        let path = |name: &str| PathBuf::from(args.value_of(name).unwrap());
+        let result = match command {
+            "export-library" => app::cli::export_library(&path("archive")),
+            "import-library" => app::cli::import_library(
+                &path("archive"),
+                args.is_present("dry-run"),
+                args.is_present("include-settings")
+            ),
+            "info" => app::cli::info(&path("file")),
+            "toc" => app::cli::toc(&path("file")),
+            "text" => match args.value_of("chapter").unwrap().parse::<usize>() {
+                Ok(chapter) => app::cli::text(&path("file"), chapter),
+                Err(_) => {
+                    eprintln!("The chapter must be a number.");
+                    std::process::exit(2);
+                }
+            },
+            "recent" => app::cli::recent(args.is_present("json")),
+            _ => Ok(()),
+        };
         
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(err.exit_code());
+        }
+        return Ok(());
+    }
+    
+    // The files and the page in the arguments are handled by the instance that is already running,
+    // if there is one, and this one exits. Otherwise this instance handles them in `on_command_line`.
+    // A reader with its own directory has its own library, so it's never the same reader as the default one.
+    let mut flags = gio::ApplicationFlags::HANDLES_OPEN | gio::ApplicationFlags::HANDLES_COMMAND_LINE;
+    if matches.is_present("new-instance") || paths::is_single_dir() {
+        flags |= gio::ApplicationFlags::NON_UNIQUE;
+    }
+    
     let application = gtk::Application::new(
-        Some("com.github.temeez.badreader"),
-        gio::ApplicationFlags::FLAGS_NONE,
+        Some(APP_ID),
+        flags,
     );
     
     // Make the gtk app acknowledge the arguments defined above.
@@ -98,11 +207,44 @@ fn main() -> Result<(), Box<dyn Error>> {
         Some("dummy")
     );
     
-    application.connect_startup(move |application| {
-        app::on_startup(application, &matches);
+    application.add_main_option(
+        "portable",
+        glib::Char::from(0u8),
+        glib::OptionFlags::IN_MAIN,
+        glib::OptionArg::None,
+        "dummy",
+        None
+    );
+    application.add_main_option(
+        "config-dir",
+        glib::Char::from(0u8),
+        glib::OptionFlags::IN_MAIN,
+        glib::OptionArg::Filename,
+        "dummy",
+        Some("dummy")
+    );
+    application.add_main_option(
+        "new-instance",
+        glib::Char::from(0u8),
+        glib::OptionFlags::IN_MAIN,
+        glib::OptionArg::None,
+        "dummy",
+        None
+    );
+    
+    // Startup only happens in the instance that opens the window, the log
+    // of the running instance is not cleared when another one hands a book to it.
+    application.connect_startup(|application| {
+        setup_logging().expect("failed to initialize logging.");
+        paths::migrate_legacy_files();
+        
+        app::on_startup(application);
     });
     
-    application.run();
+    let status = application.run();
+    if status != 0 {
+        std::process::exit(status);
+    }
     
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,165 @@
+use super::UI;
+
+use gtk::prelude::*;
+use gtk::ResponseType;
+use crate::PROGRAM_NAME;
+use crate::app::AppRuntime;
+use crate::app::database::Bookmark;
+use crate::app::utils::BuilderExtManualCustom;
+
+/// Shown before the header text when the position is bookmarked.
+const BOOKMARK_MARKER: &str = "🔖 ";
+
+
+impl UI {
+    pub fn connect_bookmarks(&self, app_runtime: AppRuntime) {
+        let bookmarks_list = self.builder.get::<gtk::ListBox>("bookmarks_list");
+        bookmarks_list.connect_row_activated(glib::clone!(@strong app_runtime => move |_, row| {
+            let index = row.index() as usize;
+            app_runtime.update_state_with(move |state| {
+                state.open_bookmark(index);
+            });
+        }));
+
+        // Keep the header marker up to date when scrolling and when the page changes
+        let adj = self.scroller.vadjustment();
+        adj.connect_value_changed(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.update_bookmark_marker());
+        }));
+        adj.connect_changed(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.update_bookmark_marker());
+        }));
+    }
+
+    /// Update the list of the bookmarks of the open book.
+    pub fn update_bookmarks(&mut self, app_runtime: AppRuntime, bookmarks: &[Bookmark]) {
+        let bookmarks_list = self.builder.get::<gtk::ListBox>("bookmarks_list");
+        for child in bookmarks_list.children() {
+            bookmarks_list.remove(&child);
+        }
+
+        for (index, bookmark) in bookmarks.iter().enumerate() {
+            let default_label = format!("Page {}", bookmark.page + 1);
+            let title = cascade! {
+                gtk::Label::new(Some(bookmark.label.as_deref().unwrap_or(&default_label)));
+                ..set_xalign(0.0);
+                ..set_ellipsize(gtk::pango::EllipsizeMode::End);
+            };
+            title.style_context().add_class("bookmark-title");
+
+            let details = cascade! {
+                gtk::Label::new(Some(&format!(
+                    "Page {} - {}",
+                    bookmark.page + 1,
+                    bookmark.created.format("%Y-%m-%d %H:%M")
+                )));
+                ..set_xalign(0.0);
+            };
+            details.style_context().add_class("bookmark-details");
+
+            let text = cascade! {
+                gtk::Box::new(gtk::Orientation::Vertical, 2);
+                ..pack_start(&title, false, false, 0);
+                ..pack_start(&details, false, false, 0);
+            };
+
+            let rename = cascade! {
+                gtk::Button::from_icon_name(Some("document-edit-symbolic"), gtk::IconSize::Button);
+                ..set_relief(gtk::ReliefStyle::None);
+                ..set_tooltip_text(Some("Rename"));
+                ..set_valign(gtk::Align::Center);
+                ..connect_clicked(glib::clone!(@strong app_runtime => move |_| {
+                    app_runtime.update_state_with(move |state| {
+                        state.toggle_rename_bookmark_dialog(index);
+                    });
+                }));
+            };
+
+            let delete = cascade! {
+                gtk::Button::from_icon_name(Some("user-trash-symbolic"), gtk::IconSize::Button);
+                ..set_relief(gtk::ReliefStyle::None);
+                ..set_tooltip_text(Some("Delete"));
+                ..set_valign(gtk::Align::Center);
+                ..connect_clicked(glib::clone!(@strong app_runtime => move |_| {
+                    app_runtime.update_state_with(move |state| {
+                        state.delete_bookmark(index);
+                    });
+                }));
+            };
+
+            let content = cascade! {
+                gtk::Box::new(gtk::Orientation::Horizontal, 4);
+                ..set_margin_start(10);
+                ..set_margin_end(6);
+                ..set_margin_top(6);
+                ..set_margin_bottom(6);
+                ..pack_start(&text, true, true, 0);
+                ..pack_end(&delete, false, false, 0);
+                ..pack_end(&rename, false, false, 0);
+            };
+
+            let row = cascade! {
+                gtk::ListBoxRow::new();
+                ..add(&content);
+            };
+            bookmarks_list.add(&row);
+        }
+
+        bookmarks_list.show_all();
+    }
+
+    pub fn rename_bookmark_dialog(&self, app_runtime: AppRuntime, index: usize, label: &str) {
+        let title = gtk::Label::new(Some("Bookmark name"));
+
+        let entry = cascade! {
+            gtk::Entry::new();
+            ..set_text(label);
+            ..set_activates_default(true);
+            ..set_margin(10);
+            ..set_margin_top(0);
+        };
+
+        let _ = cascade! {
+            gtk::Dialog::new();
+            ..set_title(&format!("Rename bookmark - {}", PROGRAM_NAME));
+            ..set_modal(true);
+            ..set_transient_for(Some(&self.main_window));
+            ..set_decorated(false);
+            ..add_button("Ok", ResponseType::Ok);
+            ..add_button("Cancel", ResponseType::Cancel);
+            ..set_default_response(ResponseType::Ok);
+            ..content_area().pack_start(&title, true, false, 12);
+            ..content_area().pack_end(&entry, true, false, 12);
+            ..set_size_request(300, 140);
+            ..connect_response(glib::clone!(@strong app_runtime => move |dialog, response| {
+                if response == ResponseType::Ok {
+                    let label = entry.text().to_string();
+                    app_runtime.update_state_with(move |state| {
+                        state.rename_bookmark(index, label);
+                    });
+                }
+                dialog.close();
+            }));
+            ..show_all();
+        };
+    }
+
+    /// Show or hide the bookmark marker in the reader header.
+    pub fn set_bookmark_marker(&mut self, is_bookmarked: bool) {
+        if self.is_bookmarked != is_bookmarked {
+            self.is_bookmarked = is_bookmarked;
+            self.update_header();
+        }
+    }
+
+    /// Set the reader header text, with the bookmark marker if the position is bookmarked.
+    pub fn update_header(&self) {
+        let mut header_content = String::new();
+        if self.is_bookmarked {
+            header_content.push_str(BOOKMARK_MARKER);
+        }
+        header_content.push_str(&self.header_text);
+
+        self.show_header(header_content);
+    }
+}
@@ -0,0 +1,290 @@
+use super::UI;
+
+use std::path::Path;
+use gtk::prelude::*;
+use crate::app::AppRuntime;
+use crate::appop::OpenBook;
+use super::renderer::{ChapterRenderer, RenderedChapter};
+
+/// Most chapters kept in the reader in the continuous mode, more only while they are all in view.
+const MAX_LOADED_CHAPTERS: usize = 3;
+
+/// A chapter in the reader buffer. Only the continuous mode has more than one of them.
+pub struct LoadedChapter {
+    /// Index of the section in the book.
+    pub page: usize,
+    /// Start of the chapter in the buffer, moves along when text is added or removed before it.
+    start: gtk::TextMark,
+    /// Anchors and links of the chapter, their offsets are from the start of the chapter.
+    pub rendered: RenderedChapter,
+}
+
+/// Where a chapter is added in the continuous mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChapterPlace {
+    Before,
+    After,
+}
+
+/// The text at the top of the reader, so it can be kept there when the text before it changes.
+pub struct ViewAnchor {
+    mark: gtk::TextMark,
+    /// How far below the top of the line the view was.
+    delta: f64,
+    /// Where the chapter was added, the extra chapters are removed from the other end.
+    place: ChapterPlace,
+}
+
+impl UI {
+    pub fn connect_chapters(&self, app_runtime: AppRuntime) {
+        // Follow the chapter in view and load the next one near the end.
+        let adj = self.scroller.vadjustment();
+        adj.connect_value_changed(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.update_continuous());
+        }));
+        adj.connect_changed(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.update_continuous());
+        }));
+
+        // Scrolling up at the top loads the previous chapter.
+        self.scroller.connect_scroll_event(glib::clone!(@strong app_runtime => move |scroller, event| {
+            let is_up = match event.direction() {
+                gdk::ScrollDirection::Up => true,
+                gdk::ScrollDirection::Smooth => event.delta().1 < 0.0,
+                _ => false,
+            };
+            if is_up && scroller.vadjustment().value() <= 0.0 {
+                app_runtime.update_state_with(|state| state.load_previous_chapter());
+            }
+
+            gtk::Inhibit(false)
+        }));
+    }
+
+    /// The reader adds the chapters before and after the current one while scrolling.
+    pub fn is_continuous(&self) -> bool {
+        self.continuous && !self.is_paginated() && self.reader.is_visible()
+    }
+
+    /// The view is within a screen of the end of the loaded chapters.
+    pub fn is_near_end(&self) -> bool {
+        let adj = self.scroller.vadjustment();
+
+        adj.value() + 2.0 * adj.page_size() >= adj.upper()
+    }
+
+    /// A chapter was just added and the view isn't restored yet.
+    pub fn is_chapter_loading(&self) -> bool {
+        self.chapter_loading
+    }
+
+    /// Make the `rendered` chapter `page` the only chapter in the reader.
+    pub(super) fn set_single_chapter(&mut self, page: usize, rendered: RenderedChapter) {
+        self.clear_chapters();
+
+        self.failed_pages.clear();
+
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let start = buffer.create_mark(None, &buffer.start_iter(), false);
+        self.chapters.push(LoadedChapter {
+            page,
+            start,
+            rendered,
+        });
+    }
+
+    /// Forget the loaded chapters, the text is replaced separately.
+    pub(super) fn clear_chapters(&mut self) {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        for chapter in self.chapters.drain(..) {
+            buffer.delete_mark(&chapter.start);
+        }
+        self.chapter_loading = false;
+    }
+
+    /// Add the chapter `page` of the book before or after the loaded chapters.
+    /// A chapter that could not be loaded is not tried again until another book or chapter is opened.
+    ///
+    /// The text in view moves when the text before it changes, `restore_view` with the
+    /// returned anchor moves it back once the reader has been laid out.
+    pub fn load_chapter(&mut self, book: &mut OpenBook, page: usize, place: ChapterPlace) -> Option<ViewAnchor> {
+        if self.failed_pages.contains(&page) {
+            return None;
+        }
+        let content = match book.doc.section_content(page) {
+            Ok(content) => content,
+            Err(e) => {
+                error!("Cannot load the chapter {}. {}", page, e);
+                self.failed_pages.insert(page);
+                return None;
+            }
+        };
+
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let anchor = self.view_anchor(place);
+
+        // Rendered separately so the offsets start from the start of the chapter.
+        let scratch = gtk::TextBuffer::new(buffer.tag_table().as_ref());
+        let chapter_path = book.doc.sections()[page].path.clone();
+        let mut load_resource = |path: &Path| book.doc.resource(path);
+        let rendered = ChapterRenderer::new(&scratch, chapter_path, self.reader_content_width(), &mut load_resource)
+            .render(&content);
+
+        match place {
+            ChapterPlace::Before => {
+                buffer.insert_range(&mut buffer.start_iter(), &scratch.start_iter(), &scratch.end_iter());
+                let start = buffer.create_mark(None, &buffer.start_iter(), false);
+                self.chapters.insert(0, LoadedChapter { page, start, rendered });
+            },
+            ChapterPlace::After => {
+                let offset = buffer.end_iter().offset();
+                buffer.insert_range(&mut buffer.end_iter(), &scratch.start_iter(), &scratch.end_iter());
+                let start = buffer.create_mark(None, &buffer.iter_at_offset(offset), false);
+                self.chapters.push(LoadedChapter { page, start, rendered });
+            },
+        }
+
+        self.apply_highlights(&book.highlights);
+        self.find_in_chapter(false);
+        self.chapter_loading = true;
+
+        Some(anchor)
+    }
+
+    /// Scroll the text at the `anchor` back to where it was, then remove the chapters over
+    /// `MAX_LOADED_CHAPTERS` that are out of view.
+    ///
+    /// Removing a chapter before the view moves the text again, the returned anchor has to
+    /// be restored too once the reader has been laid out.
+    pub fn restore_view(&mut self, anchor: ViewAnchor) -> Option<ViewAnchor> {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        if !self.chapter_loading {
+            if !anchor.mark.is_deleted() {
+                buffer.delete_mark(&anchor.mark);
+            }
+            return None;
+        }
+        if !anchor.mark.is_deleted() {
+            let y = self.offset_y(buffer.iter_at_mark(&anchor.mark).offset());
+            self.scroller.vadjustment().set_value(y + anchor.delta);
+            buffer.delete_mark(&anchor.mark);
+        }
+
+        self.chapter_loading = false;
+        self.trim_chapters(anchor.place)
+    }
+
+    /// The chapter with the buffer char `offset` and the offset in the chapter.
+    pub fn chapter_at(&self, offset: i32) -> Option<(&LoadedChapter, i32)> {
+        let chapter = self.chapters
+            .iter()
+            .rev()
+            .find(|chapter| self.chapter_start(chapter) <= offset)
+            .or_else(|| self.chapters.first())?;
+
+        Some((chapter, offset - self.chapter_start(chapter)))
+    }
+
+    /// The page and the offset in it of the buffer char `offset`.
+    pub fn chapter_position(&self, offset: i32) -> Option<(usize, i32)> {
+        self.chapter_at(offset).map(|(chapter, offset)| (chapter.page, offset))
+    }
+
+    /// Buffer offset of the char `offset` in the chapter `page`, if the chapter is in the reader.
+    pub fn buffer_offset(&self, page: usize, offset: i32) -> Option<i32> {
+        self.chapters
+            .iter()
+            .find(|chapter| chapter.page == page)
+            .map(|chapter| self.chapter_start(chapter) + offset)
+    }
+
+    /// Page of the chapter at the top of the reader.
+    pub fn page_at_top(&self) -> Option<usize> {
+        self.chapter_at(self.buffer_offset_at_top()).map(|(chapter, _)| chapter.page)
+    }
+
+    /// The chapter at the top of the reader.
+    pub(super) fn chapter_at_top(&self) -> Option<&LoadedChapter> {
+        self.chapter_at(self.buffer_offset_at_top()).map(|(chapter, _)| chapter)
+    }
+
+    pub(super) fn chapter_start(&self, chapter: &LoadedChapter) -> i32 {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+
+        buffer.iter_at_mark(&chapter.start).offset()
+    }
+
+    fn page_at_bottom(&self) -> Option<usize> {
+        let adj = self.scroller.vadjustment();
+        let y = (adj.value() + adj.page_size()) as i32 - self.reader.allocation().y();
+        let (_, buffer_y) = self.reader.window_to_buffer_coords(gtk::TextWindowType::Widget, 0, y.max(0));
+        let (iter, _) = self.reader.line_at_y(buffer_y);
+
+        self.chapter_position(iter.offset()).map(|(page, _)| page)
+    }
+
+    /// Remove the chapters at the other end from the `place` the last one was added to while there
+    /// are too many of them. The chapters in view stay, there are only a few of them in a screen.
+    fn trim_chapters(&mut self, place: ChapterPlace) -> Option<ViewAnchor> {
+        while self.chapters.len() > MAX_LOADED_CHAPTERS {
+            match place {
+                ChapterPlace::Before => {
+                    let last = self.chapters.len() - 1;
+                    if self.page_at_bottom() == Some(self.chapters[last].page) {
+                        break;
+                    }
+                    self.unload_chapter(last);
+                    self.find_in_chapter(false);
+                },
+                ChapterPlace::After => {
+                    if self.page_at_top() == Some(self.chapters[0].page) {
+                        break;
+                    }
+                    let anchor = self.view_anchor(place);
+                    self.unload_chapter(0);
+                    self.find_in_chapter(false);
+                    self.chapter_loading = true;
+
+                    return Some(anchor);
+                },
+            }
+        }
+
+        None
+    }
+
+    /// Remove the chapter at `index` in `chapters` from the reader.
+    fn unload_chapter(&mut self, index: usize) {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let chapter = self.chapters.remove(index);
+
+        let mut start = buffer.iter_at_mark(&chapter.start);
+        let mut end = match self.chapters.get(index) {
+            Some(next) => buffer.iter_at_mark(&next.start),
+            None => buffer.end_iter(),
+        };
+        buffer.delete(&mut start, &mut end);
+        buffer.delete_mark(&chapter.start);
+    }
+
+    fn view_anchor(&self, place: ChapterPlace) -> ViewAnchor {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let offset = self.buffer_offset_at_top();
+        let delta = self.scroller.vadjustment().value() - self.offset_y(offset);
+
+        ViewAnchor {
+            mark: buffer.create_mark(None, &buffer.iter_at_offset(offset), false),
+            delta,
+            place,
+        }
+    }
+
+    /// Scroll position of the line with the buffer char `offset`.
+    fn offset_y(&self, offset: i32) -> f64 {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let location = self.reader.iter_location(&buffer.iter_at_offset(offset));
+        let (_, y) = self.reader.buffer_to_window_coords(gtk::TextWindowType::Widget, location.x(), location.y());
+
+        (self.reader.allocation().y() + y) as f64
+    }
+}
@@ -0,0 +1,188 @@
+use super::UI;
+
+use std::io::Cursor;
+use gdk_pixbuf::{Pixbuf, InterpType};
+use gtk::gdk;
+use gtk::prelude::*;
+use crate::PROGRAM_NAME;
+use crate::app::AppRuntime;
+use crate::app::comic_book::ComicBook;
+use crate::app::settings::Settings;
+use crate::app::utils::BuilderExtManualCustom;
+
+/// How much one zoom step changes the size of the page.
+const ZOOM_STEP: f64 = 1.25;
+const MIN_ZOOM: f64 = 0.25;
+const MAX_ZOOM: f64 = 8.0;
+
+/// What the comic page is scaled to before zooming.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComicFit {
+    Width,
+    Height,
+}
+
+pub struct ComicView {
+    pub image: gtk::Image,
+    /// The page in its full size, it's scaled to the view when shown.
+    page: Option<Pixbuf>,
+    fit: ComicFit,
+    zoom: f64,
+    /// Size of the view the page was last scaled to.
+    scaled_to: (i32, i32),
+}
+
+impl ComicView {
+    pub fn new(builder: &gtk::Builder) -> ComicView {
+        ComicView {
+            image: builder.get::<gtk::Image>("comic_image"),
+            page: None,
+            fit: ComicFit::Width,
+            zoom: 1.0,
+            scaled_to: (0, 0),
+        }
+    }
+}
+
+impl UI {
+    pub fn connect_comic_view(&self, app_runtime: AppRuntime) {
+        // Scale the page to the new size of the window.
+        self.scroller.connect_size_allocate(glib::clone!(@strong app_runtime => move |_, _| {
+            app_runtime.update_state_with(|state| state.ui.scale_comic_page(false));
+        }));
+
+        // Ctrl + mouse wheel zooms the page.
+        let image = self.comic_view.image.clone();
+        self.scroller.connect_scroll_event(glib::clone!(@strong app_runtime => move |_, event| {
+            if !image.is_visible() || !event.state().contains(gdk::ModifierType::CONTROL_MASK) {
+                return gtk::Inhibit(false);
+            }
+
+            let factor = match event.direction() {
+                gdk::ScrollDirection::Up => ZOOM_STEP,
+                gdk::ScrollDirection::Down => 1.0 / ZOOM_STEP,
+                gdk::ScrollDirection::Smooth if event.delta().1 < 0.0 => ZOOM_STEP,
+                gdk::ScrollDirection::Smooth if event.delta().1 > 0.0 => 1.0 / ZOOM_STEP,
+                _ => return gtk::Inhibit(true),
+            };
+            app_runtime.update_state_with(move |state| state.ui.zoom_comic(factor));
+
+            gtk::Inhibit(true)
+        }));
+    }
+
+    /// Show the comic page view instead of the text reader, or the other way round.
+    pub fn set_comic_mode(&mut self, is_comic: bool) {
+        self.reader.set_visible(!is_comic);
+        self.comic_view.image.set_visible(is_comic);
+
+        if is_comic {
+            // Nothing in the text reader should be found or saved from the comic.
+            self.reader.buffer().expect("Could not get buffer").set_text("");
+            self.clear_chapters();
+            for child in self.left_content_box.children() {
+                self.left_content_box.remove(&child);
+            }
+            self.toc_view = None;
+            self.hide_progress();
+        } else {
+            self.comic_view.page = None;
+            self.comic_view.image.clear();
+        }
+        self.update_pager_mode();
+    }
+
+    pub fn show_comic_page(&mut self, comic: &mut ComicBook, settings: &Settings) {
+        self.header_text = if settings.general.show_page_num {
+            format!("Page {} of {}", comic.current_page + 1, comic.num_pages())
+        } else {
+            String::new()
+        };
+        self.update_header();
+
+        let book_filename = comic.file.file_name().unwrap().to_string_lossy().to_string();
+        self.main_window.set_title(&format!("{} - {}", book_filename, PROGRAM_NAME));
+        self.builder
+            .get::<gtk::Label>("chapter_list_label")
+            .set_label(&format!("Reading: {:?}", book_filename));
+
+        self.comic_view.page = comic
+            .page_image(comic.current_page)
+            .and_then(|bytes| match Pixbuf::from_read(Cursor::new(bytes)) {
+                Ok(pixbuf) => Some(pixbuf),
+                Err(e) => {
+                    warn!("Cannot load the comic page {}. {}", comic.current_page, e);
+                    None
+                }
+            });
+
+        self.scale_comic_page(true);
+        self.scroll_to_top();
+    }
+
+    /// Scale the page to the view. Does nothing if the view size hasn't changed, unless `force`.
+    pub fn scale_comic_page(&mut self, force: bool) {
+        let size = self.comic_view_size();
+        let page = match &self.comic_view.page {
+            Some(page) => page,
+            None => {
+                self.comic_view.image.clear();
+                return;
+            }
+        };
+        if !force && size == self.comic_view.scaled_to {
+            return;
+        }
+        self.comic_view.scaled_to = size;
+
+        let fit_scale = match self.comic_view.fit {
+            ComicFit::Width => size.0 as f64 / page.width() as f64,
+            ComicFit::Height => size.1 as f64 / page.height() as f64,
+        };
+        let scale = fit_scale * self.comic_view.zoom;
+        let width = ((page.width() as f64 * scale).round() as i32).max(1);
+        let height = ((page.height() as f64 * scale).round() as i32).max(1);
+
+        let scaled = page.scale_simple(width, height, InterpType::Bilinear);
+        self.comic_view.image.set_from_pixbuf(scaled.as_ref());
+    }
+
+    /// Switch between fitting the page to the width and the height of the view.
+    pub fn toggle_comic_fit(&mut self) {
+        self.comic_view.fit = match self.comic_view.fit {
+            ComicFit::Width => ComicFit::Height,
+            ComicFit::Height => ComicFit::Width,
+        };
+        self.comic_view.zoom = 1.0;
+        self.scale_comic_page(true);
+    }
+
+    pub fn zoom_comic(&mut self, factor: f64) {
+        self.comic_view.zoom = (self.comic_view.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.scale_comic_page(true);
+    }
+
+    pub fn comic_zoom_in(&mut self) {
+        self.zoom_comic(ZOOM_STEP);
+    }
+
+    pub fn comic_zoom_out(&mut self) {
+        self.zoom_comic(1.0 / ZOOM_STEP);
+    }
+
+    pub fn reset_comic_zoom(&mut self) {
+        self.comic_view.zoom = 1.0;
+        self.scale_comic_page(true);
+    }
+
+    /// Size of the visible part of the scroller below the header.
+    fn comic_view_size(&self) -> (i32, i32) {
+        let scrollbar = self.scroller.vscrollbar().map_or(0, |scrollbar| scrollbar.allocated_width());
+        let header = if self.reader_header.is_visible() { self.reader_header.allocated_height() } else { 0 };
+
+        (
+            (self.scroller.allocated_width() - scrollbar).max(1),
+            (self.scroller.allocated_height() - header).max(1)
+        )
+    }
+}
@@ -0,0 +1,25 @@
+use super::UI;
+
+use gtk::prelude::*;
+use gtk::{ButtonsType, DialogFlags, MessageType};
+
+
+impl UI {
+    /// Tell the user about a problem that they should know about, like lost data.
+    pub fn error_dialog(&self, message: &str) {
+        let dialog = cascade! {
+            gtk::MessageDialog::new(
+                Some(&self.main_window),
+                DialogFlags::MODAL,
+                MessageType::Warning,
+                ButtonsType::Ok,
+                message
+            );
+            ..connect_response(move |d, _| {
+                d.close();
+            });
+        };
+        
+        dialog.show();
+    }
+}
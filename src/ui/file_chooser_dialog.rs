@@ -8,7 +8,7 @@ use crate::app::AppRuntime;
 impl UI {
     pub fn file_chooser_dialog(&self, app_runtime: AppRuntime) {
         let dialog = cascade! {
-            gtk::FileChooserDialog::new(Some("Choose epub file to open"), Some(&self.main_window), FileChooserAction::Open);
+            gtk::FileChooserDialog::new(Some("Choose a book to open"), Some(&self.main_window), FileChooserAction::Open);
             ..set_modal(true);
             ..set_transient_for(Some(&self.main_window));
             ..add_buttons(&[
@@ -23,7 +23,7 @@ impl UI {
                     if let Some(filename) = dialog.filename() {
                         app_runtime.update_state_with(move |state| {
                             state.open_file_from_path(filename, None);
-                            state.ui.update_recent(state.app_runtime.clone(), &state.db.read().clone().rows.unwrap());
+                            state.refresh_recent();
                         });
                         dialog.close();
                     }
@@ -0,0 +1,267 @@
+use super::UI;
+
+use gtk::prelude::*;
+use gtk::{TextBuffer, TextSearchFlags};
+use crate::app::AppRuntime;
+use crate::app::utils::BuilderExtManualCustom;
+
+// Names of the tags used for highlighting the matches
+const FIND_TAG: &str = "find";
+const FIND_CURRENT_TAG: &str = "find-current";
+
+
+pub struct FindBar {
+    pub bar: gtk::SearchBar,
+    pub entry: gtk::SearchEntry,
+    pub case_check: gtk::CheckButton,
+    pub word_check: gtk::CheckButton,
+    pub matches_label: gtk::Label,
+    /// Start and end offsets of the matches in the reader buffer.
+    pub matches: Vec<(i32, i32)>,
+    /// Index of the selected match.
+    pub current: Option<usize>,
+}
+
+impl FindBar {
+    pub fn new(builder: &gtk::Builder) -> FindBar {
+        let bar = builder.get::<gtk::SearchBar>("find_bar");
+        let entry = builder.get::<gtk::SearchEntry>("find_entry");
+        bar.connect_entry(&entry);
+
+        FindBar {
+            bar,
+            entry,
+            case_check: builder.get::<gtk::CheckButton>("find_case_check"),
+            word_check: builder.get::<gtk::CheckButton>("find_word_check"),
+            matches_label: builder.get::<gtk::Label>("find_matches_label"),
+            matches: vec![],
+            current: None,
+        }
+    }
+
+    pub fn connect(&self, builder: &gtk::Builder, app_runtime: AppRuntime) {
+        let previous_button = builder.get::<gtk::Button>("find_previous_button");
+        let next_button = builder.get::<gtk::Button>("find_next_button");
+
+        self.entry.connect_search_changed(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.ui.find_in_chapter(true));
+        }));
+
+        for check in &[&self.case_check, &self.word_check] {
+            check.connect_toggled(glib::clone!(@strong app_runtime => move |_| {
+                app_runtime.update_state_with(|state| state.ui.find_in_chapter(true));
+            }));
+        }
+
+        // Enter and Ctrl+G go to the next match
+        self.entry.connect_activate(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.ui.find_next());
+        }));
+        self.entry.connect_next_match(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.ui.find_next());
+        }));
+        next_button.connect_clicked(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.ui.find_next());
+        }));
+
+        // Shift+Ctrl+G goes to the previous match
+        self.entry.connect_previous_match(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.ui.find_previous());
+        }));
+        previous_button.connect_clicked(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.ui.find_previous());
+        }));
+
+        // Shift+Enter isn't handled by the entry itself
+        self.entry.connect_key_press_event(glib::clone!(@strong app_runtime => move |_, event| {
+            let is_enter = matches!(event.keyval(), gdk::keys::constants::Return | gdk::keys::constants::KP_Enter);
+            if is_enter && event.state().contains(gdk::ModifierType::SHIFT_MASK) {
+                app_runtime.update_state_with(|state| state.ui.find_previous());
+                return gtk::Inhibit(true);
+            }
+
+            gtk::Inhibit(false)
+        }));
+
+        // Remove the highlights when the bar is closed
+        self.bar.connect_search_mode_enabled_notify(glib::clone!(@strong app_runtime => move |bar| {
+            if !bar.is_search_mode() {
+                app_runtime.update_state_with(|state| {
+                    state.ui.clear_find();
+                    state.ui.reader.grab_focus();
+                });
+            }
+        }));
+    }
+}
+
+impl UI {
+    /// Open the find bar, or close it if it already has the focus.
+    pub fn toggle_find_bar(&mut self) {
+        let find_bar = &self.find_bar;
+        if find_bar.bar.is_search_mode() && find_bar.entry.has_focus() {
+            find_bar.bar.set_search_mode(false);
+        } else {
+            find_bar.bar.set_search_mode(true);
+            find_bar.entry.grab_focus();
+        }
+    }
+
+    /// Highlight every match of the find bar text in the current chapter.
+    ///
+    /// When `scroll` is true the first match below the top of the view is selected.
+    pub fn find_in_chapter(&mut self, scroll: bool) {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        self.clear_find();
+
+        if !self.find_bar.bar.is_search_mode() {
+            return;
+        }
+
+        let text = self.find_bar.entry.text().to_string();
+        let case_sensitive = self.find_bar.case_check.is_active();
+        let whole_word = self.find_bar.word_check.is_active();
+
+        self.find_bar.matches = find_matches(&buffer, &text, case_sensitive, whole_word);
+        for (start, end) in &self.find_bar.matches {
+            buffer.apply_tag_by_name(FIND_TAG, &buffer.iter_at_offset(*start), &buffer.iter_at_offset(*end));
+        }
+
+        if scroll && !self.find_bar.matches.is_empty() {
+            let top = self.buffer_offset_at_top();
+            let index = self.find_bar.matches
+                .iter()
+                .position(|(start, _)| *start >= top)
+                .unwrap_or(0);
+            self.select_match(index);
+        }
+
+        self.update_find_counter();
+    }
+
+    /// Select the match after the current one, wraps around to the first one.
+    pub fn find_next(&mut self) {
+        let count = self.find_bar.matches.len();
+        if count == 0 {
+            return;
+        }
+
+        let index = match self.find_bar.current {
+            Some(current) => (current + 1) % count,
+            None => 0,
+        };
+        self.select_match(index);
+        self.update_find_counter();
+    }
+
+    /// Select the match before the current one, wraps around to the last one.
+    pub fn find_previous(&mut self) {
+        let count = self.find_bar.matches.len();
+        if count == 0 {
+            return;
+        }
+
+        let index = match self.find_bar.current {
+            Some(current) if current > 0 => current - 1,
+            _ => count - 1,
+        };
+        self.select_match(index);
+        self.update_find_counter();
+    }
+
+    /// Remove all the match highlights from the reader.
+    pub fn clear_find(&mut self) {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        create_find_tags(&buffer);
+
+        let (start, end) = buffer.bounds();
+        buffer.remove_tag_by_name(FIND_TAG, &start, &end);
+        buffer.remove_tag_by_name(FIND_CURRENT_TAG, &start, &end);
+
+        self.find_bar.matches.clear();
+        self.find_bar.current = None;
+        self.update_find_counter();
+    }
+
+    /// Move the current match highlight to the match at `index` and scroll to it.
+    fn select_match(&mut self, index: usize) {
+        let (start, end) = self.find_bar.matches[index];
+        self.highlight_match(start, end);
+        self.find_bar.current = Some(index);
+    }
+
+    /// Highlight the text between the offsets as the current match and scroll to it.
+    pub(super) fn highlight_match(&mut self, start: i32, end: i32) {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        create_find_tags(&buffer);
+
+        let (buffer_start, buffer_end) = buffer.bounds();
+        buffer.remove_tag_by_name(FIND_CURRENT_TAG, &buffer_start, &buffer_end);
+        buffer.apply_tag_by_name(FIND_CURRENT_TAG, &buffer.iter_at_offset(start), &buffer.iter_at_offset(end));
+
+        // Leave some of the text before the match visible
+        self.scroll_to_buffer_offset(start);
+        if !self.is_paginated() {
+            let page_size = self.scroller.vadjustment().page_size();
+            self.scroll_up(page_size / 3.0);
+        }
+    }
+
+    /// Show the number of matches and the current match.
+    fn update_find_counter(&self) {
+        let find_bar = &self.find_bar;
+        let label = if find_bar.entry.text().is_empty() {
+            String::new()
+        } else if find_bar.matches.is_empty() {
+            "No matches".to_string()
+        } else if let Some(current) = find_bar.current {
+            format!("{} of {}", current + 1, find_bar.matches.len())
+        } else {
+            format!("{} matches", find_bar.matches.len())
+        };
+
+        find_bar.matches_label.set_label(&label);
+    }
+}
+
+/// Start and end offsets of every occurrence of `text` in the `buffer`.
+pub(super) fn find_matches(buffer: &TextBuffer, text: &str, case_sensitive: bool, whole_word: bool) -> Vec<(i32, i32)> {
+    let mut matches = vec![];
+    if text.is_empty() {
+        return matches;
+    }
+
+    let mut flags = TextSearchFlags::VISIBLE_ONLY | TextSearchFlags::TEXT_ONLY;
+    if !case_sensitive {
+        flags |= TextSearchFlags::CASE_INSENSITIVE;
+    }
+
+    let mut iter = buffer.start_iter();
+    while let Some((start, end)) = iter.forward_search(text, flags, None) {
+        if !whole_word || (start.starts_word() && end.ends_word()) {
+            matches.push((start.offset(), end.offset()));
+        }
+        iter = end;
+    }
+
+    matches
+}
+
+/// Create the highlight tags if the buffer doesn't have them yet.
+fn create_find_tags(buffer: &TextBuffer) {
+    let table = buffer.tag_table().expect("Could not get tag table");
+
+    // The current match tag is created last so it is drawn over the other matches
+    if table.lookup(FIND_TAG).is_none() {
+        buffer.create_tag(Some(FIND_TAG), &[
+            ("background", &"#fce94f"),
+            ("foreground", &"#000000"),
+        ]);
+    }
+    if table.lookup(FIND_CURRENT_TAG).is_none() {
+        buffer.create_tag(Some(FIND_CURRENT_TAG), &[
+            ("background", &"#f57900"),
+            ("foreground", &"#000000"),
+        ]);
+    }
+}
@@ -0,0 +1,30 @@
+use super::UI;
+
+use gtk::prelude::*;
+
+
+impl UI {
+    /// Show the footnote `text` in a popover pointing at the char `offset` in the reader.
+    pub fn footnote_popover(&self, offset: i32, text: &str) {
+        let label = cascade! {
+            gtk::Label::new(Some(text));
+            ..set_selectable(true);
+            ..set_line_wrap(true);
+            ..set_max_width_chars(60);
+            ..set_xalign(0.0);
+            ..set_margin(12);
+        };
+        
+        let popover = cascade! {
+            gtk::Popover::new(Some(&self.reader));
+            ..set_pointing_to(&self.offset_rectangle(offset));
+            ..add(&label);
+            ..connect_closed(|popover| {
+                popover.hide();
+            });
+        };
+        
+        label.show();
+        popover.popup();
+    }
+}
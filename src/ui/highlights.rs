@@ -0,0 +1,247 @@
+use super::UI;
+
+use gtk::prelude::*;
+use gtk::TextBuffer;
+use crate::app::AppRuntime;
+use crate::app::database::{Highlight, HighlightColor};
+use crate::app::utils::BuilderExtManualCustom;
+
+
+impl UI {
+    pub fn connect_highlights(&self, app_runtime: AppRuntime) {
+        let highlights_list = self.builder.get::<gtk::ListBox>("highlights_list");
+        highlights_list.connect_row_activated(glib::clone!(@strong app_runtime => move |_, row| {
+            let index = row.index() as usize;
+            app_runtime.update_state_with(move |state| {
+                state.open_highlight(index);
+            });
+        }));
+    }
+
+    /// Tag the highlights of the chapters in the reader, removing the old ones first.
+    pub fn apply_highlights(&self, highlights: &[Highlight]) {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        create_highlight_tags(&buffer);
+
+        let (start, end) = buffer.bounds();
+        for color in HighlightColor::ALL.iter() {
+            buffer.remove_tag_by_name(color.tag_name(), &start, &end);
+        }
+
+        for chapter in &self.chapters {
+            let chapter_start = self.chapter_start(chapter);
+            for highlight in highlights.iter().filter(|highlight| highlight.page == chapter.page) {
+                buffer.apply_tag_by_name(
+                    highlight.color.tag_name(),
+                    &buffer.iter_at_offset(chapter_start + highlight.start as i32),
+                    &buffer.iter_at_offset(chapter_start + highlight.end as i32)
+                );
+            }
+        }
+    }
+
+    /// Update the list of the highlights of the open book.
+    pub fn update_highlights(&mut self, highlights: &[Highlight]) {
+        let highlights_list = self.builder.get::<gtk::ListBox>("highlights_list");
+        for child in highlights_list.children() {
+            highlights_list.remove(&child);
+        }
+
+        for highlight in highlights {
+            let text = cascade! {
+                gtk::Label::new(Some(&highlight.text));
+                ..set_xalign(0.0);
+                ..set_line_wrap(true);
+                ..set_lines(3);
+                ..set_ellipsize(gtk::pango::EllipsizeMode::End);
+                ..set_max_width_chars(40);
+            };
+            text.style_context().add_class("highlight-text");
+            text.style_context().add_class(highlight.color.tag_name());
+
+            let content = cascade! {
+                gtk::Box::new(gtk::Orientation::Vertical, 2);
+                ..set_margin_start(10);
+                ..set_margin_end(10);
+                ..set_margin_top(6);
+                ..set_margin_bottom(6);
+                ..pack_start(&text, false, false, 0);
+            };
+
+            if let Some(note) = &highlight.note {
+                let note = cascade! {
+                    gtk::Label::new(Some(note));
+                    ..set_xalign(0.0);
+                    ..set_line_wrap(true);
+                    ..set_max_width_chars(40);
+                };
+                note.style_context().add_class("highlight-note");
+                content.pack_start(&note, false, false, 0);
+            }
+
+            let details = cascade! {
+                gtk::Label::new(Some(&format!("Page {}", highlight.page + 1)));
+                ..set_xalign(0.0);
+            };
+            details.style_context().add_class("highlight-details");
+            content.pack_start(&details, false, false, 0);
+
+            let row = cascade! {
+                gtk::ListBoxRow::new();
+                ..add(&content);
+            };
+            highlights_list.add(&row);
+        }
+
+        highlights_list.show_all();
+    }
+
+    /// Popover for highlighting the text between the offsets.
+    pub fn new_highlight_popover(&self, app_runtime: AppRuntime, start: i32, end: i32) {
+        let note_entry = cascade! {
+            gtk::Entry::new();
+            ..set_placeholder_text(Some("Note (optional)"));
+            ..set_width_chars(30);
+        };
+
+        let popover = gtk::Popover::new(Some(&self.reader));
+        let colors = color_buttons(None, glib::clone!(@strong app_runtime, @weak popover, @weak note_entry => move |color| {
+            let note = note_entry.text().to_string();
+            app_runtime.update_state_with(move |state| {
+                state.add_highlight(start, end, color, note);
+            });
+            popover.popdown();
+        }));
+
+        let content = cascade! {
+            gtk::Box::new(gtk::Orientation::Vertical, 6);
+            ..set_margin(8);
+            ..pack_start(&colors, false, false, 0);
+            ..pack_start(&note_entry, false, false, 0);
+        };
+
+        popover.set_pointing_to(&self.offset_rectangle(end));
+        popover.add(&content);
+        popover.connect_closed(|popover| {
+            popover.hide();
+        });
+
+        content.show_all();
+        popover.popup();
+    }
+
+    /// Popover for changing the color and the note of the highlight at `index`, or removing it.
+    pub fn edit_highlight_popover(&self, app_runtime: AppRuntime, index: usize, highlight: &Highlight) {
+        let note_view = cascade! {
+            gtk::TextView::new();
+            ..set_wrap_mode(gtk::WrapMode::WordChar);
+            ..set_size_request(260, 80);
+        };
+        note_view
+            .buffer()
+            .expect("Could not get buffer")
+            .set_text(highlight.note.as_deref().unwrap_or(""));
+
+        let popover = gtk::Popover::new(Some(&self.reader));
+        let colors = color_buttons(Some(highlight.color), glib::clone!(@strong app_runtime, @weak popover, @weak note_view => move |color| {
+            let note = text_view_text(&note_view);
+            app_runtime.update_state_with(move |state| {
+                state.update_highlight(index, color, note);
+            });
+            popover.popdown();
+        }));
+
+        let color = highlight.color;
+        let save = cascade! {
+            gtk::Button::with_label("Save note");
+            ..connect_clicked(glib::clone!(@strong app_runtime, @weak popover, @weak note_view => move |_| {
+                let note = text_view_text(&note_view);
+                app_runtime.update_state_with(move |state| {
+                    state.update_highlight(index, color, note);
+                });
+                popover.popdown();
+            }));
+        };
+        let remove = cascade! {
+            gtk::Button::with_label("Remove");
+            ..connect_clicked(glib::clone!(@strong app_runtime, @weak popover => move |_| {
+                app_runtime.update_state_with(move |state| {
+                    state.delete_highlight(index);
+                });
+                popover.popdown();
+            }));
+        };
+        remove.style_context().add_class("destructive-action");
+
+        let buttons = cascade! {
+            gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            ..pack_end(&save, false, false, 0);
+            ..pack_start(&remove, false, false, 0);
+        };
+
+        let note_scroller = cascade! {
+            gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+            ..set_shadow_type(gtk::ShadowType::In);
+            ..add(&note_view);
+        };
+
+        let content = cascade! {
+            gtk::Box::new(gtk::Orientation::Vertical, 6);
+            ..set_margin(8);
+            ..pack_start(&colors, false, false, 0);
+            ..pack_start(&note_scroller, true, true, 0);
+            ..pack_start(&buttons, false, false, 0);
+        };
+
+        let start = self.buffer_offset(highlight.page, highlight.start as i32).unwrap_or(highlight.start as i32);
+        popover.set_pointing_to(&self.offset_rectangle(start));
+        popover.add(&content);
+        popover.connect_closed(|popover| {
+            popover.hide();
+        });
+
+        content.show_all();
+        popover.popup();
+    }
+}
+
+/// A button for each highlight color, `on_click` gets the clicked color.
+fn color_buttons(selected: Option<HighlightColor>, on_click: impl Fn(HighlightColor) + Clone + 'static) -> gtk::Box {
+    let buttons = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+
+    for color in HighlightColor::ALL.iter().copied() {
+        let on_click = on_click.clone();
+        let button = cascade! {
+            gtk::Button::new();
+            ..set_size_request(28, 28);
+            ..connect_clicked(move |_| on_click(color));
+        };
+        button.style_context().add_class("highlight-button");
+        button.style_context().add_class(color.tag_name());
+        if selected == Some(color) {
+            button.style_context().add_class("selected");
+        }
+
+        buttons.pack_start(&button, false, false, 0);
+    }
+
+    buttons
+}
+
+fn text_view_text(view: &gtk::TextView) -> String {
+    let buffer = view.buffer().expect("Could not get buffer");
+    let (start, end) = buffer.bounds();
+
+    buffer.text(&start, &end, false).map(|text| text.to_string()).unwrap_or_default()
+}
+
+/// Create the highlight tags if the buffer doesn't have them yet.
+fn create_highlight_tags(buffer: &TextBuffer) {
+    let table = buffer.tag_table().expect("Could not get tag table");
+
+    for color in HighlightColor::ALL.iter() {
+        if table.lookup(color.tag_name()).is_none() {
+            buffer.create_tag(Some(color.tag_name()), &[("background", &color.rgba())]);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use super::UI;
+
+use gtk::prelude::*;
+use crate::app::AppRuntime;
+use crate::app::database::DatabaseRow;
+
+
+impl UI {
+    pub fn connect_library(&self, app_runtime: AppRuntime) {
+        // The library is only rebuilt when it's in view, catch up when it's shown.
+        self.left_revealer.connect_reveal_child_notify(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.refresh_outdated_recent());
+        }));
+        self.overlay_notebook.connect_page_notify(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.refresh_outdated_recent());
+        }));
+    }
+    
+    /// The library page of the side panel is open.
+    pub fn is_recent_shown(&self) -> bool {
+        self.left_revealer.reveals_child() && self.overlay_notebook.page() == 0
+    }
+    
+    /// Update the library grid of the books in the database (right side).
+    pub fn update_recent(&mut self, app_runtime: AppRuntime, rows: &[DatabaseRow]) {
+        self.recent_outdated = false;
+        
+        // Remove any child elements
+        for child in self.right_content_box.children() {
+            self.right_content_box.remove(&child);
+        }
+        
+        let flow_box = cascade! {
+            gtk::FlowBox::new();
+            ..set_selection_mode(gtk::SelectionMode::None);
+            ..set_homogeneous(true);
+            ..set_min_children_per_line(2);
+            ..set_max_children_per_line(2);
+            ..set_valign(gtk::Align::Start);
+        };
+        
+        for row in rows {
+            let file_path = row.file.clone();
+            let button = cascade! {
+                gtk::Button::new();
+                ..set_relief(gtk::ReliefStyle::None);
+                ..set_tooltip_text(row.file.to_str());
+                ..add(&library_item(row));
+                ..connect_clicked(glib::clone!(@strong app_runtime => move |_| {
+                    let file_path = file_path.clone();
+                    app_runtime.update_state_with(move |state| {
+                        // Open the file
+                        state.open_file_from_path(file_path, None);
+                    });
+                }));
+            };
+            button.style_context().add_class("library-item");
+            
+            flow_box.add(&button);
+        }
+        
+        self.right_content_box.add(&flow_box);
+        self.right_content_box.show_all();
+    }
+}
+
+/// The library shows the `new` row the same way as the `old` one.
+pub fn is_same_item(old: &DatabaseRow, new: &DatabaseRow) -> bool {
+    old.file == new.file && old.metadata == new.metadata && progress_text(old) == progress_text(new)
+}
+
+/// Cover, title, author and progress of the book.
+fn library_item(row: &DatabaseRow) -> gtk::Box {
+    let metadata = row.metadata.clone().unwrap_or_default();
+    
+    let cover = match &metadata.thumbnail {
+        Some(thumbnail) if thumbnail.exists() => gtk::Image::from_file(thumbnail),
+        _ => gtk::Image::from_icon_name(Some("x-office-document"), gtk::IconSize::Dialog),
+    };
+    
+    let title = metadata.title
+        .unwrap_or_else(|| row.filename.to_string_lossy().to_string());
+    let title = cascade! {
+        gtk::Label::new(Some(&title));
+        ..set_line_wrap(true);
+        ..set_lines(2);
+        ..set_ellipsize(gtk::pango::EllipsizeMode::End);
+        ..set_max_width_chars(16);
+        ..set_justify(gtk::Justification::Center);
+    };
+    title.style_context().add_class("library-title");
+    
+    let author = cascade! {
+        gtk::Label::new(metadata.author.as_deref());
+        ..set_ellipsize(gtk::pango::EllipsizeMode::End);
+        ..set_max_width_chars(16);
+    };
+    author.style_context().add_class("library-author");
+    
+    let progress = gtk::Label::new(Some(&progress_text(row)));
+    progress.style_context().add_class("library-progress");
+    
+    cascade! {
+        gtk::Box::new(gtk::Orientation::Vertical, 4);
+        ..pack_start(&cover, false, false, 0);
+        ..pack_start(&title, false, false, 0);
+        ..pack_start(&author, false, false, 0);
+        ..pack_start(&progress, false, false, 0);
+    }
+}
+
+fn progress_text(row: &DatabaseRow) -> String {
+    let num_pages = row.metadata.as_ref().map_or(0, |metadata| metadata.num_pages);
+    
+    match row.progress {
+        Some(progress) => format!("{}% read", (progress * 100.0).floor()),
+        // Books that haven't been opened since the progress was added
+        None if num_pages > 0 => format!("Page {} of {}", row.current_page + 1, num_pages),
+        None => format!("Page {}", row.current_page + 1),
+    }
+}
@@ -0,0 +1,121 @@
+use super::UI;
+
+use gtk::prelude::*;
+use gtk::{FileChooserAction, ResponseType};
+use crate::PROGRAM_NAME;
+use crate::app::AppRuntime;
+
+
+impl UI {
+    pub fn export_library_chooser(&self, app_runtime: AppRuntime) {
+        let dialog = cascade! {
+            gtk::FileChooserDialog::new(Some("Export library"), Some(&self.main_window), FileChooserAction::Save);
+            ..set_modal(true);
+            ..set_do_overwrite_confirmation(true);
+            ..set_current_name(&format!("bad-reader-library-{}.zip", chrono::Local::now().format("%Y-%m-%d")));
+            ..add_buttons(&[
+                ("Export", ResponseType::Ok),
+                ("Cancel", ResponseType::Cancel),
+            ]);
+        };
+
+        dialog.connect_response(glib::clone!(@strong app_runtime => move |dialog, response| {
+            if response == ResponseType::Ok {
+                if let Some(filename) = dialog.filename() {
+                    app_runtime.update_state_with(move |state| state.export_library(filename));
+                }
+            }
+            dialog.close();
+        }));
+
+        dialog.show_all();
+    }
+
+    pub fn import_library_chooser(&self, app_runtime: AppRuntime) {
+        let filter = cascade! {
+            gtk::FileFilter::new();
+            ..set_name(Some("Library archives"));
+            ..add_pattern("*.zip");
+        };
+
+        let dialog = cascade! {
+            gtk::FileChooserDialog::new(Some("Import library"), Some(&self.main_window), FileChooserAction::Open);
+            ..set_modal(true);
+            ..add_filter(&filter);
+            ..add_buttons(&[
+                ("Open", ResponseType::Ok),
+                ("Cancel", ResponseType::Cancel),
+            ]);
+        };
+
+        dialog.connect_response(glib::clone!(@strong app_runtime => move |dialog, response| {
+            if response == ResponseType::Ok {
+                if let Some(filename) = dialog.filename() {
+                    app_runtime.update_state_with(move |state| state.preview_import(filename));
+                }
+            }
+            dialog.close();
+        }));
+
+        dialog.show_all();
+    }
+
+    /// Show what importing the archive would change and import it if the user agrees.
+    pub fn import_library_dialog(&self, app_runtime: AppRuntime, report: &str, settings_changed: bool) {
+        let title = gtk::Label::new(Some("Importing the library will make these changes:"));
+
+        let report_label = cascade! {
+            gtk::Label::new(Some(report));
+            ..set_xalign(0.0);
+            ..set_yalign(0.0);
+            ..set_selectable(true);
+            ..set_line_wrap(true);
+        };
+
+        let report_scroller = cascade! {
+            gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+            ..set_shadow_type(gtk::ShadowType::In);
+            ..set_min_content_height(200);
+            ..add(&report_label);
+        };
+
+        let settings_check = cascade! {
+            gtk::CheckButton::with_label("Also import the settings");
+            ..set_sensitive(settings_changed);
+        };
+
+        let content = cascade! {
+            gtk::Box::new(gtk::Orientation::Vertical, 8);
+            ..set_margin(10);
+            ..pack_start(&title, false, false, 0);
+            ..pack_start(&report_scroller, true, true, 0);
+            ..pack_start(&settings_check, false, false, 0);
+        };
+
+        let dialog = cascade! {
+            gtk::Dialog::new();
+            ..set_title(&format!("Import library - {}", PROGRAM_NAME));
+            ..set_modal(true);
+            ..set_transient_for(Some(&self.main_window));
+            ..add_button("Import", ResponseType::Ok);
+            ..add_button("Cancel", ResponseType::Cancel);
+            ..set_default_response(ResponseType::Ok);
+            ..set_default_size(460, 360);
+        };
+        dialog.content_area().pack_start(&content, true, true, 0);
+
+        dialog.connect_response(glib::clone!(@strong app_runtime => move |dialog, response| {
+            let include_settings = settings_check.is_active();
+            app_runtime.update_state_with(move |state| {
+                if response == ResponseType::Ok {
+                    state.import_library(include_settings);
+                } else {
+                    state.pending_import = None;
+                }
+            });
+            dialog.close();
+        }));
+
+        dialog.show_all();
+    }
+}
@@ -0,0 +1,37 @@
+use super::UI;
+
+use gtk::prelude::*;
+use gtk::ResponseType;
+use crate::PROGRAM_NAME;
+
+
+impl UI {
+    /// Show an external link so it can be copied. External links are never opened automatically.
+    pub fn external_link_dialog(&self, url: &str) {
+        let label = cascade! {
+            gtk::Label::new(Some(url));
+            ..set_selectable(true);
+            ..set_line_wrap(true);
+            ..set_margin(10);
+        };
+        
+        let url = url.to_string();
+        let _ = cascade! {
+            gtk::Dialog::new();
+            ..set_title(&format!("External link - {}", PROGRAM_NAME));
+            ..set_modal(true);
+            ..set_transient_for(Some(&self.main_window));
+            ..add_button("Copy", ResponseType::Apply);
+            ..add_button("Close", ResponseType::Close);
+            ..content_area().pack_start(&label, true, false, 12);
+            ..set_size_request(400, 140);
+            ..connect_response(move |dialog, response| {
+                if response == ResponseType::Apply {
+                    gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&url);
+                }
+                dialog.close();
+            });
+            ..show_all();
+        };
+    }
+}
@@ -2,32 +2,55 @@ mod settings_dialog;
 mod about_dialog;
 mod file_chooser_dialog;
 mod page_dialog;
+pub mod renderer;
+mod link_dialog;
+mod footnote_popover;
+mod error_dialog;
+pub mod library;
+mod find_bar;
+mod search_panel;
+mod bookmarks;
+mod highlights;
+mod library_archive_dialog;
+mod comic_view;
+mod pager;
+mod shortcuts;
+pub mod chapters;
+mod progress;
 
 use gtk::{prelude::*, Justification, TickCallbackId, CssProvider};
 
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::Path;
 use std::fmt;
 use crate::app::AppRuntime;
-use select::document::Document;
-use select::predicate::{Name, Any};
-use crate::appop::EpubBook;
+use crate::appop::OpenBook;
 use crate::ui::settings_dialog::SettingsDialog;
+use crate::ui::renderer::ChapterRenderer;
+use crate::ui::find_bar::FindBar;
+use crate::ui::comic_view::ComicView;
+use crate::ui::pager::Pager;
+use crate::ui::chapters::LoadedChapter;
+use crate::app::search::SearchHit;
 use serde::{Deserialize, Serialize};
-use regex::Regex;
-use crate::app::database::DatabaseRow;
 use crate::app::settings::Settings;
 use crate::app::utils::{add_additional_style, remove_style, Resources};
 use crate::PROGRAM_NAME;
 use crate::app::utils::BuilderExtManualCustom;
+use crate::app::book::TocItem;
+use select::document::Document;
 use std::str::FromStr;
-use glib::SignalHandlerId;
-use std::collections::HashMap;
 use std::io::Cursor;
 use gdk_pixbuf::Pixbuf;
 
-// Used when scrolling up and down with arrow keys
+// Used when scrolling up and down a line, with the arrow keys by default
 pub const SCROLL_AMOUNT: f64 = 120.0;
 
+// Table of contents tree store columns
+const TOC_LABEL_COLUMN: u32 = 0;
+const TOC_PAGE_COLUMN: u32 = 1;
+const TOC_ANCHOR_COLUMN: u32 = 2;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum Theme {
     None,
@@ -69,21 +92,39 @@ pub struct UI {
     pub scroller: gtk::ScrolledWindow,
     pub reader_header: gtk::TextView,
     pub reader: gtk::TextView,
+    /// Chapters currently in the reader, in the order of the book.
+    pub chapters: Vec<LoadedChapter>,
+    /// Load the chapters before and after the current one while scrolling.
+    pub continuous: bool,
+    /// A chapter was added and the view is restored once the reader is laid out.
+    chapter_loading: bool,
+    /// Chapters that could not be loaded in the continuous mode, they're not tried again.
+    failed_pages: HashSet<usize>,
     
     pub overlay: gtk::Overlay,
     pub overlay_notebook: gtk::Notebook,
     pub left_revealer: gtk::Revealer,
     pub left_content_box: gtk::Box,
     pub right_content_box: gtk::Box,
+    /// The books in the database changed while the library wasn't shown.
+    pub recent_outdated: bool,
     pub spinner: gtk::Spinner,
-    pub recent_handlers: HashMap<gtk::LinkButton, SignalHandlerId>,
     
-    pub toc_buttons: Option<Vec<gtk::Button>>,
+    pub toc_view: Option<gtk::TreeView>,
+    /// Page number or file name shown in the reader header, empty if neither is shown.
+    pub header_text: String,
+    /// The visible part of the page has a bookmark.
+    pub is_bookmarked: bool,
+    /// Results of the book search in the order of the search panel rows.
+    pub search_hits: Vec<SearchHit>,
 
     pub scrolling_tick: Option<TickCallbackId>,
     pub is_fullscreen: bool,
     
     pub settings_dialog: SettingsDialog,
+    pub find_bar: FindBar,
+    pub comic_view: ComicView,
+    pub pager: Pager,
     
     pub additional_css: Vec<CssProvider>,
 }
@@ -108,6 +149,9 @@ impl UI {
         main_window.set_icon(Some(&icon_pix));
     
         let settings_dialog = SettingsDialog::new(&builder, &main_window);
+        let find_bar = FindBar::new(&builder);
+        let comic_view = ComicView::new(&builder);
+        let pager = Pager::new(&builder);
     
         let overlay = builder.get::<gtk::Overlay>("overlay");
         let overlay_box = builder.get::<gtk::Box>("overlay_box");
@@ -132,20 +176,30 @@ impl UI {
             scroller: scroller_window,
             reader_header,
             reader,
+            chapters: vec![],
+            continuous: false,
+            chapter_loading: false,
+            failed_pages: HashSet::new(),
             
             overlay,
             overlay_notebook,
             left_revealer,
             left_content_box,
             right_content_box,
+            recent_outdated: false,
             spinner,
-            recent_handlers: HashMap::new(),
             
-            toc_buttons: None,
+            toc_view: None,
+            header_text: String::new(),
+            is_bookmarked: false,
+            search_hits: vec![],
             scrolling_tick: None,
             is_fullscreen: false,
             
             settings_dialog,
+            find_bar,
+            comic_view,
+            pager,
             
             additional_css: vec![]
         }
@@ -176,38 +230,86 @@ impl UI {
             gtk::Inhibit(false)
         }));
     
-        self.main_window.connect_key_press_event(glib::clone!(@strong app_runtime => move |_window, event| {
-            match event.keycode() {
-                // ARROW UP
-                Some(38) => {
-                    app_runtime.update_state_with(|state| {
-                        state.ui.scroll_up(SCROLL_AMOUNT);
-                    });
-                },
-                // ARROW DOWN
-                Some(40) => {
-                    app_runtime.update_state_with(|state| {
-                        state.ui.scroll_down(SCROLL_AMOUNT);
-                    });
-                },
-                // ARROW LEFT
-                Some(37) => {
-                    app_runtime.update_state_with(move |state| {
-                        state.previous_page();
-                    });
-                },
-                // ARROW RIGHT
-                Some(39) => {
-                    app_runtime.update_state_with(move |state| {
-                        state.next_page();
-                    });
-                }
-                _ => {}
+        self.main_window.connect_key_press_event(glib::clone!(@strong app_runtime => move |window, event| {
+            // Keys typed into an editable widget (like the find bar or a highlight note) and the
+            // arrow keys in the lists go to them before the single key shortcuts and the page turning.
+            let focus_handles_keys = window.focus().map_or(false, |widget| {
+                let is_editable_text = widget
+                    .downcast_ref::<gtk::TextView>()
+                    .map_or(false, |text_view| text_view.is_editable());
+                
+                widget.is::<gtk::Editable>() || is_editable_text || widget.is::<gtk::TreeView>() || widget.is::<gtk::ListBox>()
+            });
+            if focus_handles_keys && window.propagate_key_event(event) {
+                return gtk::Inhibit(true);
             }
-        
+            
+            // Everything else is bound through the keymap.
             gtk::Inhibit(false)
         }));
     
+        // Follow links clicked in the reader, highlight selected text
+        // and edit highlights that are clicked.
+        self.reader.connect_button_release_event(glib::clone!(@strong app_runtime => move |reader, event| {
+            let buffer = reader.buffer().expect("Could not get buffer");
+            if event.button() != 1 {
+                return gtk::Inhibit(false);
+            }
+            
+            // Selecting text over a link shouldn't follow it
+            if let Some((start, end)) = buffer.selection_bounds() {
+                let (start, end) = (start.offset(), end.offset());
+                app_runtime.update_state_with(move |state| {
+                    state.ui.new_highlight_popover(state.app_runtime.clone(), start, end);
+                });
+            } else if let Some(iter) = link_iter_at(reader, event.position()) {
+                let offset = iter.offset();
+                app_runtime.update_state_with(move |state| {
+                    state.open_link_at(offset);
+                });
+            } else {
+                let offset = iter_at_position(reader, event.position()).map(|iter| iter.offset());
+                let x = event.position().0;
+                app_runtime.update_state_with(move |state| {
+                    // Highlights can be edited in the page turning zones too
+                    if offset.map_or(false, |offset| state.edit_highlight_at(offset)) {
+                        return;
+                    }
+                    match state.ui.page_turn_zone(x) {
+                        Some(true) => state.next_screen_page(),
+                        Some(false) => state.previous_screen_page(),
+                        None => {}
+                    }
+                });
+            }
+            
+            gtk::Inhibit(false)
+        }));
+        
+        // Show a hand cursor over the links
+        self.reader.connect_motion_notify_event(|reader, event| {
+            let cursor_type = match link_iter_at(reader, event.position()) {
+                Some(_) => gdk::CursorType::Hand2,
+                None => gdk::CursorType::Xterm,
+            };
+            
+            if let Some(window) = reader.window(gtk::TextWindowType::Text) {
+                let cursor = gdk::Cursor::for_display(&window.display(), cursor_type);
+                window.set_cursor(Some(&cursor));
+            }
+            
+            gtk::Inhibit(false)
+        });
+    
+        self.connect_search_panel(app_runtime.clone());
+        self.connect_bookmarks(app_runtime.clone());
+        self.connect_highlights(app_runtime.clone());
+        self.connect_comic_view(app_runtime.clone());
+        self.connect_pager(app_runtime.clone());
+        self.connect_chapters(app_runtime.clone());
+        self.connect_progress(app_runtime.clone());
+        self.connect_library(app_runtime.clone());
+    
         // Change the reader font size on the fly
         let fontsize = &self.builder.get::<gtk::SpinButton>( "fontsize_spin");
         fontsize.connect_changed(glib::clone!(@strong app_runtime => move |elem| {
@@ -224,30 +326,90 @@ impl UI {
         self.settings_dialog.dialog.show();
     }
     
-    /// Update the box which contains buttons for opening pages (left side).
-    pub fn update_toc(&self, app_runtime: AppRuntime, book: &mut EpubBook) {
+    /// Update the box which contains the table of contents (left side).
+    ///
+    /// Uses the table of contents of the book and falls back to
+    /// listing the sections if the book doesn't have one.
+    pub fn update_toc(&mut self, app_runtime: AppRuntime, book: &mut OpenBook) {
         // Clear the whole box before adding new elements
         for child in self.left_content_box.children() {
             self.left_content_box.remove(&child);
         }
+        self.toc_view = None;
         
-        // Get the spine from the epub doc and make the buttons
-        for spine_item in &book.doc.spine {
-            let ch = gtk::LinkButton::new(spine_item);
-            let new_chap = book
-                .doc
-                .resource_uri_to_chapter(&book
-                    .doc
-                    .resources
-                    .get(spine_item)
-                    .unwrap()
-                    .0
-                );
-            ch.set_label(&book.current_chapter_file_name(spine_item));
+        if book.doc.toc().is_empty() {
+            self.update_toc_from_sections(app_runtime, book);
+        } else {
+            self.update_toc_from_nav(app_runtime, book);
+        }
+    
+        self.left_content_box.show_all();
+    }
+    
+    /// Collapsible tree of the book's own table of contents.
+    fn update_toc_from_nav(&mut self, app_runtime: AppRuntime, book: &mut OpenBook) {
+        let store = gtk::TreeStore::new(&[
+            String::static_type(),
+            i32::static_type(),
+            String::static_type(),
+        ]);
+        fill_toc_store(&store, None, book.doc.toc());
+        
+        let cell = gtk::CellRendererText::new();
+        cell.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        let column = cascade! {
+            gtk::TreeViewColumn::new();
+            ..pack_start(&cell, true);
+            ..add_attribute(&cell, "text", TOC_LABEL_COLUMN as i32);
+        };
+        
+        let tree = cascade! {
+            gtk::TreeView::with_model(&store);
+            ..append_column(&column);
+            ..set_headers_visible(false);
+            ..set_activate_on_single_click(true);
+            ..set_enable_search(false);
+            ..set_vexpand(true);
+        };
+        
+        tree.connect_row_activated(glib::clone!(@strong app_runtime => move |tree, path, _| {
+            let model = match tree.model() {
+                Some(model) => model,
+                None => return,
+            };
+            let iter = match model.iter(path) {
+                Some(iter) => iter,
+                None => return,
+            };
+            
+            let page = model.value(&iter, TOC_PAGE_COLUMN as i32).get::<i32>().unwrap_or(-1);
+            let anchor = model.value(&iter, TOC_ANCHOR_COLUMN as i32).get::<String>().unwrap_or_default();
+            // Entries that only group other entries don't point to a page
+            if page < 0 {
+                return;
+            }
+            
+            app_runtime.update_state_with(move |state| {
+                let anchor = if anchor.is_empty() { None } else { Some(anchor) };
+                state.open_page_at_anchor(page as usize, anchor);
+                // Close the toc revealer
+                state.ui.toggle_toc();
+            });
+        }));
+        
+        self.left_content_box.add(&tree);
+        self.toc_view = Some(tree);
+    }
+    
+    /// List of the sections by their file names.
+    fn update_toc_from_sections(&self, app_runtime: AppRuntime, book: &mut OpenBook) {
+        for (new_chap, section) in book.doc.sections().iter().enumerate() {
+            let ch = gtk::LinkButton::new(&section.path.to_string_lossy());
+            ch.set_label(&section.display_name());
             ch.connect_activate_link(glib::clone!(@strong app_runtime => move |_| {
                 app_runtime.update_state_with(move |state| {
                     // Open the requested page
-                    state.open_page_send(new_chap.unwrap());
+                    state.open_page_send(new_chap);
                     // Close the toc revealer
                     state.ui.toggle_toc();
                 });
@@ -256,116 +418,175 @@ impl UI {
             }));
             self.left_content_box.add(&ch);
         }
-    
-        self.left_content_box.show_all();
     }
     
-    /// Update the box which contains recent files (right side).
-    ///
-    /// TODO: Probably inefficient, improve.
-    pub fn update_recent(&mut self, app_runtime: AppRuntime, rows: &[DatabaseRow]) {
-        // If there are old handlers then disconnect them
-        // so they can be added back.
-        if !self.recent_handlers.is_empty() {
-            for (btn, handler) in self.recent_handlers.drain() {
-                btn.disconnect(handler);
+    /// Select the first table of contents entry of the `page` and expand its parents.
+    pub fn select_toc_page(&self, page: usize) {
+        let tree = match &self.toc_view {
+            Some(tree) => tree,
+            None => return,
+        };
+        let model = match tree.model() {
+            Some(model) => model,
+            None => return,
+        };
+        
+        let mut found = None;
+        model.foreach(|model, path, iter| {
+            let row_page = model.value(iter, TOC_PAGE_COLUMN as i32).get::<i32>().unwrap_or(-1);
+            if row_page == page as i32 {
+                found = Some(path.clone());
+                return true;
             }
+            false
+        });
+        
+        let selection = tree.selection();
+        match found {
+            Some(path) => {
+                tree.expand_to_path(&path);
+                selection.select_path(&path);
+            },
+            None => selection.unselect_all(),
         }
+    }
     
-        // Remove any child elements
-        for child in self.right_content_box.children() {
-            self.right_content_box.remove(&child);
-        }
+    /// Show the current page of the book in the reader.
+    pub fn update(&mut self, book: &mut OpenBook, settings: &Settings) {
+        self.update_chapter_info(book, settings);
         
-        for row in rows {
-            let link_button = gtk::LinkButton::new(row.file.to_str().unwrap());
-            link_button.set_label(&format!("{} — page {}", row.filename.to_str().unwrap(), row.current_page + 1));
-            let handler = link_button.connect_activate_link(glib::clone!(@strong app_runtime => move |btn| {
-                let file_path = PathBuf::from(btn.uri().unwrap().to_string());
-                app_runtime.update_state_with(move |state| {
-                    // Open the file
-                    state.open_file_from_path(file_path, None);
-                    // Close the recent revealer
-                    // state.ui.toggle_recent_files();
-                });
-                gtk::Inhibit(true)
-            }));
-            self.right_content_box.add(&link_button);
-            self.recent_handlers.insert(link_button, handler);
-        }
+        let book_content = match book.doc.section_content(book.current_page) {
+            Ok(content) => content,
+            Err(e) => {
+                error!("{}", e);
+                
+                Document::from("Error loading content :(")
+            }
+        };
         
-        self.right_content_box.show_all();
+        let chapter_path = book.current_section().path.clone();
+        let mut load_resource = |path: &Path| book.doc.resource(path);
+        
+        self.reader.set_justification(Justification::Left);
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let rendered = ChapterRenderer::new(&buffer, chapter_path, self.reader_content_width(), &mut load_resource)
+            .render(&book_content);
+        self.set_single_chapter(book.current_page, rendered);
+        self.apply_highlights(&book.highlights);
+        self.reset_pages();
+        
+        // Keep highlighting the find bar matches in the new chapter
+        self.find_in_chapter(false);
     }
     
-    pub fn update(&self, book: &mut EpubBook, settings: &Settings) {
+    /// Show the current page in the header, the window title and the table of contents.
+    pub fn update_chapter_info(&mut self, book: &OpenBook, settings: &Settings) {
         let chapter_list_label = &self.builder.get::<gtk::Label>("chapter_list_label");
         
-        let current_chapter_id = book.doc.get_current_id().unwrap();
-        let current_chapter_filename = book.current_chapter_file_name(&current_chapter_id);
+        let current_chapter_filename = book.current_section().display_name();
         chapter_list_label.set_label(&format!("Reading: {:?}", current_chapter_filename));
-        
-        if !settings.general.show_page_num && !settings.general.show_page_file {
-            self.reader_header.set_visible(false);
-        } else {
-            self.reader_header.set_visible(true);
-        }
+        self.select_toc_page(book.current_page);
         
         let mut header_content = String::new();
-        if settings.general.show_page_num && !settings.general.show_page_file {
-            header_content.push_str(&format!("Page {}", book.doc.get_current_page() + 1));
+        if !settings.general.show_page_num && !settings.general.show_page_file {
+            // Nothing to show besides the bookmark marker
+        } else if settings.general.show_page_num && !settings.general.show_page_file {
+            header_content.push_str(&format!("Page {}", book.current_page + 1));
         } else if settings.general.show_page_file && !settings.general.show_page_num {
             header_content.push_str(&current_chapter_filename);
         } else {
-            header_content.push_str(&format!("Page {} - {}", book.doc.get_current_page() + 1, current_chapter_filename));
+            header_content.push_str(&format!("Page {} - {}", book.current_page + 1, current_chapter_filename));
         }
         
-        self.reader_header
-            .buffer()
-            .expect("Could not get buffer")
-            .set_text(&header_content);
-        
-        let book_content = match book.doc.get_current_str() {
-            Ok(content) => content,
-            Err(e) => {
-                error!("{}", e);
-                
-                "Error loading content :(".to_string()
-            }
-        };
+        self.header_text = header_content;
+        self.update_header();
         
         let book_filename = book.file.file_name().unwrap().to_str().unwrap().to_string();
         
         self.main_window.set_title(&format!("{} - {} - {}", current_chapter_filename, book_filename, PROGRAM_NAME));
+    }
+    
+    /// Width available for the reader content, without the margins.
+    pub fn reader_content_width(&self) -> i32 {
+        let width = self.reader.allocated_width().max(self.reader.width_request());
         
-        let mut content = String::new();
-        
-        if book_content.contains("</p>") {
-            let html = Document::from(book_content.as_str());
-            for tag in html.select(Any) {
-                if let Some(name) = tag.name() {
-                    if name == "p" {
-                        content.push_str(tag.text().as_str());
-                        content.push_str("\n\n");
-                    } else {
-                        // content.push_str("");
-                    }
-                }
-            }
-        } else {
-            let re = Regex::new(r"<br.?/>").unwrap();
-            let result = re.replace_all(book_content.as_str(), "\n");
+        width - self.reader.left_margin() - self.reader.right_margin()
+    }
     
-            let html = Document::from(result.to_string().as_str());
-            for body in html.select(Name("body")) {
-                content.push_str(body.text().as_str());
-            }
+    /// Scroll so that the character at `offset` in the chapter at the top is at the top.
+    pub fn scroll_to_offset(&mut self, offset: i32) {
+        let start = self.chapter_at_top().map_or(0, |chapter| self.chapter_start(chapter));
+        self.scroll_to_buffer_offset(start + offset);
+    }
+    
+    /// Scroll so that the character at `offset` in the reader buffer is at the top.
+    /// The paginated reader shows the screen page with the character instead.
+    pub fn scroll_to_buffer_offset(&mut self, offset: i32) {
+        if self.is_paginated() {
+            self.show_screen_page_at_offset(offset);
+            return;
         }
         
-        self.reader.set_justification(Justification::Left);
-        self.reader
-            .buffer()
-            .expect("Could not get buffer")
-            .set_text(&content);
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let iter = buffer.iter_at_offset(offset);
+        let location = self.reader.iter_location(&iter);
+        let (_, y) = self.reader.buffer_to_window_coords(gtk::TextWindowType::Widget, location.x(), location.y());
+        
+        // The reader is below the header in the scrolled box.
+        let adj = self.scroller.vadjustment();
+        adj.set_value((self.reader.allocation().y() + y) as f64);
+    }
+    
+    /// Char offset of the first line visible at the top of the reader, in the chapter it is in.
+    pub fn offset_at_top(&self) -> i32 {
+        let offset = self.buffer_offset_at_top();
+        
+        self.chapter_position(offset).map_or(offset, |(_, offset)| offset)
+    }
+    
+    /// Char offset of the first line visible at the top of the reader in the reader buffer.
+    pub fn buffer_offset_at_top(&self) -> i32 {
+        let adj = self.scroller.vadjustment();
+        let y = (adj.value() as i32 - self.reader.allocation().y()).max(0);
+        let (_, buffer_y) = self.reader.window_to_buffer_coords(gtk::TextWindowType::Widget, 0, y);
+        let (iter, _) = self.reader.line_at_y(buffer_y);
+        
+        iter.offset()
+    }
+    
+    /// Area of the char at `offset` in the reader widget, for pointing popovers at it.
+    pub fn offset_rectangle(&self, offset: i32) -> gdk::Rectangle {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let location = self.reader.iter_location(&buffer.iter_at_offset(offset));
+        let (x, y) = self.reader.buffer_to_window_coords(gtk::TextWindowType::Widget, location.x(), location.y());
+        
+        gdk::Rectangle::new(x, y, location.width().max(1), location.height())
+    }
+    
+    /// Char offsets of the first and the last line visible in the reader,
+    /// in the chapter at the top.
+    pub fn visible_offsets(&self) -> (i32, i32) {
+        let adj = self.scroller.vadjustment();
+        let y = (adj.value() + adj.page_size()) as i32 - self.reader.allocation().y();
+        let (_, buffer_y) = self.reader.window_to_buffer_coords(gtk::TextWindowType::Widget, 0, y.max(0));
+        let (iter, _) = self.reader.line_at_y(buffer_y);
+        let mut line_end = iter.clone();
+        line_end.forward_to_line_end();
+        
+        let top = self.buffer_offset_at_top();
+        let (top, start) = self.chapter_position(top).map_or((top, 0), |(_, offset)| (offset, top - offset));
+        
+        (top, line_end.offset() - start)
+    }
+    
+    /// Scroll to the element with the id `anchor` in the current chapter.
+    /// Scrolls to the top if the chapter has no such element.
+    pub fn scroll_to_anchor(&mut self, anchor: &str) {
+        let offset = self.chapter_at_top().and_then(|chapter| chapter.rendered.anchors.get(anchor)).copied();
+        match offset {
+            Some(offset) => self.scroll_to_offset(offset),
+            None => self.scroll_to_top(),
+        }
     }
     
     /// Removes the old theme class from elements and
@@ -383,6 +604,9 @@ impl UI {
         let recent_scroller = &self.builder.get::<gtk::ScrolledWindow>("recent_scroller");
         let toc_scroller = &self.builder.get::<gtk::ScrolledWindow>("toc_scroller");
         let toc_container = &self.builder.get::<gtk::Box>("toc_container");
+        let search_container = &self.builder.get::<gtk::Box>("search_container");
+        let bookmarks_scroller = &self.builder.get::<gtk::ScrolledWindow>("bookmarks_scroller");
+        let highlights_scroller = &self.builder.get::<gtk::ScrolledWindow>("highlights_scroller");
         
         self.reader_header.style_context().remove_class(&old_theme_name);
         self.reader_header.style_context().add_class(&theme_name);
@@ -390,6 +614,13 @@ impl UI {
         self.reader.style_context().remove_class(&old_theme_name);
         self.reader.style_context().add_class(&theme_name);
         
+        self.pager.header.style_context().remove_class(&old_theme_name);
+        self.pager.header.style_context().add_class(&theme_name);
+        
+        let reader_box = &self.builder.get::<gtk::Box>("reader_box");
+        reader_box.style_context().remove_class(&old_theme_name);
+        reader_box.style_context().add_class(&theme_name);
+        
         self.scroller.style_context().remove_class(&old_theme_name);
         self.scroller.style_context().add_class(&theme_name);
         
@@ -404,6 +635,15 @@ impl UI {
         
         toc_container.style_context().remove_class(&old_theme_name);
         toc_container.style_context().add_class(&theme_name);
+        
+        search_container.style_context().remove_class(&old_theme_name);
+        search_container.style_context().add_class(&theme_name);
+        
+        bookmarks_scroller.style_context().remove_class(&old_theme_name);
+        bookmarks_scroller.style_context().add_class(&theme_name);
+        
+        highlights_scroller.style_context().remove_class(&old_theme_name);
+        highlights_scroller.style_context().add_class(&theme_name);
     }
     
     /// Set font size for the reader element in pixels.
@@ -472,7 +712,12 @@ impl UI {
     }
     
     /// Start scrolling down and save the `TickCallbackId` so it can be stopped.
+    /// The paginated reader doesn't scroll.
     pub fn start_scrolling_down(&mut self, speed: f64) {
+        if self.is_paginated() {
+            return;
+        }
+        
         self.scrolling_tick = scroll_down_automatic(&mut self.scroller, speed);
     }
     
@@ -494,14 +739,24 @@ impl UI {
         }
     }
     
-    /// Scroll to the top.
+    /// Scroll to the bottom, or show the last screen page.
     pub fn scroll_to_bottom(&mut self) {
+        if self.is_paginated() {
+            self.show_last_screen_page();
+            return;
+        }
+        
         let adj = self.scroller.vadjustment();
         adj.set_value(adj.upper() - adj.page_size());
     }
     
-    /// Scroll to the bottom.
+    /// Scroll to the top, or show the first screen page.
     pub fn scroll_to_top(&mut self) {
+        if self.is_paginated() {
+            self.show_screen_page_at_offset(0);
+            return;
+        }
+        
         let adj = self.scroller.vadjustment();
         adj.set_value(0.0);
     }
@@ -520,22 +775,42 @@ impl UI {
 
     /// Toggle recent files revealer and notebook on or off.
     pub fn toggle_recent_files(&mut self) {
-        // Check if the revealer needs to be opened or closed
-        if !(self.left_revealer.is_child_revealed() && self.overlay_notebook.page() == 1) {
-            self.left_revealer.set_reveal_child(!self.left_revealer.is_child_revealed());
-        }
-        // Open the correct notebook page
-        self.overlay_notebook.set_page(0);
+        self.toggle_side_page(0);
     }
     
     /// Toggle table of contents revealer and notebook on or off.
     pub fn toggle_toc(&mut self) {
+        self.toggle_side_page(1);
+    }
+    
+    /// Toggle book search revealer and notebook on or off.
+    pub fn toggle_search(&mut self) {
+        self.toggle_side_page(2);
+        if self.left_revealer.reveals_child() {
+            self.builder.get::<gtk::SearchEntry>("book_search_entry").grab_focus();
+        }
+    }
+    
+    /// Toggle bookmarks revealer and notebook on or off.
+    pub fn toggle_bookmarks(&mut self) {
+        self.toggle_side_page(3);
+    }
+    
+    /// Toggle highlights revealer and notebook on or off.
+    pub fn toggle_highlights(&mut self) {
+        self.toggle_side_page(4);
+    }
+    
+    /// Close the revealer if it shows the notebook `page`, otherwise
+    /// open it on that page.
+    fn toggle_side_page(&mut self, page: i32) {
         // Check if the revealer needs to be opened or closed
-        if !(self.left_revealer.is_child_revealed() && self.overlay_notebook.page() == 0) {
-            self.left_revealer.set_reveal_child(!self.left_revealer.is_child_revealed());
+        let is_open = self.left_revealer.is_child_revealed();
+        if !is_open || self.overlay_notebook.page() == page {
+            self.left_revealer.set_reveal_child(!is_open);
         }
         // Open the correct notebook page
-        self.overlay_notebook.set_page(1);
+        self.overlay_notebook.set_page(page);
     }
     
     /// Toggle spinner to show that the app is doing something.
@@ -549,37 +824,61 @@ impl UI {
     }
 }
 
+/// Add the `items` and their children recursively under `parent`.
+fn fill_toc_store(store: &gtk::TreeStore, parent: Option<&gtk::TreeIter>, items: &[TocItem]) {
+    for item in items {
+        let page = item.section.map(|page| page as i32).unwrap_or(-1);
+        let anchor = item.anchor.clone().unwrap_or_default();
+        
+        let iter = store.insert_with_values(
+            parent,
+            None,
+            &[TOC_LABEL_COLUMN, TOC_PAGE_COLUMN, TOC_ANCHOR_COLUMN],
+            &[&item.label, &page, &anchor]
+        );
+        
+        fill_toc_store(store, Some(&iter), &item.children);
+    }
+}
+
+/// Text iter under the `position` in the reader if there's a link.
+fn link_iter_at(reader: &gtk::TextView, position: (f64, f64)) -> Option<gtk::TextIter> {
+    let buffer = reader.buffer()?;
+    let link_tag = buffer.tag_table()?.lookup("link")?;
+    
+    iter_at_position(reader, position).filter(|iter| iter.has_tag(&link_tag))
+}
+
+/// Text iter under the `position` in the reader.
+fn iter_at_position(reader: &gtk::TextView, position: (f64, f64)) -> Option<gtk::TextIter> {
+    let (x, y) = reader.window_to_buffer_coords(gtk::TextWindowType::Widget, position.0 as i32, position.1 as i32);
+    
+    reader.iter_at_location(x, y)
+}
+
 /// Automatic scrolling down logic.
 fn scroll_down_automatic(view: &mut gtk::ScrolledWindow, duration_multiplier: f64) -> Option<TickCallbackId> {
     let adj = view.vadjustment();
     if let Some(clock) = view.frame_clock() {
-        let start = adj.value();
-        let real_end = adj.upper() - adj.page_size();
-        // Large default end value so the length doesn't
-        // affect the scrolling speedoverlay_box
-        let mut end = 99_999.0;
-        // Fallback just in case. I don't expect epub pages to past 20_000 normally.
-        if real_end > end {
-            end = real_end
-        };
+        // Large distance so the length doesn't
+        // affect the scrolling speed
+        let distance = 99_999.0;
         // Larger value for slower scrolling
         let duration = (duration_multiplier * 1_000_000.0) as i64;
         
         let start_time = clock.frame_time();
         let end_time = start_time + 1000 * duration;
+        let mut last_time = start_time;
         let tick_id = view.add_tick_callback(move |_view, clock| {
             let now = clock.frame_time();
-            if now < end_time && (adj.value() - end).abs() > f64::EPSILON {
-                let t = (now - start_time) as f64 / (end_time - start_time) as f64;
-                // t = ease_out_cubic(t);
-                // Comment out start to make the speed constant regardless of
-                // the current scroll position
-                // Otherwise it gets slower the closer the end is
-                // adj.set_value(start + t * (end - start));
-                adj.set_value(start + t * end);
+            if now < end_time {
+                // Move on from the current position instead of the start, so it keeps
+                // going when the continuous reader adds or removes chapters
+                let step = (now - last_time) as f64 / (end_time - start_time) as f64;
+                last_time = now;
+                adj.set_value(adj.value() + step * distance);
                 glib::Continue(true)
             } else {
-                adj.set_value(end);
                 glib::Continue(false)
             }
         });
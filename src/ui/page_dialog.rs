@@ -29,8 +29,7 @@ impl UI {
             ..content_area().pack_end(&spin_btn, true, false, 12);
             ..set_size_request(300, 140);
             ..connect_key_press_event(move |dialog, event| {
-                // ENTER
-                if let Some(13) = event.keycode() {
+                if matches!(event.keyval(), gdk::keys::constants::Return | gdk::keys::constants::KP_Enter) {
                     dialog.response(ResponseType::Ok);
                 }
                 gtk::Inhibit(false)
@@ -0,0 +1,287 @@
+use super::UI;
+
+use gtk::prelude::*;
+use crate::app::AppRuntime;
+use crate::app::utils::BuilderExtManualCustom;
+
+/// Bottom margin of the reader in the ui file.
+const READER_BOTTOM_MARGIN: i32 = 20;
+/// Part of the reader width on each side where a click turns the page.
+const PAGE_TURN_ZONE: f64 = 0.25;
+
+/// Where the paginated reader should be once the pages are known.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PageTarget {
+    /// The screen page with the char at the offset.
+    Offset(i32),
+    /// The last screen page of the chapter.
+    Last,
+}
+
+/// Splits the chapter into screen sized pages in the paginated mode.
+///
+/// The pages are computed from the layout of the reader, so they change with
+/// the window size and the font. The `target` keeps the reading position
+/// when that happens.
+pub struct Pager {
+    pub enabled: bool,
+    pub header: gtk::TextView,
+    /// Scroll position of the top of every screen page.
+    pages: Vec<f64>,
+    current: usize,
+    target: PageTarget,
+}
+
+impl Pager {
+    pub fn new(builder: &gtk::Builder) -> Pager {
+        Pager {
+            enabled: false,
+            header: builder.get::<gtk::TextView>("paged_header"),
+            pages: vec![],
+            current: 0,
+            target: PageTarget::Offset(0),
+        }
+    }
+}
+
+impl UI {
+    pub fn connect_pager(&self, app_runtime: AppRuntime) {
+        // Compute the pages again when the size of the chapter or the window changes.
+        self.scroller.vadjustment().connect_changed(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.ui.paginate());
+        }));
+
+        // The mouse wheel turns the pages.
+        self.scroller.connect_scroll_event(glib::clone!(@strong app_runtime => move |scroller, event| {
+            // Only the paginated reader hides the scrollbar this way.
+            let paginated = scroller.policy().1 == gtk::PolicyType::External;
+            if !paginated || event.state().contains(gdk::ModifierType::CONTROL_MASK) {
+                return gtk::Inhibit(false);
+            }
+
+            let forward = match event.direction() {
+                gdk::ScrollDirection::Up => false,
+                gdk::ScrollDirection::Down => true,
+                gdk::ScrollDirection::Smooth if event.delta().1 < 0.0 => false,
+                gdk::ScrollDirection::Smooth if event.delta().1 > 0.0 => true,
+                _ => return gtk::Inhibit(false),
+            };
+            app_runtime.update_state_with(move |state| {
+                if forward {
+                    state.next_screen_page();
+                } else {
+                    state.previous_screen_page();
+                }
+            });
+
+            gtk::Inhibit(true)
+        }));
+    }
+
+    /// The reader shows screen pages, only text books are paginated.
+    pub fn is_paginated(&self) -> bool {
+        self.pager.enabled && self.reader.is_visible()
+    }
+
+    /// Switch between the paginated and the scrolling reader.
+    pub fn set_paginated(&mut self, paginated: bool) {
+        if paginated && !self.pager.enabled {
+            self.pager.target = PageTarget::Offset(self.buffer_offset_at_top());
+        }
+        self.pager.enabled = paginated;
+        self.update_pager_mode();
+    }
+
+    /// Hide the scrollbar and move the header out of the scrolled area in the paginated mode.
+    pub(super) fn update_pager_mode(&mut self) {
+        let paginated = self.is_paginated();
+        let policy = if paginated { gtk::PolicyType::External } else { gtk::PolicyType::Always };
+        self.scroller.set_policy(gtk::PolicyType::Never, policy);
+
+        if !paginated {
+            self.pager.pages.clear();
+            self.reader.set_bottom_margin(READER_BOTTOM_MARGIN);
+        }
+        self.update_header();
+        self.paginate();
+    }
+
+    /// Go to the first screen page once the chapter is laid out.
+    pub(super) fn reset_pages(&mut self) {
+        self.pager.pages.clear();
+        self.pager.current = 0;
+        self.pager.target = PageTarget::Offset(0);
+    }
+
+    /// Split the chapter into screen pages and show the page with the target position.
+    pub fn paginate(&mut self) {
+        if !self.is_paginated() {
+            return;
+        }
+
+        // Room after the text so the last page can be scrolled to the top too.
+        let adj = self.scroller.vadjustment();
+        let page_height = adj.page_size() as i32;
+        let bottom_margin = READER_BOTTOM_MARGIN + page_height;
+        if self.reader.bottom_margin() != bottom_margin {
+            self.reader.set_bottom_margin(bottom_margin);
+        }
+
+        self.pager.pages = self.screen_pages(page_height);
+        let page = match self.pager.target {
+            PageTarget::Offset(offset) => self.screen_page_at(offset),
+            PageTarget::Last => self.pager.pages.len() - 1,
+        };
+        self.set_screen_page(page);
+    }
+
+    /// Show the screen page at `index`, the position is kept when the pages change.
+    pub fn show_screen_page(&mut self, index: usize) {
+        if !self.is_paginated() || index >= self.pager.pages.len() {
+            return;
+        }
+
+        self.set_screen_page(index);
+        self.pager.target = PageTarget::Offset(self.buffer_offset_at_top());
+    }
+
+    /// Show the next screen page. `false` if the last page is already shown.
+    pub fn next_screen_page(&mut self) -> bool {
+        if self.pager.current + 1 >= self.pager.pages.len() {
+            return false;
+        }
+
+        self.show_screen_page(self.pager.current + 1);
+        true
+    }
+
+    /// Show the previous screen page. `false` if the first page is already shown.
+    pub fn previous_screen_page(&mut self) -> bool {
+        if self.pager.current == 0 || self.pager.pages.is_empty() {
+            return false;
+        }
+
+        self.show_screen_page(self.pager.current - 1);
+        true
+    }
+
+    /// Show the screen page with the char at `offset`.
+    pub(super) fn show_screen_page_at_offset(&mut self, offset: i32) {
+        self.pager.target = PageTarget::Offset(offset);
+        if !self.pager.pages.is_empty() {
+            let page = self.screen_page_at(offset);
+            self.set_screen_page(page);
+        }
+    }
+
+    pub(super) fn show_last_screen_page(&mut self) {
+        self.pager.target = PageTarget::Last;
+        if !self.pager.pages.is_empty() {
+            self.set_screen_page(self.pager.pages.len() - 1);
+        }
+    }
+
+    /// Show the header `content` above the reader, and the screen page in the chapter when the
+    /// reader is paginated. The paginated reader keeps its header above the scrolled pages.
+    pub(super) fn show_header(&self, mut content: String) {
+        if let Some((page, num_pages)) = self.screen_page_status() {
+            if !self.header_text.is_empty() {
+                content.push_str(" - ");
+            }
+            content.push_str(&format!("{} of {} in chapter", page + 1, num_pages));
+        }
+
+        let (header, other_header) = if self.is_paginated() {
+            (&self.pager.header, &self.reader_header)
+        } else {
+            (&self.reader_header, &self.pager.header)
+        };
+        other_header.set_visible(false);
+        header.set_visible(!content.is_empty());
+
+        // Setting the same text would lay out the header and the pages again.
+        let buffer = header.buffer().expect("Could not get buffer");
+        let (start, end) = buffer.bounds();
+        if buffer.text(&start, &end, false).map_or(true, |text| text != content) {
+            buffer.set_text(&content);
+        }
+    }
+
+    /// The current screen page and the number of them, if the reader is paginated.
+    fn screen_page_status(&self) -> Option<(usize, usize)> {
+        if !self.is_paginated() || self.pager.pages.is_empty() {
+            return None;
+        }
+
+        Some((self.pager.current, self.pager.pages.len()))
+    }
+
+    /// Whether a click at `x` in the reader turns the page, `Some(true)` for the next page.
+    pub fn page_turn_zone(&self, x: f64) -> Option<bool> {
+        if !self.is_paginated() {
+            return None;
+        }
+
+        let width = self.reader.allocated_width() as f64;
+        if x < width * PAGE_TURN_ZONE {
+            Some(false)
+        } else if x > width * (1.0 - PAGE_TURN_ZONE) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    fn set_screen_page(&mut self, index: usize) {
+        self.pager.current = index;
+        self.scroller.vadjustment().set_value(self.pager.pages[index]);
+        self.update_header();
+    }
+
+    /// Index of the screen page that has the char at `offset`.
+    fn screen_page_at(&self, offset: i32) -> usize {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let location = self.reader.iter_location(&buffer.iter_at_offset(offset));
+        let (_, y) = self.reader.buffer_to_window_coords(gtk::TextWindowType::Widget, location.x(), location.y());
+        let y = (self.reader.allocation().y() + y) as f64;
+
+        self.pager.pages.iter().rposition(|top| *top <= y).unwrap_or(0)
+    }
+
+    /// Scroll positions of the screen pages. A page ends before the line that doesn't fit
+    /// on it completely, unless the line is taller than the page like a large image.
+    fn screen_pages(&self, page_height: i32) -> Vec<f64> {
+        let reader_y = self.reader.allocation().y();
+        let mut pages = vec![reader_y as f64];
+        if page_height <= 0 {
+            return pages;
+        }
+
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let end = self.reader.iter_location(&buffer.end_iter());
+        let (_, text_bottom) = self.reader.buffer_to_window_coords(gtk::TextWindowType::Widget, 0, end.y() + end.height());
+
+        let mut top = 0;
+        while top + page_height < text_bottom {
+            let bottom = top + page_height;
+            let (_, buffer_y) = self.reader.window_to_buffer_coords(gtk::TextWindowType::Widget, 0, bottom);
+            let (_, line_top) = self.reader.buffer_to_window_coords(gtk::TextWindowType::Widget, 0, self.display_line_top(buffer_y));
+
+            top = if line_top > top { line_top } else { bottom };
+            pages.push((reader_y + top) as f64);
+        }
+
+        pages
+    }
+
+    /// Top of the wrapped line at the `buffer_y` in buffer coordinates.
+    fn display_line_top(&self, buffer_y: i32) -> i32 {
+        let (mut line, _) = self.reader.line_at_y(buffer_y);
+        let mut next = line.clone();
+        while self.reader.forward_display_line(&mut next) && self.reader.iter_location(&next).y() <= buffer_y {
+            line = next.clone();
+        }
+
+        self.reader.iter_location(&line).y()
+    }
+}
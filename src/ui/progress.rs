@@ -0,0 +1,37 @@
+use super::UI;
+
+use gtk::prelude::*;
+use crate::app::AppRuntime;
+use crate::app::progress::format_reading_time;
+use crate::app::utils::BuilderExtManualCustom;
+
+
+impl UI {
+    pub fn connect_progress(&self, app_runtime: AppRuntime) {
+        self.scroller.vadjustment().connect_value_changed(glib::clone!(@strong app_runtime => move |_| {
+            app_runtime.update_state_with(|state| state.update_progress());
+        }));
+    }
+
+    /// Show the `progress` in the book and how long the `chapter_left` and the `book_left`
+    /// chars take to read at the `speed`, if it has been measured already.
+    pub fn show_progress(&self, progress: f64, chapter_left: usize, book_left: usize, speed: Option<f64>) {
+        let mut status = format!("{}%", (progress * 100.0).floor());
+        if let Some(speed) = speed {
+            status.push_str(&format!(
+                " · {} left in chapter · {} left in book",
+                format_reading_time(chapter_left, speed),
+                format_reading_time(book_left, speed)
+            ));
+        }
+
+        self.builder.get::<gtk::ProgressBar>("reading_progress_bar").set_fraction(progress);
+        self.builder.get::<gtk::Label>("reading_status_label").set_label(&status);
+        self.builder.get::<gtk::Box>("reading_status_box").set_visible(true);
+    }
+
+    /// Hide the progress until it's known, and for comic books.
+    pub fn hide_progress(&self) {
+        self.builder.get::<gtk::Box>("reading_status_box").set_visible(false);
+    }
+}
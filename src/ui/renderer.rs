@@ -0,0 +1,600 @@
+use gtk::prelude::*;
+use gtk::{pango, TextBuffer, Justification};
+use gdk_pixbuf::{Pixbuf, InterpType};
+use select::document::Document;
+use select::node::Node;
+use select::predicate::Name;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::mem;
+use std::path::{Path, PathBuf};
+use crate::app::utils::{resolve_href, has_epub_type};
+
+
+/// Heading elements and their size relative to the reader font size.
+const HEADINGS: [(&str, f64); 6] = [
+    ("h1", 2.0),
+    ("h2", 1.6),
+    ("h3", 1.4),
+    ("h4", 1.2),
+    ("h5", 1.1),
+    ("h6", 1.0),
+];
+
+/// Numeric value of `pango::Weight::Bold`, the tag `weight` property is an int.
+const BOLD: i32 = 700;
+/// Left margin in pixels for each level of list or blockquote nesting.
+const INDENT: i32 = 36;
+/// The reader's own left margin, tag margins replace it so it has to be included.
+const BASE_MARGIN: i32 = 20;
+
+/// Where the things that can be navigated to ended up in the rendered buffer.
+#[derive(Debug, Default, Clone)]
+pub struct RenderedChapter {
+    /// Char offsets of the elements with an `id` (or `<a name>`), by the id.
+    pub anchors: HashMap<String, i32>,
+    /// Links in the order they appear.
+    pub links: Vec<Link>,
+    /// Chars in the rendered chapter.
+    pub length: i32,
+}
+
+impl RenderedChapter {
+    /// The link whose text contains the char `offset`.
+    pub fn link_at(&self, offset: i32) -> Option<&Link> {
+        self.links
+            .iter()
+            .find(|link| link.start <= offset && offset < link.end)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Link {
+    /// Char range of the link text from the start of the chapter.
+    pub start: i32,
+    pub end: i32,
+    /// The `href` as it is in the html.
+    pub href: String,
+    /// Link to a footnote, shown in a popover instead of being followed.
+    pub is_noteref: bool,
+}
+
+/// Char that stands for an image in the text, `TextBuffer` counts and returns images as it.
+pub const IMAGE_CHAR: char = '\u{FFFC}';
+
+/// Where the renderer writes the chapter.
+pub trait Output {
+    /// An image that has been loaded and is ready to be inserted.
+    type Image;
+
+    fn clear(&mut self);
+
+    /// Number of chars written so far.
+    fn char_count(&self) -> i32;
+
+    fn insert(&mut self, text: &str);
+
+    /// Apply the tags `names` and the indentation of the nesting `level` from `start_offset` to the end.
+    fn apply_tags(&mut self, start_offset: i32, names: &[String], level: usize);
+
+    /// Load the image at `path`, `None` if it cannot be shown. The flag tells if it gets a line of its own.
+    fn load_image(&mut self, path: &Path) -> Option<(Self::Image, bool)>;
+
+    fn insert_image(&mut self, image: Self::Image);
+}
+
+/// Writes the chapter into a `TextBuffer` with `TextTag`s for headings, emphasis, quotes, lists and so on.
+///
+/// None of the tags set colors so the theme and custom colors still apply.
+pub struct BufferOutput<'a> {
+    buffer: &'a TextBuffer,
+    /// Images wider than this are scaled down.
+    max_width: i32,
+    /// Loads a resource, such as an image, from the book by its path.
+    load_resource: &'a mut dyn FnMut(&Path) -> Option<Vec<u8>>,
+}
+
+impl<'a> Output for BufferOutput<'a> {
+    type Image = Pixbuf;
+
+    fn clear(&mut self) {
+        self.buffer.set_text("");
+        create_tags(self.buffer);
+    }
+
+    fn char_count(&self) -> i32 {
+        self.buffer.char_count()
+    }
+
+    fn insert(&mut self, text: &str) {
+        self.buffer.insert(&mut self.buffer.end_iter(), text);
+    }
+
+    fn apply_tags(&mut self, start_offset: i32, names: &[String], level: usize) {
+        let start = self.buffer.iter_at_offset(start_offset);
+        let end = self.buffer.end_iter();
+
+        for name in names {
+            self.buffer.apply_tag_by_name(name, &start, &end);
+        }
+
+        if level > 0 {
+            let tag = indent_tag(self.buffer, level);
+            self.buffer.apply_tag_by_name(&tag, &start, &end);
+        }
+    }
+
+    fn load_image(&mut self, path: &Path) -> Option<(Pixbuf, bool)> {
+        let pixbuf = (self.load_resource)(path)
+            .and_then(|bytes| Pixbuf::from_read(Cursor::new(bytes)).ok())?;
+        let pixbuf = scale_to_width(pixbuf, self.max_width);
+        // Large images get their own centered line, small ones stay inline with the text.
+        let is_block = pixbuf.width() * 2 > self.max_width;
+
+        Some((pixbuf, is_block))
+    }
+
+    fn insert_image(&mut self, image: Pixbuf) {
+        self.buffer.insert_pixbuf(&mut self.buffer.end_iter(), &image);
+    }
+}
+
+/// Collects the chapter as plain text, the same text the reader buffer has.
+/// The images are not loaded, each one is an `IMAGE_CHAR` on a line of its own.
+#[derive(Default)]
+struct PlainText {
+    text: String,
+    chars: i32,
+}
+
+impl Output for PlainText {
+    type Image = ();
+
+    fn clear(&mut self) {
+        self.text.clear();
+        self.chars = 0;
+    }
+
+    fn char_count(&self) -> i32 {
+        self.chars
+    }
+
+    fn insert(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.chars += text.chars().count() as i32;
+    }
+
+    fn apply_tags(&mut self, _start_offset: i32, _names: &[String], _level: usize) {}
+
+    fn load_image(&mut self, _path: &Path) -> Option<((), bool)> {
+        Some(((), true))
+    }
+
+    fn insert_image(&mut self, _image: ()) {
+        self.insert(&IMAGE_CHAR.to_string());
+    }
+}
+
+/// Text of the chapter the way the reader shows it, for searching, measuring and printing the
+/// chapter without a `TextBuffer`. Offsets in it are the offsets in the reader, except after
+/// small inline images or images that cannot be loaded.
+pub fn chapter_text(document: &Document) -> String {
+    let mut renderer = ChapterRenderer::with_output(PlainText::default(), PathBuf::new());
+    renderer.render_document(document);
+
+    renderer.output.text
+}
+
+/// Walks the chapter html and writes its text to the `Output`, keeping track of the block
+/// breaks, list markers and the offsets of the anchors and links.
+pub struct ChapterRenderer<O: Output> {
+    output: O,
+    /// Path of the chapter inside the book, relative hrefs are resolved against it.
+    chapter_path: PathBuf,
+    /// Names of the tags applied to text inserted at the moment.
+    tags: Vec<String>,
+    /// Open lists, innermost last. Ordered lists hold the next item number.
+    lists: Vec<Option<usize>>,
+    /// Number of open blockquotes.
+    quotes: usize,
+    /// Depth of `<pre>` elements. Whitespace is kept as is inside them.
+    preformatted: usize,
+    /// Number of newlines at the end of the buffer.
+    newlines: usize,
+    /// Collapsed whitespace waiting to be written before the next word.
+    pending_space: bool,
+    /// A list marker was just written so the next block break is skipped.
+    after_marker: bool,
+    /// Ids waiting for the next visible content to get their offset.
+    pending_anchors: Vec<String>,
+    chapter: RenderedChapter,
+}
+
+impl<'a> ChapterRenderer<BufferOutput<'a>> {
+    pub fn new(
+        buffer: &'a TextBuffer,
+        chapter_path: PathBuf,
+        max_width: i32,
+        load_resource: &'a mut dyn FnMut(&Path) -> Option<Vec<u8>>
+    ) -> ChapterRenderer<BufferOutput<'a>> {
+        ChapterRenderer::with_output(BufferOutput { buffer, max_width, load_resource }, chapter_path)
+    }
+}
+
+impl<O: Output> ChapterRenderer<O> {
+    fn with_output(output: O, chapter_path: PathBuf) -> ChapterRenderer<O> {
+        ChapterRenderer {
+            output,
+            chapter_path,
+            tags: vec![],
+            lists: vec![],
+            quotes: 0,
+            preformatted: 0,
+            newlines: 0,
+            pending_space: false,
+            after_marker: false,
+            pending_anchors: vec![],
+            chapter: RenderedChapter::default(),
+        }
+    }
+
+    /// Clear the output and render the chapter document into it.
+    pub fn render(mut self, document: &Document) -> RenderedChapter {
+        self.render_document(document);
+        self.chapter.length = self.output.char_count();
+
+        mem::take(&mut self.chapter)
+    }
+
+    fn render_document(&mut self, document: &Document) {
+        self.output.clear();
+
+        if let Some(body) = document.select(Name("body")).next() {
+            self.render_children(body);
+        } else if let Some(root) = document.nth(0) {
+            self.render_node(root);
+        }
+
+        let end = self.output.char_count();
+        self.place_anchors(end);
+    }
+
+    fn render_children(&mut self, node: Node) {
+        for child in node.children() {
+            self.render_node(child);
+        }
+    }
+
+    fn render_node(&mut self, node: Node) {
+        if let Some(text) = node.as_text() {
+            self.push_text(text);
+            return;
+        }
+
+        // Comments and such
+        let name = match node.name() {
+            Some(name) => name,
+            None => return,
+        };
+
+        // Footnote asides are shown in a popover from their noteref instead.
+        if name == "aside" && has_epub_type(node, &["footnote", "endnote", "rearnote"]) {
+            return;
+        }
+
+        if let Some(id) = node.attr("id") {
+            self.pending_anchors.push(id.to_string());
+        }
+
+        match name {
+            "head" | "title" | "script" | "style" => {},
+            "br" => {
+                self.pending_space = false;
+                self.insert("\n");
+            },
+            // `image` is the svg element, html `<image>` is parsed as `<img>`.
+            "img" | "image" => self.insert_image(node),
+            "hr" => {
+                self.block(2);
+                self.with_tag("hr", |r| r.insert(&"\u{2015}".repeat(12)));
+                self.block(2);
+            },
+            "a" => {
+                if let Some(anchor) = node.attr("name") {
+                    self.pending_anchors.push(anchor.to_string());
+                }
+
+                match node.attr("href") {
+                    Some(href) => {
+                        let start = self.output.char_count();
+                        self.with_tag("link", |r| r.render_children(node));
+                        let end = self.output.char_count();
+
+                        if end > start {
+                            self.chapter.links.push(Link {
+                                start,
+                                end,
+                                href: href.to_string(),
+                                is_noteref: has_epub_type(node, &["noteref"]),
+                            });
+                        }
+                    },
+                    None => self.render_children(node),
+                }
+            },
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.block(2);
+                self.with_tag(name, |r| r.render_children(node));
+                self.block(2);
+            },
+            "blockquote" => {
+                self.block(2);
+                self.quotes += 1;
+                self.render_children(node);
+                self.quotes -= 1;
+                self.block(2);
+            },
+            "ul" | "ol" => {
+                let breaks = if self.lists.is_empty() { 2 } else { 1 };
+                let list = if name == "ol" {
+                    Some(node.attr("start").and_then(|s| s.parse().ok()).unwrap_or(1))
+                } else {
+                    None
+                };
+
+                self.block(breaks);
+                self.lists.push(list);
+                self.render_children(node);
+                self.lists.pop();
+                self.block(breaks);
+            },
+            "li" => {
+                self.block(1);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(num)) => {
+                        let marker = format!("{}. ", num);
+                        *num += 1;
+                        marker
+                    },
+                    _ => "• ".to_string(),
+                };
+                self.insert(&marker);
+                self.after_marker = true;
+                self.render_children(node);
+                self.after_marker = false;
+                self.block(1);
+            },
+            "pre" => {
+                self.block(2);
+                self.preformatted += 1;
+                self.with_tag("monospace", |r| r.render_children(node));
+                self.preformatted -= 1;
+                self.block(2);
+            },
+            "tr" | "dt" | "dd" | "figcaption" => {
+                self.block(1);
+                self.render_children(node);
+                self.block(1);
+            },
+            "p" | "div" | "section" | "article" | "aside" | "header" | "footer" | "nav"
+            | "figure" | "table" | "dl" | "address" | "center" => {
+                self.block(2);
+                self.render_children(node);
+                self.block(2);
+            },
+            _ => {
+                match inline_tag(name) {
+                    Some(tag) => self.with_tag(tag, |r| r.render_children(node)),
+                    None => self.render_children(node),
+                }
+            }
+        }
+    }
+
+    /// Run `f` with the tag `name` applied to everything it inserts.
+    fn with_tag<F: FnOnce(&mut Self)>(&mut self, name: &str, f: F) {
+        self.tags.push(name.to_string());
+        f(self);
+        self.tags.pop();
+    }
+
+    /// Insert text with html whitespace rules, unless inside `<pre>`.
+    fn push_text(&mut self, text: &str) {
+        if self.preformatted > 0 {
+            self.insert(text);
+            return;
+        }
+
+        let mut line_start = self.at_line_start();
+        let mut content = String::with_capacity(text.len());
+        // Only ascii whitespace collapses in html, keep non-breaking spaces.
+        for c in text.chars() {
+            if c.is_ascii_whitespace() {
+                self.pending_space = true;
+                continue;
+            }
+
+            if self.pending_space && !line_start {
+                content.push(' ');
+            }
+            self.pending_space = false;
+            line_start = false;
+            content.push(c);
+        }
+
+        if !content.is_empty() {
+            self.after_marker = false;
+            self.insert(&content);
+        }
+    }
+
+    /// Make sure the buffer ends with at least `count` newlines.
+    fn block(&mut self, count: usize) {
+        self.pending_space = false;
+
+        if self.after_marker || self.output.char_count() == 0 {
+            return;
+        }
+
+        while self.newlines < count {
+            self.insert("\n");
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.newlines > 0 || self.output.char_count() == 0
+    }
+
+    /// Insert text at the end of the output and apply the current tags to it.
+    fn insert(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+
+        let start_offset = self.output.char_count();
+        self.output.insert(text);
+        self.apply_tags(start_offset);
+
+        if text.chars().any(|c| c != '\n') {
+            self.place_anchors(start_offset);
+        }
+
+        let trailing = text.chars().rev().take_while(|c| *c == '\n').count();
+        if trailing == text.chars().count() {
+            self.newlines += trailing;
+        } else {
+            self.newlines = trailing;
+        }
+    }
+
+    /// Give the waiting anchors the offset of the content they belong to.
+    /// This way an anchor points past the block breaks before its element.
+    fn place_anchors(&mut self, offset: i32) {
+        for anchor in self.pending_anchors.drain(..) {
+            self.chapter.anchors.entry(anchor).or_insert(offset);
+        }
+    }
+
+    /// Apply the current tags from `start_offset` to the end of the output.
+    fn apply_tags(&mut self, start_offset: i32) {
+        let level = self.quotes + self.lists.len();
+        self.output.apply_tags(start_offset, &self.tags, level);
+    }
+
+    /// Load the image from the book and insert it, large images on a line of their own.
+    /// Falls back to the `alt` text if the image cannot be loaded.
+    fn insert_image(&mut self, node: Node) {
+        let src = node.attr("src")
+            .or_else(|| node.attr("href"))
+            .or_else(|| node.attr("xlink:href"));
+        let path = match src {
+            Some(src) => resolve_href(&self.chapter_path, src),
+            None => return,
+        };
+
+        let (image, is_block) = match self.output.load_image(&path) {
+            Some(image) => image,
+            None => {
+                warn!("Cannot load image: {:?}", path);
+                if let Some(alt) = node.attr("alt") {
+                    self.push_text(alt);
+                }
+                return;
+            }
+        };
+
+        if is_block {
+            self.block(1);
+            self.tags.push("image".to_string());
+        } else if self.pending_space && !self.at_line_start() {
+            self.insert(" ");
+        }
+        self.pending_space = false;
+
+        let start_offset = self.output.char_count();
+        self.output.insert_image(image);
+        self.apply_tags(start_offset);
+        self.place_anchors(start_offset);
+        self.newlines = 0;
+        self.after_marker = false;
+
+        if is_block {
+            self.tags.pop();
+            self.block(1);
+        }
+    }
+}
+
+/// Scale the image down, keeping the aspect ratio, if it's wider than `max_width`.
+fn scale_to_width(pixbuf: Pixbuf, max_width: i32) -> Pixbuf {
+    let (width, height) = (pixbuf.width(), pixbuf.height());
+    if width <= max_width || max_width <= 0 {
+        return pixbuf;
+    }
+
+    let scaled_height = (height as f64 * max_width as f64 / width as f64).round().max(1.0) as i32;
+    pixbuf
+        .scale_simple(max_width, scaled_height, InterpType::Bilinear)
+        .unwrap_or(pixbuf)
+}
+
+/// Tag name for inline elements that change how the text looks.
+fn inline_tag(name: &str) -> Option<&'static str> {
+    match name {
+        "em" | "i" | "cite" | "dfn" | "var" => Some("italic"),
+        "strong" | "b" => Some("bold"),
+        "u" | "ins" => Some("underline"),
+        "s" | "strike" | "del" => Some("strikethrough"),
+        "code" | "tt" | "kbd" | "samp" => Some("monospace"),
+        "sup" => Some("superscript"),
+        "sub" => Some("subscript"),
+        "small" => Some("small"),
+        _ => None,
+    }
+}
+
+/// Name of the indentation tag for the nesting `level`, the tag is created if missing.
+fn indent_tag(buffer: &TextBuffer, level: usize) -> String {
+    let name = format!("indent-{}", level);
+    let table = buffer.tag_table().expect("Could not get tag table");
+
+    if table.lookup(&name).is_none() {
+        buffer.create_tag(Some(&name), &[
+            ("left-margin", &(BASE_MARGIN + INDENT * level as i32)),
+        ]);
+    }
+
+    name
+}
+
+/// Create the static reader tags if the buffer doesn't have them yet.
+fn create_tags(buffer: &TextBuffer) {
+    let table = buffer.tag_table().expect("Could not get tag table");
+    let tag = |name: &str, properties: &[(&str, &dyn ToValue)]| {
+        if table.lookup(name).is_none() {
+            buffer.create_tag(Some(name), properties);
+        }
+    };
+
+    // `scale` is relative to the font size so the headings follow
+    // the font size setting without re-rendering.
+    for (name, scale) in HEADINGS.iter() {
+        tag(name, &[
+            ("scale", scale),
+            ("weight", &BOLD),
+            ("pixels-above-lines", &12),
+        ]);
+    }
+
+    tag("italic", &[("style", &pango::Style::Italic)]);
+    tag("bold", &[("weight", &BOLD)]);
+    tag("underline", &[("underline", &pango::Underline::Single)]);
+    tag("strikethrough", &[("strikethrough", &true)]);
+    tag("monospace", &[("family", &"monospace")]);
+    tag("small", &[("scale", &0.85)]);
+    tag("superscript", &[("scale", &0.7), ("rise", &(6 * pango::SCALE))]);
+    tag("subscript", &[("scale", &0.7), ("rise", &(-3 * pango::SCALE))]);
+    tag("link", &[("underline", &pango::Underline::Single)]);
+    tag("hr", &[("justification", &Justification::Center)]);
+    tag("image", &[("justification", &Justification::Center)]);
+}
@@ -0,0 +1,105 @@
+use super::UI;
+use super::find_bar::find_matches;
+
+use gtk::prelude::*;
+use crate::app::AppRuntime;
+use crate::app::search::SearchHit;
+use crate::app::utils::BuilderExtManualCustom;
+
+
+impl UI {
+    pub fn connect_search_panel(&self, app_runtime: AppRuntime) {
+        let search_entry = self.builder.get::<gtk::SearchEntry>("book_search_entry");
+        let results_list = self.builder.get::<gtk::ListBox>("search_results_list");
+
+        // Searching the whole book takes a while so it starts only with Enter
+        search_entry.connect_activate(glib::clone!(@strong app_runtime => move |entry| {
+            let query = entry.text().to_string();
+            app_runtime.update_state_with(move |state| {
+                state.search_book(query);
+            });
+        }));
+
+        search_entry.connect_stop_search(glib::clone!(@strong app_runtime => move |entry| {
+            entry.set_text("");
+            app_runtime.update_state_with(|state| {
+                state.search_book(String::new());
+            });
+        }));
+
+        results_list.connect_row_activated(glib::clone!(@strong app_runtime => move |_, row| {
+            let index = row.index() as usize;
+            app_runtime.update_state_with(move |state| {
+                state.open_search_hit(index);
+            });
+        }));
+    }
+
+    /// Remove the results of the previous search.
+    pub fn clear_search_results(&mut self) {
+        let results_list = self.builder.get::<gtk::ListBox>("search_results_list");
+        for child in results_list.children() {
+            results_list.remove(&child);
+        }
+
+        self.search_hits.clear();
+        self.set_search_status("");
+    }
+
+    /// Add a row with the chapter title and the text around the match.
+    pub fn add_search_result(&mut self, hit: SearchHit) {
+        let results_list = self.builder.get::<gtk::ListBox>("search_results_list");
+
+        let title = cascade! {
+            gtk::Label::new(Some(&hit.chapter_title));
+            ..set_xalign(0.0);
+            ..set_ellipsize(gtk::pango::EllipsizeMode::End);
+        };
+        title.style_context().add_class("search-result-title");
+
+        let snippet = cascade! {
+            gtk::Label::new(Some(&hit.snippet));
+            ..set_xalign(0.0);
+            ..set_line_wrap(true);
+            ..set_line_wrap_mode(gtk::pango::WrapMode::WordChar);
+            ..set_max_width_chars(40);
+        };
+
+        let content = cascade! {
+            gtk::Box::new(gtk::Orientation::Vertical, 2);
+            ..set_margin_start(10);
+            ..set_margin_end(10);
+            ..set_margin_top(6);
+            ..set_margin_bottom(6);
+            ..pack_start(&title, false, false, 0);
+            ..pack_start(&snippet, false, false, 0);
+        };
+
+        let row = cascade! {
+            gtk::ListBoxRow::new();
+            ..add(&content);
+        };
+        row.style_context().add_class("search-result");
+
+        results_list.add(&row);
+        row.show_all();
+
+        self.search_hits.push(hit);
+    }
+
+    pub fn set_search_status(&self, status: &str) {
+        self.builder.get::<gtk::Label>("search_status_label").set_label(status);
+    }
+
+    /// Highlight the match number `occurrence` of `query` in the chapter and scroll to it.
+    pub fn show_search_hit(&mut self, query: &str, occurrence: usize) {
+        let buffer = self.reader.buffer().expect("Could not get buffer");
+        let matches = find_matches(&buffer, query, false, false);
+
+        // The chapter text may differ a little from what the search saw so
+        // fall back to the last match.
+        if let Some((start, end)) = matches.get(occurrence).or_else(|| matches.last()) {
+            self.highlight_match(*start, *end);
+        }
+    }
+}
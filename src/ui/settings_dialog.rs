@@ -52,11 +52,11 @@ impl SettingsDialog {
         });
         
         self.dialog.connect_key_release_event(glib::clone!(@strong app_runtime => move |dialog, event| {
-            match event.keycode() {
-                Some(13) => {
+            match event.keyval() {
+                gdk::keys::constants::Return | gdk::keys::constants::KP_Enter => {
                     dialog.response(ResponseType::Ok);
                 },
-                Some(27) => {
+                gdk::keys::constants::Escape => {
                     dialog.response(ResponseType::Cancel);
                 },
                 _ => {}
@@ -84,6 +84,8 @@ impl SettingsDialog {
         let auto_scroll_speed = builder.get::<gtk::SpinButton>("auto_scroll_speed");
         let show_page_num = builder.get::<gtk::CheckButton>("show_page_num");
         let show_page_file = builder.get::<gtk::CheckButton>("show_page_file");
+        let paginated = builder.get::<gtk::CheckButton>("paginated");
+        let continuous = builder.get::<gtk::CheckButton>("continuous");
 
         let fontsize = builder.get::<gtk::SpinButton>("fontsize_spin");
         let fontfamily = builder.get::<gtk::Entry>("fontfamily_entry");
@@ -95,6 +97,8 @@ impl SettingsDialog {
         auto_scroll_speed.set_value(settings.general.auto_scroll_speed);
         show_page_num.set_active(settings.general.show_page_num);
         show_page_file.set_active(settings.general.show_page_file);
+        paginated.set_active(settings.general.paginated);
+        continuous.set_active(settings.general.continuous);
         fontsize.set_value(settings.general.font_size);
         fontfamily.set_text(&settings.general.font_family);
         use_custom_color.set_active(settings.general.use_custom_color);
@@ -0,0 +1,224 @@
+use super::UI;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use gtk::prelude::*;
+use gtk::ResponseType;
+use crate::PROGRAM_NAME;
+use crate::app::AppRuntime;
+use crate::app::keymap::{self, Keymap, ACTIONS};
+use crate::app::utils::BuilderExtManualCustom;
+
+
+/// What was pressed in the shortcut capture dialog.
+enum Captured {
+    Cancel,
+    /// Remove the shortcuts of the action.
+    Clear,
+    Accel(String),
+}
+
+impl UI {
+    pub fn connect_shortcuts(&self, app_runtime: AppRuntime) {
+        self.builder
+            .get::<gtk::Button>("reset_shortcuts_button")
+            .connect_clicked(glib::clone!(@strong app_runtime => move |_| {
+                app_runtime.update_state_with(|state| state.reset_all_shortcuts());
+            }));
+    }
+
+    /// Fill the shortcuts page of the settings with the actions and their shortcuts.
+    pub fn update_shortcuts(&self, app_runtime: AppRuntime, keymap: &Keymap) {
+        let listbox = self.builder.get::<gtk::ListBox>("shortcuts_listbox");
+        for child in listbox.children() {
+            listbox.remove(&child);
+        }
+
+        for action in ACTIONS {
+            let name = action.name;
+            let accels = keymap.accels(name);
+
+            let label = cascade! {
+                gtk::Label::new(Some(action.label));
+                ..set_xalign(0.0);
+                ..set_hexpand(true);
+            };
+            let shortcut: gtk::Widget = if accels.is_empty() {
+                cascade! {
+                    gtk::Label::new(Some("Disabled"));
+                    ..style_context().add_class("dim-label");
+                }.upcast()
+            } else {
+                gtk::ShortcutLabel::new(&accels.join(" ")).upcast()
+            };
+            let change = cascade! {
+                gtk::Button::with_label("Change");
+                ..connect_clicked(glib::clone!(@strong app_runtime => move |_| {
+                    app_runtime.update_state_with(move |state| {
+                        state.ui.shortcut_capture_dialog(state.app_runtime.clone(), name);
+                    });
+                }));
+            };
+            let reset = cascade! {
+                gtk::Button::with_label("Reset");
+                ..set_sensitive(!keymap.is_default(action));
+                ..connect_clicked(glib::clone!(@strong app_runtime => move |_| {
+                    app_runtime.update_state_with(move |state| state.reset_shortcut(name));
+                }));
+            };
+
+            let row_box = cascade! {
+                gtk::Box::new(gtk::Orientation::Horizontal, 10);
+                ..set_margin(4);
+                ..pack_start(&label, true, true, 0);
+                ..pack_start(&shortcut, false, false, 0);
+                ..pack_start(&change, false, false, 0);
+                ..pack_start(&reset, false, false, 0);
+            };
+            let row = cascade! {
+                gtk::ListBoxRow::new();
+                ..set_activatable(false);
+                ..add(&row_box);
+            };
+            listbox.add(&row);
+        }
+
+        listbox.show_all();
+    }
+
+    /// Wait for the new shortcut of the `action`. Escape cancels and Backspace removes the shortcuts.
+    pub fn shortcut_capture_dialog(&self, app_runtime: AppRuntime, action: &'static str) {
+        let label = keymap::action(action).map_or(action, |action| action.label);
+        let text = cascade! {
+            gtk::Label::new(Some(&format!(
+                "Press the new shortcut for “{}”.\n\nEscape cancels, Backspace removes the shortcut.",
+                label
+            )));
+            ..set_line_wrap(true);
+            ..set_margin(10);
+        };
+
+        let dialog = cascade! {
+            gtk::Dialog::new();
+            ..set_title(&format!("Change shortcut - {}", PROGRAM_NAME));
+            ..set_modal(true);
+            ..set_transient_for(Some(&self.settings_dialog.dialog));
+            ..add_button("Cancel", ResponseType::Cancel);
+            ..content_area().pack_start(&text, true, false, 12);
+            ..set_size_request(360, 140);
+            ..connect_response(|dialog, _| dialog.close());
+        };
+
+        // The key is read when it's pressed but the dialog is closed only when it's released,
+        // otherwise the release of Enter or Escape would go to the settings dialog and close it too.
+        let captured: Rc<RefCell<Option<Captured>>> = Rc::new(RefCell::new(None));
+        dialog.connect_key_press_event(glib::clone!(@strong captured => move |_, event| {
+            if event.is_modifier() {
+                return gtk::Inhibit(true);
+            }
+
+            let modifiers = event.state() & gtk::accelerator_get_default_mod_mask();
+            let key = event.keyval().to_lower();
+            if modifiers.is_empty() && key == gdk::keys::constants::Escape {
+                *captured.borrow_mut() = Some(Captured::Cancel);
+                return gtk::Inhibit(true);
+            }
+            if modifiers.is_empty() && key == gdk::keys::constants::BackSpace {
+                *captured.borrow_mut() = Some(Captured::Clear);
+                return gtk::Inhibit(true);
+            }
+
+            // Keep waiting if the key cannot be a shortcut
+            if gtk::accelerator_valid(*key, modifiers) {
+                if let Some(accel) = gtk::accelerator_name(*key, modifiers) {
+                    *captured.borrow_mut() = Some(Captured::Accel(accel.to_string()));
+                }
+            }
+
+            gtk::Inhibit(true)
+        }));
+        dialog.connect_key_release_event(glib::clone!(@strong app_runtime => move |dialog, _| {
+            let captured = match captured.borrow_mut().take() {
+                Some(captured) => captured,
+                None => return gtk::Inhibit(true),
+            };
+            dialog.close();
+
+            match captured {
+                Captured::Cancel => {},
+                Captured::Clear => {
+                    app_runtime.update_state_with(move |state| state.clear_shortcut(action));
+                },
+                Captured::Accel(accel) => {
+                    app_runtime.update_state_with(move |state| state.bind_shortcut(action, accel));
+                },
+            }
+
+            gtk::Inhibit(true)
+        }));
+
+        dialog.show_all();
+    }
+
+    /// Ask whether to move the `accel` from the action `other` that already has it to `action`.
+    pub fn shortcut_conflict_dialog(&self, app_runtime: AppRuntime, action: &'static str, accel: String, other: &str) {
+        let label = keymap::action(action).map_or(action, |action| action.label);
+        let (key, modifiers) = gtk::accelerator_parse(&accel);
+        let shortcut = gtk::accelerator_get_label(key, modifiers).map(|label| label.to_string()).unwrap_or_else(|| accel.clone());
+
+        let dialog = cascade! {
+            gtk::MessageDialog::new(
+                Some(&self.settings_dialog.dialog),
+                gtk::DialogFlags::MODAL,
+                gtk::MessageType::Question,
+                gtk::ButtonsType::None,
+                &format!("{} is already the shortcut of “{}”.", shortcut, other)
+            );
+            ..set_title(&format!("Shortcut conflict - {}", PROGRAM_NAME));
+            ..set_secondary_text(Some(&format!("Use it for “{}” instead?", label)));
+            ..add_button("Cancel", ResponseType::Cancel);
+            ..add_button("Replace", ResponseType::Ok);
+            ..set_default_response(ResponseType::Ok);
+        };
+
+        dialog.connect_response(glib::clone!(@strong app_runtime => move |dialog, response| {
+            dialog.close();
+            if response == ResponseType::Ok {
+                let accel = accel.clone();
+                app_runtime.update_state_with(move |state| state.replace_shortcut(action, accel));
+            }
+        }));
+
+        dialog.show_all();
+    }
+
+    /// Show the shortcuts of the menu items, the menubar only knows the actions.
+    pub fn update_menu_accels(&self, keymap: &Keymap) {
+        fn update(container: &gtk::Container, keymap: &Keymap) {
+            for child in container.children() {
+                let item = match child.downcast::<gtk::MenuItem>() {
+                    Ok(item) => item,
+                    Err(_) => continue,
+                };
+
+                let action = item.action_name().map(|name| name.to_string()).unwrap_or_default();
+                if let Some(action) = action.strip_prefix("app.") {
+                    let (key, modifiers) = keymap.accels(action)
+                        .first()
+                        .map(|accel| gtk::accelerator_parse(accel))
+                        .unwrap_or((0, gdk::ModifierType::empty()));
+
+                    if let Some(label) = item.child().and_then(|label| label.downcast::<gtk::AccelLabel>().ok()) {
+                        label.set_accel(key, modifiers);
+                    }
+                }
+
+                if let Some(submenu) = item.submenu().and_then(|submenu| submenu.downcast::<gtk::Container>().ok()) {
+                    update(&submenu, keymap);
+                }
+            }
+        }
+
+        update(self.builder.get::<gtk::MenuBar>("menu_bar").upcast_ref(), keymap);
+    }
+}